@@ -0,0 +1,405 @@
+//! Compact binary on-disk index format.
+//!
+//! Layout (integers are little-endian):
+//! - header, 96 bytes: magic, format version, embedding dimension, vector count, and the
+//!   offset/length of the sections below
+//! - metadata section: JSON with settings, note states, chunks and the optional HNSW graph
+//! - lexical section: the BM25 index in its varint encoding
+//! - quantized section: int8 or binary codes of the vectors, when the index is quantized
+//!   (empty otherwise)
+//! - vector section: `count * dim` f32 values, contiguous and 64-byte aligned, so the file
+//!   can be memory-mapped and searched in place without parsing the embeddings
+//!
+//! Only the current version is read; older binary files must be rebuilt. The legacy
+//! `index.json` format is still readable; see [crate::persisted_index].
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::chunks::Chunk;
+use crate::hnsw::HnswIndex;
+use crate::lexical::Bm25Index;
+use crate::persisted_index::{IndexSettings, NoteState, PersistedIndex, PersistedIndexError};
+use crate::quantize::QuantizedVectors;
+use crate::store::{FlatVectors, VectorStore};
+
+/// File magic at offset 0 of every binary index.
+pub const BINARY_INDEX_MAGIC: &[u8; 8] = b"NOEMAIDX";
+/// Bump this when the binary container layout changes.
+pub const BINARY_INDEX_VERSION: u32 = 3;
+
+const HEADER_LEN: usize = 96;
+const SECTION_ALIGN: usize = 64;
+
+#[derive(Serialize)]
+struct MetaRef<'a> {
+    schema_version: u32,
+    created_at_unix: i64,
+    updated_at_unix: i64,
+    settings: &'a IndexSettings,
+    note_states: &'a BTreeMap<String, NoteState>,
+    chunks: &'a [Chunk],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ann: Option<&'a HnswIndex>,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    schema_version: u32,
+    created_at_unix: i64,
+    updated_at_unix: i64,
+    settings: IndexSettings,
+    note_states: BTreeMap<String, NoteState>,
+    chunks: Vec<Chunk>,
+    #[serde(default)]
+    ann: Option<HnswIndex>,
+}
+
+struct Header {
+    version: u32,
+    dim: usize,
+    count: usize,
+    meta_offset: usize,
+    meta_len: usize,
+    vectors_offset: usize,
+    vectors_len: usize,
+    /// Offset and length of the lexical section.
+    lexical: (usize, usize),
+    /// Offset and length of the quantized section; `None` when empty.
+    quantized: Option<(usize, usize)>,
+}
+
+/// True if the file at `path` starts with the binary index magic.
+pub fn is_binary_index<P: AsRef<Path>>(path: P) -> std::io::Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == BINARY_INDEX_MAGIC),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write `index` to `path` in the binary format. Writes to a sibling temp file and renames
+/// it into place, so a reader that has the old file mapped is never truncated underneath.
+pub(crate) fn write(index: &PersistedIndex, path: &Path) -> Result<(), PersistedIndexError> {
+    let store = &index.store;
+    let meta = serde_json::to_vec(&MetaRef {
+        schema_version: index.schema_version,
+        created_at_unix: index.created_at_unix,
+        updated_at_unix: index.updated_at_unix,
+        settings: &index.settings,
+        note_states: &index.note_states,
+        chunks: store.chunks(),
+        ann: store.ann(),
+    })
+    .map_err(PersistedIndexError::Serialize)?;
+
+    let lexical = index.lexical.encode();
+    let quantized = store
+        .quantized()
+        .map(QuantizedVectors::encode)
+        .unwrap_or_default();
+    let vectors = store.vectors().as_slice();
+    let meta_offset = HEADER_LEN;
+    let lexical_offset = meta_offset + meta.len();
+    let quantized_offset = lexical_offset + lexical.len();
+    let vectors_offset = align_up(quantized_offset + quantized.len(), SECTION_ALIGN);
+    let vectors_len = std::mem::size_of_val(vectors);
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(BINARY_INDEX_MAGIC);
+    header.extend_from_slice(&BINARY_INDEX_VERSION.to_le_bytes());
+    header.extend_from_slice(&(store.vectors().dim() as u32).to_le_bytes());
+    for v in [
+        store.len(),
+        meta_offset,
+        meta.len(),
+        vectors_offset,
+        vectors_len,
+        lexical_offset,
+        lexical.len(),
+        quantized_offset,
+        quantized.len(),
+        0,
+    ] {
+        header.extend_from_slice(&(v as u64).to_le_bytes());
+    }
+    debug_assert_eq!(header.len(), HEADER_LEN);
+
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        w.write_all(&header)?;
+        w.write_all(&meta)?;
+        w.write_all(&lexical)?;
+        w.write_all(&quantized)?;
+        w.write_all(&vec![
+            0u8;
+            vectors_offset - quantized_offset - quantized.len()
+        ])?;
+        if cfg!(target_endian = "little") {
+            w.write_all(bytemuck::cast_slice(vectors))?;
+        } else {
+            for x in vectors {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Read a binary index. The vector section stays memory-mapped when the platform allows it.
+pub(crate) fn read(path: &Path) -> Result<PersistedIndex, PersistedIndexError> {
+    let file = File::open(path)?;
+    // SAFETY: index files are only ever replaced by rename (see `write`), never modified in
+    // place, so the mapped bytes cannot change while we hold the map.
+    let map = Arc::new(unsafe { Mmap::map(&file)? });
+    let header = parse_header(&map)?;
+    if header.version != BINARY_INDEX_VERSION {
+        return Err(PersistedIndexError::UnsupportedVersion(header.version));
+    }
+
+    let meta_bytes = section(&map, header.meta_offset, header.meta_len)?;
+    let meta: Meta =
+        serde_json::from_slice(meta_bytes).map_err(PersistedIndexError::Deserialize)?;
+    if meta.chunks.len() != header.count {
+        return Err(invalid("chunk count does not match header"));
+    }
+    if meta
+        .ann
+        .as_ref()
+        .is_some_and(|ann| ann.len() != header.count || !ann.is_consistent())
+    {
+        return Err(invalid("malformed HNSW graph"));
+    }
+    let expected_len = header
+        .count
+        .checked_mul(header.dim)
+        .and_then(|n| n.checked_mul(4));
+    if expected_len != Some(header.vectors_len) {
+        return Err(invalid("vector section length does not match header"));
+    }
+    let raw = section(&map, header.vectors_offset, header.vectors_len)?;
+    let range = header.vectors_offset..header.vectors_offset + header.vectors_len;
+    let vectors = match FlatVectors::from_mapped(header.dim, Arc::clone(&map), range) {
+        Some(v) => v,
+        None => FlatVectors::from_vec(
+            header.dim,
+            raw.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        ),
+    };
+
+    let (lexical_offset, lexical_len) = header.lexical;
+    let lexical = Bm25Index::decode(section(&map, lexical_offset, lexical_len)?)
+        .ok_or_else(|| invalid("malformed lexical section"))?;
+    let quantized = match header.quantized {
+        Some((offset, len)) => Some(
+            QuantizedVectors::decode(section(&map, offset, len)?)
+                .filter(|q| q.len() == header.count)
+                .ok_or_else(|| invalid("malformed quantized section"))?,
+        ),
+        None => None,
+    };
+
+    let mut idx = PersistedIndex {
+        schema_version: meta.schema_version,
+        created_at_unix: meta.created_at_unix,
+        updated_at_unix: meta.updated_at_unix,
+        settings: meta.settings,
+        store: VectorStore::from_parts(meta.chunks, vectors, meta.ann, quantized),
+        note_states: meta.note_states,
+        lexical,
+    };
+    idx.ensure_derived();
+    Ok(idx)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, PersistedIndexError> {
+    if bytes.len() < HEADER_LEN || &bytes[..8] != BINARY_INDEX_MAGIC {
+        return Err(invalid("missing binary index header"));
+    }
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+    let quantized = (u64_at(80) > 0).then(|| (u64_at(72), u64_at(80)));
+    Ok(Header {
+        version: u32_at(8),
+        dim: u32_at(12) as usize,
+        count: u64_at(16),
+        meta_offset: u64_at(24),
+        meta_len: u64_at(32),
+        vectors_offset: u64_at(40),
+        vectors_len: u64_at(48),
+        lexical: (u64_at(56), u64_at(64)),
+        quantized,
+    })
+}
+
+fn section(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], PersistedIndexError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| invalid("section out of bounds"))
+}
+
+fn invalid(msg: &str) -> PersistedIndexError {
+    PersistedIndexError::InvalidFormat(msg.to_string())
+}
+
+fn align_up(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::chunks::ChunkKind;
+    use crate::hnsw::HnswParams;
+    use crate::persisted_index::INDEX_SCHEMA_VERSION;
+    use crate::quantize::Quantization;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("noema-{}-{}", std::process::id(), name))
+    }
+
+    fn sample_index() -> PersistedIndex {
+        let mut store = VectorStore::new();
+        for i in 0..3 {
+            store
+                .add(
+                    Chunk {
+                        text: format!("chunk {}", i),
+                        kind: ChunkKind::Body,
+                        note_path: PathBuf::from("a.md"),
+                        index: i,
+                        meta: Default::default(),
+                        span: None,
+                        location: None,
+                    },
+                    vec![i as f32, 1.0, 0.5],
+                )
+                .unwrap();
+        }
+        PersistedIndex {
+            schema_version: INDEX_SCHEMA_VERSION,
+            created_at_unix: 1,
+            updated_at_unix: 2,
+            settings: IndexSettings {
+                notes_root: "/notes".to_string(),
+                max_chars: 512,
+                ollama_url: "http://localhost:11434".to_string(),
+                embed_model: "nomic-embed-text".to_string(),
+                embed_backend: Default::default(),
+                ann: None,
+                quantization: Quantization::None,
+                embed_dim: None,
+                embed_digest: None,
+            },
+            lexical: Bm25Index::from_texts(store.chunks().iter().map(|c| c.text.as_str())),
+            store,
+            note_states: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip_preserves_search() {
+        let path = temp_path("roundtrip.bin");
+        let idx = sample_index();
+        write(&idx, &path).unwrap();
+        assert!(is_binary_index(&path).unwrap());
+
+        let loaded = read(&path).unwrap();
+        assert_eq!(loaded.store.len(), 3);
+        assert_eq!(loaded.settings.embed_model, "nomic-embed-text");
+        let before = idx.store.search(&[2.0, 1.0, 0.5], 1);
+        let after = loaded.store.search(&[2.0, 1.0, 0.5], 1);
+        assert_eq!(before[0].0.text, after[0].0.text);
+        assert!((before[0].1 - after[0].1).abs() < 1e-6);
+        assert_eq!(loaded.lexical.len(), 3);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn quantized_codes_roundtrip() {
+        let path = temp_path("quantized.bin");
+        let mut idx = sample_index();
+        idx.settings.quantization = Quantization::Int8;
+        idx.store.set_quantization(Quantization::Int8);
+        write(&idx, &path).unwrap();
+
+        let loaded = read(&path).unwrap();
+        assert_eq!(loaded.store.quantization(), Quantization::Int8);
+        let hits = loaded.store.search(&[2.0, 1.0, 0.5], 1);
+        assert_eq!(hits[0].0.text, "chunk 2");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn rejects_corrupt_header() {
+        let path = temp_path("corrupt.bin");
+        write(&sample_index(), &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(PersistedIndexError::InvalidFormat(_))
+        ));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn rejects_out_of_range_graph_links_and_old_versions() {
+        let path = temp_path("graph.bin");
+        let mut idx = sample_index();
+        idx.store.enable_ann(HnswParams::default());
+        write(&idx, &path).unwrap();
+        assert!(read(&path).unwrap().store.ann().is_some());
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let links = b"\"links\":[[[";
+        let at = bytes.windows(links.len()).position(|w| w == links).unwrap() + links.len();
+        assert!(bytes[at].is_ascii_digit());
+        bytes[at] = b'7';
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(PersistedIndexError::InvalidFormat(_))
+        ));
+
+        write(&idx, &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&2u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(PersistedIndexError::UnsupportedVersion(2))
+        ));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn mapped_store_can_be_modified() {
+        let path = temp_path("modify.bin");
+        write(&sample_index(), &path).unwrap();
+        let mut loaded = read(&path).unwrap();
+        assert_eq!(loaded.store.remove_note(Path::new("a.md")), 3);
+        assert!(loaded.store.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,384 @@
+//! Multi-turn conversations grounded in notes.
+//!
+//! A [ChatSession] holds the message history of one conversation and is stored as JSON in
+//! `<app_data_dir>/sessions/<id>.json` (see [SessionStore]). Each turn first rewrites the
+//! latest question into a standalone query with [condense_question], so that follow-ups
+//! like "and what about last year?" retrieve the right notes, then answers through the
+//! chat endpoint of the model backend with the history and the retrieved excerpts ([chat_messages]).
+//! The new question and its excerpts form the user message, rendered from the `chat_turn`
+//! template.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::app_data::app_data_dir;
+use crate::provider::{ChatModel, GenerateOptions};
+use crate::templates::{PromptTemplates, TemplateError, TemplateSource};
+
+/// Prior messages sent to the model with each turn; older ones are left out.
+pub const MAX_HISTORY_MESSAGES: usize = 12;
+const SESSIONS_DIRNAME: &str = "sessions";
+/// Sessions are titled after the start of their first question.
+const TITLE_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// One message of a conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    /// User messages: the standalone query used for retrieval, when it differs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standalone: Option<String>,
+    /// Assistant messages: paths of the notes given as context.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub created_unix_secs: i64,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            standalone: None,
+            sources: Vec::new(),
+            created_unix_secs: unix_now_secs(),
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::Assistant, content)
+    }
+}
+
+/// A conversation and its full history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub title: String,
+    pub created_unix_secs: i64,
+    pub updated_unix_secs: i64,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Listing entry for a session, without its messages.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatSessionSummary {
+    pub id: String,
+    pub title: String,
+    pub created_unix_secs: i64,
+    pub updated_unix_secs: i64,
+    pub message_count: usize,
+}
+
+impl Default for ChatSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChatSession {
+    /// An empty session with a fresh id.
+    pub fn new() -> Self {
+        let now = unix_now_secs();
+        Self {
+            id: new_session_id(),
+            title: String::new(),
+            created_unix_secs: now,
+            updated_unix_secs: now,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Append a message. The first user message also becomes the title.
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.title.is_empty() && message.role == ChatRole::User {
+            self.title = session_title(&message.content);
+        }
+        self.updated_unix_secs = message.created_unix_secs.max(self.updated_unix_secs);
+        self.messages.push(message);
+    }
+
+    /// The recent messages sent to the model as history.
+    pub fn history(&self) -> &[ChatMessage] {
+        let start = self.messages.len().saturating_sub(MAX_HISTORY_MESSAGES);
+        &self.messages[start..]
+    }
+
+    pub fn summary(&self) -> ChatSessionSummary {
+        ChatSessionSummary {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            created_unix_secs: self.created_unix_secs,
+            updated_unix_secs: self.updated_unix_secs,
+            message_count: self.messages.len(),
+        }
+    }
+}
+
+/// Sessions stored as one JSON file each in a directory.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Store sessions in `dir`, creating it if needed.
+    pub fn at<P: AsRef<Path>>(dir: P) -> Result<Self, ChatError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The store in `<app_data_dir>/sessions`.
+    pub fn open_default() -> Option<Self> {
+        Self::at(app_data_dir()?.join(SESSIONS_DIRNAME)).ok()
+    }
+
+    /// All sessions, most recently updated first. Unreadable files are skipped.
+    pub fn list(&self) -> Result<Vec<ChatSessionSummary>, ChatError> {
+        let mut sessions = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Ok(raw) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Ok(session) = serde_json::from_str::<ChatSession>(&raw) {
+                sessions.push(session.summary());
+            }
+        }
+        sessions.sort_by(|a, b| {
+            b.updated_unix_secs
+                .cmp(&a.updated_unix_secs)
+                .then_with(|| b.id.cmp(&a.id))
+        });
+        Ok(sessions)
+    }
+
+    pub fn load(&self, id: &str) -> Result<ChatSession, ChatError> {
+        let path = self.path(id)?;
+        let raw = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ChatError::UnknownSession(id.to_string()),
+            _ => ChatError::Io(e),
+        })?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    /// Write a session, replacing the file atomically.
+    pub fn save(&self, session: &ChatSession) -> Result<(), ChatError> {
+        let path = self.path(&session.id)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(session)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), ChatError> {
+        std::fs::remove_file(self.path(id)?).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ChatError::UnknownSession(id.to_string()),
+            _ => ChatError::Io(e),
+        })
+    }
+
+    /// Session ids become file names, so only plain ids are accepted.
+    fn path(&self, id: &str) -> Result<PathBuf, ChatError> {
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(ChatError::InvalidSessionId(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+/// Rewrite a follow-up `question` into a standalone search query using `history`. Returns
+/// the question unchanged when there is no history or the rewrite fails. The prompt is the
+/// `condense` template of `templates`.
+pub async fn condense_question(
+    client: &impl ChatModel,
+    model: &str,
+    templates: &PromptTemplates,
+    history: &[ChatMessage],
+    question: &str,
+) -> String {
+    if !history.iter().any(|m| m.role == ChatRole::User) {
+        return question.to_string();
+    }
+    let options = GenerateOptions {
+        temperature: Some(0.0),
+        max_tokens: Some(128),
+        ..Default::default()
+    };
+    let Ok(prompt) = templates.render_condense(history, question) else {
+        return question.to_string();
+    };
+    match client.generate(model, &prompt, &options).await {
+        Ok(reply) => {
+            let line = reply.lines().map(str::trim).find(|l| !l.is_empty());
+            let rewritten = line.unwrap_or_default().trim_matches('"').trim();
+            if rewritten.is_empty() {
+                question.to_string()
+            } else {
+                rewritten.to_string()
+            }
+        }
+        Err(_) => question.to_string(),
+    }
+}
+
+/// Messages for the chat endpoint: the `system` instructions (see
+/// [PromptTemplates::render_chat_system]), the conversation `history`, then the new
+/// `question` together with the numbered excerpts in `context` and their `sources`,
+/// rendered with the `chat_turn` template.
+pub fn chat_messages(
+    templates: &PromptTemplates,
+    system: &str,
+    history: &[ChatMessage],
+    context: &str,
+    question: &str,
+    sources: &[TemplateSource],
+) -> Result<Vec<ChatMessage>, TemplateError> {
+    let mut messages = vec![ChatMessage::system(system)];
+    messages.extend(
+        history
+            .iter()
+            .filter(|m| m.role != ChatRole::System)
+            .map(|m| ChatMessage::new(m.role, m.content.clone())),
+    );
+    messages.push(ChatMessage::user(
+        templates.render_chat_turn(context, question, sources)?,
+    ));
+    Ok(messages)
+}
+
+fn session_title(question: &str) -> String {
+    let question = question.split_whitespace().collect::<Vec<_>>().join(" ");
+    if question.chars().count() <= TITLE_CHARS {
+        return question;
+    }
+    let cut: String = question.chars().take(TITLE_CHARS).collect();
+    format!("{}\u{2026}", cut.trim_end())
+}
+
+/// Time-ordered, practically unique id: creation time in nanoseconds plus a counter.
+fn new_session_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff;
+    format!("{:x}-{:04x}", nanos, n)
+}
+
+fn unix_now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[derive(Debug, Error)]
+pub enum ChatError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid session file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("no chat session with id {0:?}")]
+    UnknownSession(String),
+    #[error("invalid chat session id: {0:?}")]
+    InvalidSessionId(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_roundtrip_through_store() {
+        let dir = std::env::temp_dir().join(format!("noema-{}-sessions", std::process::id()));
+        let store = SessionStore::at(&dir).unwrap();
+        let mut session = ChatSession::new();
+        session.push(ChatMessage::user("What did I spend on rent in 2024?"));
+        session.push(ChatMessage::assistant("About 14k [1]."));
+        store.save(&session).unwrap();
+
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].title, "What did I spend on rent in 2024?");
+        assert_eq!(listed[0].message_count, 2);
+        assert_eq!(store.load(&session.id).unwrap(), session);
+
+        store.delete(&session.id).unwrap();
+        assert!(matches!(
+            store.load(&session.id),
+            Err(ChatError::UnknownSession(_))
+        ));
+        assert!(matches!(
+            store.load("../config"),
+            Err(ChatError::InvalidSessionId(_))
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn prompts_carry_history_and_context() {
+        let history = vec![
+            ChatMessage::user("What did I spend on rent in 2024?"),
+            ChatMessage::assistant("About 14k."),
+        ];
+        let prompt = PromptTemplates::builtin()
+            .render_condense(&history, "and the year before?")
+            .unwrap();
+        assert!(prompt.contains("User: What did I spend on rent in 2024?"));
+        assert!(
+            prompt.ends_with("Follow-up question: and the year before?\n\nStandalone question:")
+        );
+
+        let templates = PromptTemplates::builtin();
+        let system = templates.render_chat_system(None).unwrap();
+        let messages = chat_messages(
+            &templates,
+            &system,
+            &history,
+            "[1][body]\nRent 2023: 13k\n",
+            "and 2023?",
+            &[],
+        )
+        .unwrap();
+        let roles: Vec<ChatRole> = messages.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            vec![
+                ChatRole::System,
+                ChatRole::User,
+                ChatRole::Assistant,
+                ChatRole::User
+            ]
+        );
+        assert!(messages[3].content.contains("Rent 2023: 13k"));
+    }
+}
@@ -5,7 +5,7 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-use crate::notes::Note;
+use crate::notes::{location_at, Note, SourceLocation};
 
 /// Default maximum characters per chunk. Keeps chunks small enough for embedding models.
 pub const DEFAULT_MAX_CHARS: usize = 512;
@@ -23,6 +23,82 @@ impl Default for ChunkKind {
     }
 }
 
+/// Note-level metadata copied onto each chunk so search can filter without rereading notes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkMeta {
+    /// Frontmatter tags, lowercased and without a leading `#`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Frontmatter `type`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_type: Option<String>,
+    /// Note date as `YYYY-MM-DD`, from frontmatter `date` or a dated file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+impl ChunkMeta {
+    pub fn from_note(note: &Note) -> Self {
+        let fm = note.frontmatter.as_ref();
+        let tags = fm
+            .map(|fm| {
+                fm.tags
+                    .iter()
+                    .map(|t| t.trim().trim_start_matches('#').to_lowercase())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let note_type = fm
+            .and_then(|fm| fm.kind.as_ref())
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty());
+        let date = fm
+            .and_then(|fm| fm.date.as_deref())
+            .and_then(iso_date_prefix)
+            .or_else(|| {
+                note.path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(iso_date_prefix)
+            });
+        Self {
+            tags,
+            note_type,
+            date,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.note_type.is_none() && self.date.is_none()
+    }
+}
+
+/// Returns the leading `YYYY-MM-DD` of `s`, if it starts with one.
+fn iso_date_prefix(s: &str) -> Option<String> {
+    let d = s.trim().get(..10)?;
+    let b = d.as_bytes();
+    let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+    if digits(0..4) && b[4] == b'-' && digits(5..7) && b[7] == b'-' && digits(8..10) {
+        Some(d.to_string())
+    } else {
+        None
+    }
+}
+
+/// Where a chunk's text sits in the original note file (frontmatter included).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    /// Byte offset of the first character.
+    pub start_byte: usize,
+    /// Byte offset just past the last character.
+    pub end_byte: usize,
+    /// 1-based line of `start_byte`.
+    pub start_line: usize,
+    /// 1-based line of the last character.
+    pub end_line: usize,
+}
+
 /// A chunk of text from a note, with source reference.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
@@ -32,6 +108,15 @@ pub struct Chunk {
     pub note_path: PathBuf,
     /// Index of this chunk within the note (0, 1, 2, …).
     pub index: usize,
+    /// Metadata of the note this chunk came from.
+    #[serde(default, skip_serializing_if = "ChunkMeta::is_empty")]
+    pub meta: ChunkMeta,
+    /// Location in the note file. `None` for chunks indexed before spans were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SourceSpan>,
+    /// Page or section of a document (PDF, HTML) where the chunk starts. `None` for notes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
 }
 
 /// Chunk a single note's body into smaller pieces.
@@ -40,6 +125,7 @@ pub fn chunk_note(note: &Note, max_chars: usize) -> Vec<Chunk> {
     if title.is_none() && body.is_empty() {
         return Vec::new();
     }
+    let meta = ChunkMeta::from_note(note);
     let mut chunks = Vec::new();
     let mut next_index = 0usize;
 
@@ -51,6 +137,9 @@ pub fn chunk_note(note: &Note, max_chars: usize) -> Vec<Chunk> {
                 kind: ChunkKind::Title,
                 note_path: note.path.clone(),
                 index: next_index,
+                meta: meta.clone(),
+                span: None,
+                location: None,
             });
             next_index += 1;
         }
@@ -70,12 +159,61 @@ pub fn chunk_note(note: &Note, max_chars: usize) -> Vec<Chunk> {
                 kind: ChunkKind::Body,
                 note_path: note.path.clone(),
                 index: next_index + i,
+                meta: meta.clone(),
+                span: None,
+                location: None,
             });
         }
     }
+    locate_spans(&note.raw, &mut chunks);
+    if !note.locations.is_empty() {
+        for chunk in &mut chunks {
+            chunk.location = chunk
+                .span
+                .and_then(|span| location_at(&note.locations, span.start_byte))
+                .cloned();
+        }
+    }
     chunks
 }
 
+/// Fill in each chunk's [SourceSpan] by finding its text in `raw`. Chunking only trims,
+/// rejoins and overlaps text, so matching ignores whitespace. Chunks are located in order,
+/// each starting after the previous chunk's start (overlapping chunks share text).
+fn locate_spans(raw: &str, chunks: &mut [Chunk]) {
+    // `raw` without whitespace, and the raw byte offset of each of its bytes.
+    let mut compact = String::with_capacity(raw.len());
+    let mut offsets: Vec<usize> = Vec::with_capacity(raw.len());
+    for (at, ch) in raw.char_indices().filter(|(_, c)| !c.is_whitespace()) {
+        compact.push(ch);
+        offsets.extend((0..ch.len_utf8()).map(|i| at + i));
+    }
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(raw.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |byte: usize| line_starts.partition_point(|&s| s <= byte);
+
+    let mut cursor = 0usize;
+    for chunk in chunks {
+        let needle: String = chunk.text.chars().filter(|c| !c.is_whitespace()).collect();
+        if needle.is_empty() {
+            continue;
+        }
+        let Some(found) = compact[cursor..].find(&needle).map(|i| cursor + i) else {
+            continue;
+        };
+        let start_byte = offsets[found];
+        let end_byte = offsets[found + needle.len() - 1] + 1;
+        chunk.span = Some(SourceSpan {
+            start_byte,
+            end_byte,
+            start_line: line_of(start_byte),
+            end_line: line_of(end_byte - 1),
+        });
+        cursor = found + compact[found..].chars().next().map_or(1, char::len_utf8);
+    }
+}
+
 /// Chunk all notes. Returns chunks from all notes in order.
 pub fn chunk_notes(notes: &[Note], max_chars: usize) -> Vec<Chunk> {
     notes
@@ -277,6 +415,8 @@ mod tests {
             raw: body.to_string(),
             frontmatter: None,
             body: body.to_string(),
+            links: Vec::new(),
+            locations: Vec::new(),
         }
     }
 
@@ -323,4 +463,44 @@ mod tests {
         assert_eq!(c[0].text, "Heading One");
         assert_eq!(c[0].kind, ChunkKind::Title);
     }
+
+    #[test]
+    fn chunk_meta_from_frontmatter_and_file_name() {
+        let mut n = note("Body.");
+        n.path = PathBuf::from("journal/2024-03-05 standup.md");
+        n.frontmatter = Some(crate::notes::NoteFrontmatter {
+            tags: vec!["#Money".to_string()],
+            kind: Some("decision".to_string()),
+            ..Default::default()
+        });
+        let meta = ChunkMeta::from_note(&n);
+        assert_eq!(meta.tags, vec!["money"]);
+        assert_eq!(meta.note_type.as_deref(), Some("decision"));
+        assert_eq!(meta.date.as_deref(), Some("2024-03-05"));
+        assert_eq!(chunk_note(&n, 512)[0].meta, meta);
+    }
+
+    #[test]
+    fn spans_point_into_raw_file() {
+        let raw = "---\ntitle: Plan\n---\n\nFirst paragraph here.\n\n## Next\nSecond  part.\n";
+        let mut n = note("");
+        n.raw = raw.to_string();
+        n.frontmatter = Some(crate::notes::NoteFrontmatter {
+            title: Some("Plan".to_string()),
+            ..Default::default()
+        });
+        n.body = "First paragraph here.\n\n## Next\nSecond  part.\n".to_string();
+        let c = chunk_note(&n, 24);
+        let span = |i: usize| c[i].span.unwrap();
+        assert_eq!(&raw[span(0).start_byte..span(0).end_byte], "Plan");
+        assert_eq!(span(0).start_line, 2);
+        assert_eq!(
+            &raw[span(1).start_byte..span(1).end_byte],
+            "First paragraph here."
+        );
+        assert_eq!((span(1).start_line, span(1).end_line), (5, 5));
+        let last = c.last().unwrap().span.unwrap();
+        assert!(raw[last.start_byte..last.end_byte].ends_with("Second  part."));
+        assert_eq!(last.end_line, 8);
+    }
 }
@@ -1,47 +1,270 @@
 //! Persisted config (notes root, etc.) in the app data directory.
 
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
 use crate::app_data;
+use crate::documents::ExtractionCache;
+use crate::embed_cache::DEFAULT_EMBED_CACHE_BYTES;
+use crate::embed_pipeline::EmbedParams;
+use crate::expand::QueryExpansion;
+use crate::grounding::GroundingMethod;
+use crate::hnsw::HnswParams;
+use crate::notes::FormatRegistry;
+use crate::provider::ModelBackend;
+use crate::quantize::Quantization;
+use crate::rerank::{RerankMode, RerankParams};
+use crate::staging::DEFAULT_CHECKPOINT_CHUNKS;
+use crate::vault::VaultConfig;
 
 const CONFIG_FILENAME: &str = "config.toml";
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
-    /// Path to the user's notes directory (chosen by them).
+    /// Path to the user's notes directory (chosen by them). Used when no vaults are set.
     pub notes_root: Option<String>,
+    /// Name of the vault commands operate on (default: the first vault).
+    pub active_vault: Option<String>,
     /// Optional model and query defaults.
     #[serde(default)]
     pub models: ModelConfig,
+    /// Optional search tuning.
+    #[serde(default)]
+    pub search: SearchConfig,
+    /// Optional second-stage reranking.
+    #[serde(default)]
+    pub rerank: RerankConfig,
+    /// Optional embedding cache settings.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Optional embedding batch and retry settings for index builds.
+    #[serde(default)]
+    pub indexing: IndexingConfig,
+    /// Optional answer checking.
+    #[serde(default)]
+    pub answers: AnswerConfig,
+    /// Named vaults, each with its own notes folder and index. See [crate::vault].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vaults: Vec<VaultConfig>,
 }
 
 /// Optional defaults for embed and chat models, URLs, and top-k.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct ModelConfig {
-    /// Default Ollama URL for embeddings (overrides compiled default if set).
+    /// API of the embedding server: `"ollama"` (default) or `"openai"`.
+    pub embed_backend: Option<ModelBackend>,
+    /// Default URL for embeddings (overrides compiled default if set).
     pub embed_url: Option<String>,
     /// Default embedding model name for indexing/query.
     pub embed_model: Option<String>,
-    /// Default Ollama URL for chat/completion (ask).
+    /// API of the chat server: `"ollama"` (default) or `"openai"`. `"local"` cannot chat
+    /// and is rejected when the chat client is built.
+    pub chat_backend: Option<ModelBackend>,
+    /// Default URL for chat/completion (ask).
     pub chat_url: Option<String>,
     /// Default chat/completion model name for ask.
     pub chat_model: Option<String>,
     /// Default top-k for query/ask when not overridden.
     pub default_k: Option<usize>,
+    /// Context window of the chat model in tokens. Unset: asked from Ollama, capped at its
+    /// default window.
+    pub context_length: Option<usize>,
+    /// Bearer token for OpenAI-compatible servers that require one.
+    pub api_key: Option<String>,
 }
 
-/// Load config from the app data directory. Returns default config if missing or invalid.
-pub fn load_config() -> Config {
-    let Some(data_dir) = app_data::app_data_dir() else {
-        return Config::default();
-    };
-    let path = data_dir.join(CONFIG_FILENAME);
-    let Ok(s) = std::fs::read_to_string(&path) else {
-        return Config::default();
-    };
-    toml::from_str(&s).unwrap_or_default()
+impl ModelConfig {
+    /// These settings, with unset fields taken from `base`.
+    pub fn merged_over(&self, base: &ModelConfig) -> ModelConfig {
+        ModelConfig {
+            embed_backend: self.embed_backend.or(base.embed_backend),
+            embed_url: self.embed_url.clone().or_else(|| base.embed_url.clone()),
+            embed_model: self
+                .embed_model
+                .clone()
+                .or_else(|| base.embed_model.clone()),
+            chat_backend: self.chat_backend.or(base.chat_backend),
+            chat_url: self.chat_url.clone().or_else(|| base.chat_url.clone()),
+            chat_model: self.chat_model.clone().or_else(|| base.chat_model.clone()),
+            default_k: self.default_k.or(base.default_k),
+            context_length: self.context_length.or(base.context_length),
+            api_key: self.api_key.clone().or_else(|| base.api_key.clone()),
+        }
+    }
+}
+
+/// Optional search settings. Approximate (HNSW) search is off unless `hnsw = true`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchConfig {
+    /// Build an HNSW graph with the index for approximate search on large vaults.
+    pub hnsw: Option<bool>,
+    /// Max links per graph node (higher: better recall, more memory).
+    pub hnsw_m: Option<usize>,
+    /// Candidate list size while building the graph.
+    pub hnsw_ef_construction: Option<usize>,
+    /// Candidate list size while searching (higher: better recall, slower queries).
+    pub hnsw_ef_search: Option<usize>,
+    /// Compress embeddings in memory: `"int8"` or `"binary"` (default: full precision).
+    pub quantization: Option<Quantization>,
+    /// Default MMR lambda for query/ask (0–1). Unset: no diversification.
+    pub mmr_lambda: Option<f32>,
+    /// Also search with a generated hypothetical answer (HyDE) by default.
+    pub hyde: Option<bool>,
+    /// Number of generated paraphrases to also search with by default.
+    pub paraphrases: Option<usize>,
+}
+
+impl SearchConfig {
+    /// Default query expansion for query/ask calls that do not choose one.
+    pub fn query_expansion(&self) -> QueryExpansion {
+        QueryExpansion {
+            hyde: self.hyde.unwrap_or(false),
+            paraphrases: self.paraphrases.unwrap_or(0),
+        }
+    }
+
+    /// HNSW parameters to build an index with, or `None` for exact search only.
+    pub fn hnsw_params(&self) -> Option<HnswParams> {
+        if self.hnsw != Some(true) {
+            return None;
+        }
+        let defaults = HnswParams::default();
+        Some(HnswParams {
+            m: self.hnsw_m.unwrap_or(defaults.m),
+            ef_construction: self
+                .hnsw_ef_construction
+                .unwrap_or(defaults.ef_construction),
+            ef_search: self.hnsw_ef_search.unwrap_or(defaults.ef_search),
+        })
+    }
+}
+
+/// Optional reranking of search results with a local Ollama model. Off unless `model` is set.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RerankConfig {
+    /// Reranker or chat model name.
+    pub model: Option<String>,
+    /// Ollama URL for the reranker (default: the chat URL).
+    pub url: Option<String>,
+    /// `"model"` for a yes/no reranker model, `"prompt"` for an LLM relevance grade.
+    pub mode: Option<RerankMode>,
+    /// Candidates to rescore.
+    pub top_n: Option<usize>,
+    /// Time budget per query in seconds; on timeout the search order is kept.
+    pub timeout_secs: Option<u64>,
+    /// Minimum reranker score (0–1) for an ask source when reranking succeeded.
+    pub min_relevance: Option<f32>,
+}
+
+impl RerankConfig {
+    /// Reranking parameters with defaults filled in.
+    pub fn params(&self) -> RerankParams {
+        let defaults = RerankParams::default();
+        RerankParams {
+            top_n: self.top_n.unwrap_or(defaults.top_n),
+            timeout: self
+                .timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+        }
+    }
+}
+
+/// Embedding cache settings. The cache is on by default.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Size limit of the embedding cache in MiB; `0` disables it.
+    pub embed_cache_mb: Option<usize>,
+}
+
+impl CacheConfig {
+    /// Size limit in bytes, or `None` when the cache is disabled.
+    pub fn embed_cache_bytes(&self) -> Option<usize> {
+        match self.embed_cache_mb {
+            Some(0) => None,
+            Some(mb) => Some(mb.saturating_mul(1024 * 1024)),
+            None => Some(DEFAULT_EMBED_CACHE_BYTES),
+        }
+    }
+}
+
+/// How ask answers are checked against their sources.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AnswerConfig {
+    /// Scoring of citations: `"embedding"` (default), `"llm"` or `"off"`.
+    pub grounding: Option<GroundingMethod>,
+}
+
+/// How index builds send chunks to the embedding server.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexingConfig {
+    /// Chunks per embedding request.
+    pub batch_size: Option<usize>,
+    /// Embedding requests in flight at once.
+    pub concurrency: Option<usize>,
+    /// Retries of a batch after a transient error.
+    pub max_retries: Option<u32>,
+    /// Delay before the first retry in milliseconds; doubled for each further retry.
+    pub retry_backoff_ms: Option<u64>,
+    /// Chunks embedded between two checkpoints of a full rebuild.
+    pub checkpoint_chunks: Option<usize>,
+    /// File extensions read as notes, e.g. `["md", "org"]`. Unset: every built-in format.
+    pub extensions: Option<Vec<String>>,
+}
+
+impl IndexingConfig {
+    /// Embedding parameters with defaults filled in.
+    pub fn embed_params(&self) -> EmbedParams {
+        let defaults = EmbedParams::default();
+        EmbedParams {
+            batch_size: self.batch_size.unwrap_or(defaults.batch_size).max(1),
+            concurrency: self.concurrency.unwrap_or(defaults.concurrency).max(1),
+            max_retries: self.max_retries.unwrap_or(defaults.max_retries),
+            retry_backoff: self
+                .retry_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.retry_backoff),
+        }
+    }
+
+    /// Checkpoint interval of a full rebuild, with the default filled in.
+    pub fn checkpoint_chunks(&self) -> usize {
+        self.checkpoint_chunks
+            .unwrap_or(DEFAULT_CHECKPOINT_CHUNKS)
+            .max(1)
+    }
+
+    /// Note formats to scan, limited to the configured extensions, with document text
+    /// cached in the app data directory.
+    pub fn formats(&self) -> FormatRegistry {
+        let mut formats = FormatRegistry::default();
+        if let Some(exts) = &self.extensions {
+            formats = formats.with_extensions(exts);
+        }
+        match ExtractionCache::open_default() {
+            Some(cache) => formats.with_extraction_cache(cache),
+            None => formats,
+        }
+    }
+}
+
+/// Load config from the app data directory. Returns default config if missing; an
+/// unreadable or invalid file is an error, so it is never overwritten with defaults.
+pub fn load_config() -> Result<Config, ConfigError> {
+    match app_data::app_data_dir() {
+        Some(data_dir) => load_config_from(&data_dir.join(CONFIG_FILENAME)),
+        None => Ok(Config::default()),
+    }
+}
+
+fn load_config_from(path: &Path) -> Result<Config, ConfigError> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => toml::from_str(&s).map_err(ConfigError::Parse),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(ConfigError::Read(e)),
+    }
 }
 
 /// Save config to the app data directory.
@@ -52,28 +275,34 @@ pub fn save_config(config: &Config) -> Result<(), ConfigError> {
     std::fs::write(&path, s).map_err(ConfigError::Write)
 }
 
-/// Get the configured notes root path, if any.
-pub fn get_notes_root() -> Option<PathBuf> {
-    load_config()
-        .notes_root
-        .filter(|s| !s.is_empty())
-        .map(PathBuf::from)
+/// Get the notes root of the active vault (or the configured notes root), if any.
+pub fn get_notes_root() -> Result<Option<PathBuf>, ConfigError> {
+    Ok(load_config()?.active_vault().map(|v| v.root))
 }
 
-/// Set and persist the notes root.
+/// Set and persist the notes root of the active vault (or the single notes root).
 pub fn set_notes_root(path: &Path) -> Result<(), ConfigError> {
     let path = path.canonicalize().map_err(ConfigError::Canonicalize)?;
     if !path.is_dir() {
         return Err(ConfigError::NotADirectory(path));
     }
-    let mut config = load_config();
-    config.notes_root = Some(path.to_string_lossy().into_owned());
+    let mut config = load_config()?;
+    let root = path.to_string_lossy().into_owned();
+    let active = config.active_vault().map(|v| v.name);
+    match config
+        .vaults
+        .iter_mut()
+        .find(|v| Some(&v.name) == active.as_ref())
+    {
+        Some(vault) => vault.root = root,
+        None => config.notes_root = Some(root),
+    }
     save_config(&config)
 }
 
 /// Set a model config key and persist. Keys: embed_url, embed_model, chat_url, chat_model, default_k.
 pub fn set_model_config(key: &str, value: &str) -> Result<(), ConfigError> {
-    let mut config = load_config();
+    let mut config = load_config()?;
     match key {
         "embed_url" => config.models.embed_url = Some(value.to_string()),
         "embed_model" => config.models.embed_model = Some(value.to_string()),
@@ -83,20 +312,51 @@ pub fn set_model_config(key: &str, value: &str) -> Result<(), ConfigError> {
             let k: usize = value.parse().map_err(|_| ConfigError::InvalidDefaultK)?;
             config.models.default_k = Some(k);
         }
+        "context_length" => {
+            let n: usize = value
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or(ConfigError::InvalidContextLength)?;
+            config.models.context_length = Some(n);
+        }
+        "embed_backend" => config.models.embed_backend = Some(parse_backend(value)?),
+        "chat_backend" => config.models.chat_backend = Some(parse_chat_backend(value)?),
+        "api_key" => config.models.api_key = Some(value.to_string()),
         _ => return Err(ConfigError::UnknownConfigKey(key.to_string())),
     }
     save_config(&config)
 }
 
+fn parse_backend(value: &str) -> Result<ModelBackend, ConfigError> {
+    match value {
+        "ollama" => Ok(ModelBackend::Ollama),
+        "openai" => Ok(ModelBackend::OpenAi),
+        "local" => Ok(ModelBackend::Local),
+        _ => Err(ConfigError::InvalidBackend(value.to_string())),
+    }
+}
+
+fn parse_chat_backend(value: &str) -> Result<ModelBackend, ConfigError> {
+    match parse_backend(value)? {
+        ModelBackend::Local => Err(ConfigError::LocalChatBackend),
+        backend => Ok(backend),
+    }
+}
+
 /// Clear a model config key (set back to compiled default).
 pub fn unset_model_config(key: &str) -> Result<(), ConfigError> {
-    let mut config = load_config();
+    let mut config = load_config()?;
     match key {
         "embed_url" => config.models.embed_url = None,
         "embed_model" => config.models.embed_model = None,
         "chat_url" => config.models.chat_url = None,
         "chat_model" => config.models.chat_model = None,
         "default_k" => config.models.default_k = None,
+        "context_length" => config.models.context_length = None,
+        "embed_backend" => config.models.embed_backend = None,
+        "chat_backend" => config.models.chat_backend = None,
+        "api_key" => config.models.api_key = None,
         _ => return Err(ConfigError::UnknownConfigKey(key.to_string())),
     }
     save_config(&config)
@@ -106,6 +366,10 @@ pub fn unset_model_config(key: &str) -> Result<(), ConfigError> {
 pub enum ConfigError {
     #[error("could not determine app data directory")]
     NoDataDir,
+    #[error("failed to read config: {0}")]
+    Read(std::io::Error),
+    #[error("invalid config.toml: {0}")]
+    Parse(toml::de::Error),
     #[error("failed to serialize config: {0}")]
     Serialize(toml::ser::Error),
     #[error("failed to write config: {0}")]
@@ -115,9 +379,73 @@ pub enum ConfigError {
     #[error("not a directory: {0}")]
     NotADirectory(PathBuf),
     #[error(
-        "unknown config key: {0}. Use: embed_url, embed_model, chat_url, chat_model, default_k"
+        "unknown config key: {0}. Use: embed_backend, embed_url, embed_model, chat_backend, \
+         chat_url, chat_model, default_k, context_length, api_key"
     )]
     UnknownConfigKey(String),
     #[error("invalid default_k: must be a positive integer")]
     InvalidDefaultK,
+    #[error("invalid context_length: must be a positive integer")]
+    InvalidContextLength,
+    #[error("invalid backend: {0:?}. Use \"ollama\", \"openai\" or \"local\"")]
+    InvalidBackend(String),
+    #[error(
+        "the local backend only computes embeddings; set chat_backend to \"ollama\" or \"openai\""
+    )]
+    LocalChatBackend,
+    #[error("unknown vault: {0}")]
+    UnknownVault(String),
+    #[error("a vault named {0} already exists")]
+    DuplicateVault(String),
+    #[error("invalid vault name: {0:?}. Use a plain name without slashes")]
+    InvalidVaultName(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_local_chat_backend() {
+        assert_eq!(
+            parse_backend("local").unwrap(),
+            ModelBackend::Local,
+            "fine for embeddings"
+        );
+        assert!(matches!(
+            parse_chat_backend("local"),
+            Err(ConfigError::LocalChatBackend)
+        ));
+    }
+
+    #[test]
+    fn loads_config_with_local_chat_backend() {
+        let dir = std::env::temp_dir().join(format!("noema-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILENAME);
+        std::fs::write(
+            &path,
+            "notes_root = \"/notes\"\n\n[models]\nchat_backend = \"local\"\n\n\
+             [[vaults]]\nname = \"work\"\nroot = \"/work\"\n\n\
+             [vaults.models]\nchat_backend = \"local\"\n",
+        )
+        .unwrap();
+        let config = load_config_from(&path).unwrap();
+        assert_eq!(config.notes_root.as_deref(), Some("/notes"));
+        assert_eq!(config.vaults.len(), 1);
+        let models = config.active_models();
+        assert_eq!(models.chat_backend, Some(ModelBackend::Local));
+        assert!(crate::ModelClient::for_chat(&models).is_err());
+
+        std::fs::write(&path, "notes_root = [").unwrap();
+        assert!(matches!(
+            load_config_from(&path),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(load_config_from(&dir.join("missing.toml"))
+            .unwrap()
+            .vaults
+            .is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -0,0 +1,599 @@
+//! Text extraction from documents that are not plain-text notes: PDF and HTML.
+//!
+//! A [DocumentExtractor] turns the bytes of a file into an [ExtractedDocument]: its text,
+//! an optional title, and [LocationMark]s saying which page or heading each part of the text
+//! came from. The text is what gets chunked and indexed; the marks give each chunk a
+//! [SourceLocation] such as "page 4". Extractors are registered in a
+//! [crate::notes::FormatRegistry] next to the text formats.
+//!
+//! Extraction is slow for large PDFs, so results are kept in an [ExtractionCache], keyed
+//! by a SHA-256 of the file content and the extractor. Unchanged files are never extracted
+//! twice, even after they are moved or renamed. The cache is bounded by size; when full,
+//! the least recently used entries are removed.
+
+use std::fs::File;
+use std::io::Write;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::app_data::app_data_dir;
+use crate::notes::{LocationMark, SourceLocation};
+
+/// Bump when extraction output changes, so cached results of the old extractors are
+/// ignored.
+const EXTRACTION_VERSION: u32 = 1;
+const CACHE_DIRNAME: &str = "extract_cache";
+/// Default size limit of an [ExtractionCache]: 128 MiB of extracted text.
+pub const DEFAULT_EXTRACTION_CACHE_BYTES: u64 = 128 * 1024 * 1024;
+
+/// Text of a document and where in the document each part of it came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtractedDocument {
+    /// Document title, e.g. the HTML `<title>`.
+    pub title: Option<String>,
+    /// Plain text, headings written as markdown `#` lines.
+    pub text: String,
+    /// Page or heading starts in `text`, in order.
+    pub locations: Vec<LocationMark>,
+}
+
+/// Extracts text from one kind of binary or markup document.
+pub trait DocumentExtractor: Send + Sync {
+    /// Short name, part of the cache key.
+    fn name(&self) -> &'static str;
+
+    /// Lowercase file extensions without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn extract(&self, bytes: &[u8]) -> Result<ExtractedDocument, ExtractError>;
+}
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("PDF extraction failed: {0}")]
+    Pdf(String),
+    #[error("{0} extractor panicked")]
+    Panicked(&'static str),
+}
+
+/// PDF text by page, via the pure-Rust `pdf-extract` crate. Scanned PDFs without a text
+/// layer give no text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PdfExtractor;
+
+impl DocumentExtractor for PdfExtractor {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pdf"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<ExtractedDocument, ExtractError> {
+        // pdf-extract panics on some malformed files; one bad PDF must not stop a scan.
+        let pages = catch_unwind(AssertUnwindSafe(|| {
+            pdf_extract::extract_text_from_mem_by_pages(bytes)
+        }))
+        .map_err(|_| ExtractError::Panicked(self.name()))?
+        .map_err(|e| ExtractError::Pdf(e.to_string()))?;
+
+        let mut doc = ExtractedDocument::default();
+        for (i, page) in pages.iter().enumerate() {
+            let page = tidy_pdf_text(page);
+            if page.is_empty() {
+                continue;
+            }
+            if !doc.text.is_empty() {
+                doc.text.push_str("\n\n");
+            }
+            doc.locations.push(LocationMark {
+                start_byte: doc.text.len(),
+                location: SourceLocation::Page(i as u32 + 1),
+            });
+            doc.text.push_str(&page);
+        }
+        Ok(doc)
+    }
+}
+
+/// Page text with trailing spaces dropped and runs of blank lines collapsed to one.
+fn tidy_pdf_text(page: &str) -> String {
+    let mut out = String::with_capacity(page.len());
+    let mut blank = false;
+    for line in page.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        out.push_str(line);
+        blank = false;
+    }
+    out
+}
+
+/// Readable text of an HTML page, such as a saved article. Uses `<article>` or `<main>`
+/// when present, otherwise `<body>`; navigation, scripts and other page furniture are
+/// dropped. Headings become markdown headings and locations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlExtractor;
+
+/// Elements whose content is not part of the readable text.
+const HTML_SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+    "button", "iframe", "head",
+];
+
+/// Elements that start a new paragraph.
+const HTML_BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "blockquote",
+    "ul",
+    "ol",
+    "table",
+    "tr",
+    "dl",
+    "figure",
+    "figcaption",
+    "hr",
+    "body",
+];
+
+impl DocumentExtractor for HtmlExtractor {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm", "xhtml"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<ExtractedDocument, ExtractError> {
+        let html = Html::parse_document(&String::from_utf8_lossy(bytes));
+        let select = |s: &str| Selector::parse(s).expect("valid selector");
+        let title = html
+            .select(&select("title"))
+            .next()
+            .map(|t| collapse_whitespace(&t.text().collect::<String>()))
+            .filter(|t| !t.is_empty());
+        let root = ["article", "main", "body"]
+            .iter()
+            .find_map(|s| html.select(&select(s)).next())
+            .unwrap_or_else(|| html.root_element());
+
+        let mut out = HtmlText::default();
+        out.walk(root);
+        Ok(ExtractedDocument {
+            title,
+            text: out.text.trim_end().to_string(),
+            locations: out.locations,
+        })
+    }
+}
+
+#[derive(Default)]
+struct HtmlText {
+    text: String,
+    locations: Vec<LocationMark>,
+}
+
+impl HtmlText {
+    fn walk(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        if HTML_SKIPPED.contains(&name) {
+            return;
+        }
+        if let Some(level) = heading_level(name) {
+            let heading = collapse_whitespace(&element.text().collect::<String>());
+            if !heading.is_empty() {
+                self.paragraph_break();
+                self.locations.push(LocationMark {
+                    start_byte: self.text.len(),
+                    location: SourceLocation::Heading(heading.clone()),
+                });
+                self.text.push_str(&"#".repeat(level));
+                self.text.push(' ');
+                self.text.push_str(&heading);
+                self.paragraph_break();
+            }
+            return;
+        }
+        match name {
+            "br" => return self.line_break(),
+            "pre" => {
+                self.paragraph_break();
+                self.text
+                    .push_str(element.text().collect::<String>().trim_end());
+                return self.paragraph_break();
+            }
+            "li" | "dt" | "dd" => {
+                self.line_break();
+                self.text.push_str("- ");
+            }
+            "td" | "th" => self.push_words(" "),
+            _ if HTML_BLOCKS.contains(&name) => self.paragraph_break(),
+            _ => {}
+        }
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.push_words(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.walk(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if HTML_BLOCKS.contains(&name) {
+            self.paragraph_break();
+        }
+    }
+
+    /// Append `text` with whitespace collapsed, separated from what precedes it.
+    fn push_words(&mut self, text: &str) {
+        let starts_with_space = text.starts_with(char::is_whitespace);
+        let words = collapse_whitespace(text);
+        if words.is_empty() {
+            if starts_with_space && !self.text.ends_with(char::is_whitespace) {
+                self.text.push(' ');
+            }
+            return;
+        }
+        if starts_with_space && !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+        self.text.push_str(&words);
+        if text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.trim_trailing_spaces();
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+
+    fn paragraph_break(&mut self) {
+        self.trim_trailing_spaces();
+        if self.text.is_empty() || self.text.ends_with("\n\n") {
+            return;
+        }
+        self.text.push_str(if self.text.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    }
+
+    fn trim_trailing_spaces(&mut self) {
+        let len = self.text.trim_end_matches([' ', '\t']).len();
+        self.text.truncate(len);
+    }
+}
+
+fn heading_level(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [b'h', d @ b'1'..=b'6'] => Some((d - b'0') as usize),
+        _ => None,
+    }
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Extraction results on disk, one JSON file per document, named by [extraction_key].
+/// An entry's modification time records when it was last used; writes evict the least
+/// recently used entries once the directory exceeds its size limit. Deleting the directory
+/// just costs re-extraction.
+#[derive(Debug, Clone)]
+pub struct ExtractionCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Estimated size of all entries, shared by clones: measured on the first write, then
+    /// grown by each write. The directory is listed again only once this passes the limit.
+    size: Arc<Mutex<Option<u64>>>,
+}
+
+impl ExtractionCache {
+    /// Cache in `dir`, created on the first write.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_bytes: DEFAULT_EXTRACTION_CACHE_BYTES,
+            size: Arc::default(),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Cache in the app data directory.
+    pub fn open_default() -> Option<Self> {
+        app_data_dir().map(|d| Self::open(d.join(CACHE_DIRNAME)))
+    }
+
+    /// Cached extraction for `key`, marked as recently used. Unreadable entries count as
+    /// missing.
+    pub fn get(&self, key: &str) -> Option<ExtractedDocument> {
+        let path = self.entry_path(key);
+        let bytes = std::fs::read(&path).ok()?;
+        let doc = serde_json::from_slice(&bytes).ok()?;
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(doc)
+    }
+
+    /// Store `doc` under `key`, then evict old entries if over the size limit. Written to a
+    /// temporary file first, so readers never see a partial entry.
+    pub fn put(&self, key: &str, doc: &ExtractedDocument) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("json.tmp");
+        let bytes = serde_json::to_vec(doc).map_err(std::io::Error::other)?;
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+
+        let mut size = self.size.lock().unwrap_or_else(PoisonError::into_inner);
+        let estimate = match *size {
+            Some(total) => total + bytes.len() as u64,
+            None => self.entries()?.iter().map(|(_, len, _)| len).sum(),
+        };
+        *size = Some(if estimate > self.max_bytes {
+            self.evict()?
+        } else {
+            estimate
+        });
+        Ok(())
+    }
+
+    /// Remove least recently used entries until the cache is within 90% of its limit, so
+    /// eviction runs in batches rather than on every write. Returns the size left.
+    fn evict(&self) -> std::io::Result<u64> {
+        let mut entries = self.entries()?;
+        let mut bytes: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        let target = self.max_bytes / 10 * 9;
+        entries.sort();
+        for (_, len, path) in entries {
+            if bytes <= target {
+                break;
+            }
+            match std::fs::remove_file(&path) {
+                // Another process evicted it first.
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => bytes -= len,
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Last use, size and path of every entry. Entries removed while listing are skipped.
+    fn entries(&self) -> std::io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            match entry.metadata() {
+                Ok(meta) => entries.push((meta.modified()?, meta.len(), path)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Cache key of `bytes` extracted by `extractor`: hex SHA-256 of the extractor name, the
+/// extraction version and the content.
+pub fn extraction_key(extractor: &str, bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(extractor.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(EXTRACTION_VERSION.to_le_bytes());
+    hasher.update(bytes);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunks::chunk_note;
+    use crate::notes::FormatRegistry;
+
+    /// A minimal PDF with one Helvetica text line per page.
+    fn pdf_with_pages(pages: &[&str]) -> Vec<u8> {
+        let n = pages.len();
+        let kids: Vec<String> = (0..n).map(|i| format!("{} 0 R", 4 + 2 * i)).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), n),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+        ];
+        for (i, text) in pages.iter().enumerate() {
+            let content = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                5 + 2 * i
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            ));
+        }
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, obj).into_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for off in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", off).into_bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .into_bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn extracts_pdf_pages_and_html_headings() {
+        let pdf = PdfExtractor
+            .extract(&pdf_with_pages(&[
+                "Policy number 4411",
+                "Water damage is covered",
+            ]))
+            .unwrap();
+        let pages: Vec<(&str, &SourceLocation)> = pdf
+            .locations
+            .iter()
+            .map(|m| {
+                (
+                    pdf.text[m.start_byte..].lines().next().unwrap(),
+                    &m.location,
+                )
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            vec![
+                ("Policy number 4411", &SourceLocation::Page(1)),
+                ("Water damage is covered", &SourceLocation::Page(2)),
+            ]
+        );
+
+        let html = br#"<html><head><title>Sourdough basics</title><script>x()</script></head>
+            <body><nav>Home | Recipes</nav><article><h1>Sourdough   basics</h1>
+            <p>Feed the <em>starter</em> daily.</p><h2>Baking</h2><ul><li>Preheat to 250&deg;C</li>
+            <li>Bake 40 min</li></ul></article><footer>(c) 2024</footer></body></html>"#;
+        let doc = HtmlExtractor.extract(html).unwrap();
+        assert_eq!(doc.title.as_deref(), Some("Sourdough basics"));
+        assert_eq!(
+            doc.text,
+            "# Sourdough basics\n\nFeed the starter daily.\n\n## Baking\n\n- Preheat to 250°C\n- Bake 40 min"
+        );
+        let headings: Vec<&SourceLocation> = doc.locations.iter().map(|m| &m.location).collect();
+        assert_eq!(
+            headings,
+            vec![
+                &SourceLocation::Heading("Sourdough basics".into()),
+                &SourceLocation::Heading("Baking".into()),
+            ]
+        );
+        assert_eq!(&doc.text[doc.locations[1].start_byte..][..9], "## Baking");
+    }
+
+    #[test]
+    fn caches_extractions_and_locates_chunks() {
+        let dir = std::env::temp_dir().join(format!("noema-documents-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let page = dir.join("article.html");
+        let html = "<title>Coverage</title><h1>Home insurance</h1><p>Policy 4411.</p>\
+                    <h2>Water damage</h2><p>Burst pipes are covered up to 10k.</p>";
+        std::fs::write(&page, html).unwrap();
+
+        let cache = ExtractionCache::open(dir.join("cache"));
+        let formats = FormatRegistry::default().with_extraction_cache(cache.clone());
+        let note = formats.read(&page).unwrap().unwrap();
+        let chunks = chunk_note(&note, 40);
+        let located: Vec<(&str, Option<String>)> = chunks
+            .iter()
+            .map(|c| {
+                let loc = c.location.as_ref().map(|l| l.describe("article.html"));
+                (c.text.as_str(), loc)
+            })
+            .collect();
+        assert_eq!(located[0], ("Coverage", None));
+        assert_eq!(
+            located.last().unwrap().1.as_deref(),
+            Some("section \"Water damage\" of article.html")
+        );
+
+        // A second read is served from the cache.
+        let key = extraction_key("html", html.as_bytes());
+        let mut cached = cache.get(&key).unwrap();
+        assert_eq!(cached.text, note.raw);
+        cached.title = Some("From cache".into());
+        cache.put(&key, &cached).unwrap();
+        let again = formats.read(&page).unwrap().unwrap();
+        assert_eq!(
+            again.frontmatter.unwrap().title.as_deref(),
+            Some("From cache")
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn evicts_least_recently_used_extractions() {
+        let dir = std::env::temp_dir().join(format!("noema-extract-lru-{}", std::process::id()));
+        let doc = |text: &str| ExtractedDocument {
+            title: None,
+            text: text.repeat(100),
+            locations: Vec::new(),
+        };
+        let size = serde_json::to_vec(&doc("a")).unwrap().len() as u64;
+        let cache = ExtractionCache::open(&dir).with_max_bytes(size * 7 / 2);
+        let old = SystemTime::now() - std::time::Duration::from_secs(60);
+        for (i, key) in ["one", "two", "three"].iter().enumerate() {
+            cache.put(key, &doc("a")).unwrap();
+            let file = File::options()
+                .write(true)
+                .open(cache.entry_path(key))
+                .unwrap();
+            file.set_modified(old + std::time::Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        assert!(cache.get("one").is_some());
+        cache.put("four", &doc("b")).unwrap();
+        assert!(
+            cache.get("two").is_none(),
+            "least recently used entry evicted"
+        );
+        for key in ["one", "three", "four"] {
+            assert!(cache.get(key).is_some(), "{key} kept");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,471 @@
+//! Persistent, content-addressed cache of chunk embeddings.
+//!
+//! Entries are keyed by a SHA-256 of the embedding model, where it is served (see
+//! [EmbedSource]) and the chunk text with whitespace normalized, so an unchanged paragraph
+//! is never sent to the model twice, even across full rebuilds or after a note is moved.
+//! A re-pulled model with a new digest, or the same model name on another server, gets
+//! fresh entries. The cache lives in one file in the app
+//! data directory and is bounded by size; when full, the least recently used entries are
+//! evicted.
+//!
+//! [CachedEmbedder] puts the cache in front of any [Embedder].
+//!
+//! File layout (little-endian): magic, u32 version, u64 clock, u64 entry count, then per
+//! entry a 32-byte key, u64 last-used tick, u32 dimension and the f32 values.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::app_data::app_data_dir;
+use crate::provider::{Embedder, ModelBackend, ModelError};
+
+const CACHE_MAGIC: &[u8; 8] = b"NOEMAEMB";
+const CACHE_VERSION: u32 = 2;
+const CACHE_FILENAME: &str = "embed_cache.bin";
+/// Default size limit: 256 MiB, about 80k embeddings of 768 dimensions.
+pub const DEFAULT_EMBED_CACHE_BYTES: usize = 256 * 1024 * 1024;
+/// Fixed per-entry overhead counted against the size limit: key, tick and dimension.
+const ENTRY_OVERHEAD: usize = 32 + 8 + 4;
+
+pub type CacheKey = [u8; 32];
+
+/// Where an embedding model is served. Part of every cache key besides the model name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbedSource {
+    pub backend: ModelBackend,
+    pub url: String,
+    /// Digest of the installed model, where the server reports one.
+    pub digest: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    embedding: Vec<f32>,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn size(&self) -> usize {
+        ENTRY_OVERHEAD + self.embedding.len() * 4
+    }
+}
+
+/// Embedding cache with least-recently-used eviction.
+#[derive(Debug)]
+pub struct EmbeddingCache {
+    /// Backing file; `None` for a memory-only cache.
+    path: Option<PathBuf>,
+    entries: HashMap<CacheKey, CacheEntry>,
+    max_bytes: usize,
+    bytes: usize,
+    /// Logical time, bumped on every lookup hit and insert.
+    clock: u64,
+    /// Set when entries are added or removed. Lookups only refresh recency, which is saved
+    /// along with the next change.
+    dirty: bool,
+}
+
+impl EmbeddingCache {
+    /// A cache that is never written to disk.
+    pub fn in_memory(max_bytes: usize) -> Self {
+        Self {
+            path: None,
+            entries: HashMap::new(),
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            dirty: false,
+        }
+    }
+
+    /// Open the cache file at `path`. A missing file gives an empty cache; so does an
+    /// unreadable one, since the cache can always be rebuilt from the model.
+    pub fn open<P: AsRef<Path>>(path: P, max_bytes: usize) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let mut cache = Self::in_memory(max_bytes);
+        if let Ok(file) = File::open(&path) {
+            if let Ok((clock, entries)) = read_entries(&mut BufReader::new(file)) {
+                cache.clock = clock;
+                cache.bytes = entries.values().map(CacheEntry::size).sum();
+                cache.entries = entries;
+                cache.evict();
+            }
+        }
+        cache.path = Some(path);
+        cache
+    }
+
+    /// Open the cache in the app data directory.
+    pub fn open_default(max_bytes: usize) -> Option<Self> {
+        default_embed_cache_path().map(|p| Self::open(p, max_bytes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Approximate size of the cached data in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Cached embedding of `text` under `model` from `source`, if any. Marks the entry as
+    /// recently used.
+    pub fn get(&mut self, model: &str, source: &EmbedSource, text: &str) -> Option<Vec<f32>> {
+        self.get_key(&cache_key(model, source, text))
+    }
+
+    pub(crate) fn get_key(&mut self, key: &CacheKey) -> Option<Vec<f32>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.embedding.clone())
+    }
+
+    /// Store the embedding of `text` under `model` from `source`, evicting old entries if
+    /// over the limit.
+    pub fn insert(&mut self, model: &str, source: &EmbedSource, text: &str, embedding: Vec<f32>) {
+        self.insert_key(cache_key(model, source, text), embedding);
+    }
+
+    pub(crate) fn insert_key(&mut self, key: CacheKey, embedding: Vec<f32>) {
+        self.clock += 1;
+        let entry = CacheEntry {
+            embedding,
+            last_used: self.clock,
+        };
+        self.bytes += entry.size();
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.bytes -= old.size();
+        }
+        self.dirty = true;
+        self.evict();
+    }
+
+    /// Drop every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+        self.dirty = true;
+    }
+
+    /// Write the cache to its file if it changed. The file is replaced atomically.
+    pub fn save(&mut self) -> Result<(), EmbedCacheError> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut w = BufWriter::new(File::create(&tmp_path)?);
+            w.write_all(CACHE_MAGIC)?;
+            w.write_all(&CACHE_VERSION.to_le_bytes())?;
+            w.write_all(&self.clock.to_le_bytes())?;
+            w.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+            for (key, entry) in &self.entries {
+                w.write_all(key)?;
+                w.write_all(&entry.last_used.to_le_bytes())?;
+                w.write_all(&(entry.embedding.len() as u32).to_le_bytes())?;
+                for x in &entry.embedding {
+                    w.write_all(&x.to_le_bytes())?;
+                }
+            }
+            w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Evict least recently used entries until the cache is within 90% of its limit, so
+    /// eviction runs in batches rather than on every insert.
+    fn evict(&mut self) {
+        if self.bytes <= self.max_bytes {
+            return;
+        }
+        let target = self.max_bytes / 10 * 9;
+        let mut by_age: Vec<(u64, CacheKey)> = self
+            .entries
+            .iter()
+            .map(|(k, e)| (e.last_used, *k))
+            .collect();
+        by_age.sort_unstable();
+        for (_, key) in by_age {
+            if self.bytes <= target {
+                break;
+            }
+            if let Some(old) = self.entries.remove(&key) {
+                self.bytes -= old.size();
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+/// An [Embedder] that answers from a shared [EmbeddingCache] where it can and only sends
+/// missing texts to the wrapped embedder. The caller decides when to save the cache.
+#[derive(Debug, Clone)]
+pub struct CachedEmbedder<E> {
+    inner: E,
+    cache: Option<Arc<Mutex<EmbeddingCache>>>,
+    source: EmbedSource,
+}
+
+impl<E: Embedder> CachedEmbedder<E> {
+    /// Wrap `inner`; with `cache` set to `None` every call goes straight through.
+    pub fn new(inner: E, cache: Option<Arc<Mutex<EmbeddingCache>>>) -> Self {
+        Self {
+            inner,
+            cache,
+            source: EmbedSource::default(),
+        }
+    }
+
+    /// Where `inner` is served, so its entries are kept apart from other servers' and
+    /// other versions of the same model.
+    pub fn with_source(mut self, source: EmbedSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+impl<E: Embedder> Embedder for CachedEmbedder<E> {
+    fn model_name(&self) -> &str {
+        self.inner.model_name()
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        let mut out = self.embed_batch(&[text.to_string()]).await?;
+        Ok(out.pop().unwrap_or_default())
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        let Some(cache) = self.cache.as_ref() else {
+            return self.inner.embed_batch(texts).await;
+        };
+        let model = self.inner.model_name();
+        let keys: Vec<CacheKey> = texts
+            .iter()
+            .map(|t| cache_key(model, &self.source, t))
+            .collect();
+        let mut out: Vec<Option<Vec<f32>>> = {
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            keys.iter().map(|k| cache.get_key(k)).collect()
+        };
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| out[i].is_none()).collect();
+        if !missing.is_empty() {
+            let missing_texts: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+            let fresh = self.inner.embed_batch(&missing_texts).await?;
+            if fresh.len() != missing.len() {
+                return Err(ModelError::BatchSize {
+                    expected: missing.len(),
+                    got: fresh.len(),
+                });
+            }
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            for (i, embedding) in missing.into_iter().zip(fresh) {
+                cache.insert_key(keys[i], embedding.clone());
+                out[i] = Some(embedding);
+            }
+        }
+        Ok(out.into_iter().map(Option::unwrap_or_default).collect())
+    }
+}
+
+/// Default cache file: `<app_data_dir>/embed_cache.bin`.
+pub fn default_embed_cache_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join(CACHE_FILENAME))
+}
+
+/// Cache key of `text` embedded with `model` from `source`. Runs of whitespace count as one
+/// space and leading/trailing whitespace is ignored.
+pub fn cache_key(model: &str, source: &EmbedSource, text: &str) -> CacheKey {
+    let backend = match source.backend {
+        ModelBackend::Ollama => "ollama",
+        ModelBackend::OpenAi => "openai",
+        ModelBackend::Local => "local",
+    };
+    let mut hasher = Sha256::new();
+    for part in [
+        backend,
+        source.url.trim_end_matches('/'),
+        model,
+        source.digest.as_deref().unwrap_or(""),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    for (i, word) in text.split_whitespace().enumerate() {
+        if i > 0 {
+            hasher.update(b" ");
+        }
+        hasher.update(word.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn read_entries(
+    r: &mut impl Read,
+) -> Result<(u64, HashMap<CacheKey, CacheEntry>), EmbedCacheError> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != CACHE_MAGIC {
+        return Err(EmbedCacheError::InvalidFormat);
+    }
+    if read_u32(r)? != CACHE_VERSION {
+        return Err(EmbedCacheError::InvalidFormat);
+    }
+    let clock = read_u64(r)?;
+    let count = read_u64(r)? as usize;
+    let mut entries = HashMap::with_capacity(count.min(1 << 20));
+    for _ in 0..count {
+        let mut key = [0u8; 32];
+        r.read_exact(&mut key)?;
+        let last_used = read_u64(r)?;
+        let dim = read_u32(r)? as usize;
+        let mut raw = vec![0u8; dim * 4];
+        r.read_exact(&mut raw)?;
+        let embedding = raw
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        entries.insert(
+            key,
+            CacheEntry {
+                embedding,
+                last_used,
+            },
+        );
+    }
+    Ok((clock, entries))
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+#[derive(Debug, Error)]
+pub enum EmbedCacheError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not an embedding cache file")]
+    InvalidFormat,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeEmbedder;
+
+    #[test]
+    fn key_ignores_whitespace_but_not_model() {
+        let src = EmbedSource::default();
+        assert_eq!(cache_key("m", &src, "a  b\n"), cache_key("m", &src, " a b"));
+        assert_ne!(cache_key("m", &src, "a b"), cache_key("n", &src, "a b"));
+        assert_ne!(cache_key("m", &src, "ab"), cache_key("m", &src, "a b"));
+
+        let other_server = EmbedSource {
+            url: "http://gpu-box:11434".to_string(),
+            ..src.clone()
+        };
+        let repulled = EmbedSource {
+            digest: Some("sha256:970aa74c0a90".to_string()),
+            ..src.clone()
+        };
+        let openai = EmbedSource {
+            backend: ModelBackend::OpenAi,
+            ..src.clone()
+        };
+        for other in [other_server, repulled, openai] {
+            assert_ne!(cache_key("m", &src, "a b"), cache_key("m", &other, "a b"));
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_and_persists() {
+        let path = std::env::temp_dir().join(format!("noema-{}-cache.bin", std::process::id()));
+        let src = EmbedSource::default();
+        let entry = ENTRY_OVERHEAD + 2 * 4;
+        let mut cache = EmbeddingCache::open(&path, entry * 3);
+        cache.insert("m", &src, "one", vec![1.0, 0.0]);
+        cache.insert("m", &src, "two", vec![0.0, 1.0]);
+        cache.insert("m", &src, "three", vec![1.0, 1.0]);
+        assert!(cache.get("m", &src, "one").is_some());
+        cache.insert("m", &src, "four", vec![0.5, 0.5]);
+        assert!(
+            cache.get("m", &src, "two").is_none(),
+            "oldest entry evicted"
+        );
+        cache.save().unwrap();
+        assert!(cache.get("m", &src, "one").is_some());
+        assert!(!cache.dirty, "a hit alone does not need a save");
+
+        let mut reopened = EmbeddingCache::open(&path, entry * 3);
+        assert_eq!(reopened.len(), cache.len());
+        assert_eq!(reopened.get("m", &src, "one"), Some(vec![1.0, 0.0]));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn cached_embedder_only_sends_missing_texts() {
+        let cache = Arc::new(Mutex::new(EmbeddingCache::in_memory(1 << 20)));
+        let lengths = FakeEmbedder::new(|t| vec![t.len() as f32]);
+        let embedder = CachedEmbedder::new(lengths, Some(cache));
+        let texts = vec!["a".to_string(), "bb".to_string()];
+        embedder.embed_batch(&texts).await.unwrap();
+        let again = vec!["bb".to_string(), "ccc".to_string()];
+        let out = embedder.embed_batch(&again).await.unwrap();
+        assert_eq!(out, vec![vec![2.0], vec![3.0]]);
+        assert_eq!(embedder.inner().embedded(), 3);
+
+        // A server that drops inputs must not shift embeddings onto the wrong texts.
+        let short = CachedEmbedder::new(
+            Short,
+            Some(Arc::new(Mutex::new(EmbeddingCache::in_memory(1 << 20)))),
+        );
+        assert!(matches!(
+            short.embed_batch(&again).await,
+            Err(ModelError::BatchSize {
+                expected: 2,
+                got: 1
+            })
+        ));
+    }
+
+    struct Short;
+
+    impl Embedder for Short {
+        fn model_name(&self) -> &str {
+            "m"
+        }
+
+        async fn embed(&self, _text: &str) -> Result<Vec<f32>, ModelError> {
+            Ok(vec![1.0])
+        }
+
+        async fn embed_batch(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+            Ok(vec![vec![1.0]])
+        }
+    }
+}
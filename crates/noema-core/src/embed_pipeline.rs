@@ -0,0 +1,218 @@
+//! Embedding of many chunks: split into batches, a bounded number of requests in flight,
+//! retries with exponential backoff on transient errors, and progress reporting.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+
+use crate::chunks::Chunk;
+use crate::provider::{Embedder, ModelError};
+
+pub const DEFAULT_EMBED_BATCH_SIZE: usize = 64;
+pub const DEFAULT_EMBED_CONCURRENCY: usize = 2;
+pub const DEFAULT_EMBED_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_EMBED_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Tuning for [embed_chunks].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbedParams {
+    /// Chunks per embedding request.
+    pub batch_size: usize,
+    /// Requests in flight at once.
+    pub concurrency: usize,
+    /// Retries of a failed batch before giving up; only transient errors are retried.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further retry.
+    pub retry_backoff: Duration,
+}
+
+impl Default for EmbedParams {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_EMBED_BATCH_SIZE,
+            concurrency: DEFAULT_EMBED_CONCURRENCY,
+            max_retries: DEFAULT_EMBED_MAX_RETRIES,
+            retry_backoff: DEFAULT_EMBED_RETRY_BACKOFF,
+        }
+    }
+}
+
+/// Progress of an embedding run, reported after every finished batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmbedProgress {
+    pub chunks_done: usize,
+    pub chunks_total: usize,
+    /// Notes whose chunks are all embedded.
+    pub notes_done: usize,
+    pub notes_total: usize,
+    /// Chunks taken from a checkpoint instead of embedded in this run; included in
+    /// `chunks_done`.
+    pub resumed_chunks: usize,
+    pub elapsed_secs: f64,
+    /// Estimated time left, from the throughput so far.
+    pub eta_secs: Option<f64>,
+}
+
+/// Callback receiving [EmbedProgress].
+pub type ProgressFn<'a> = &'a (dyn Fn(&EmbedProgress) + Send + Sync);
+
+/// Progress callback that ignores its input.
+pub fn no_progress(_: &EmbedProgress) {}
+
+/// Embed the text of `chunks` in batches. Returns one embedding per chunk, in order.
+/// Fails with the first batch that still fails after its retries.
+pub async fn embed_chunks(
+    embedder: &impl Embedder,
+    chunks: &[Chunk],
+    params: &EmbedParams,
+    progress: ProgressFn<'_>,
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let started = Instant::now();
+    let batch_size = params.batch_size.max(1);
+    let mut remaining_per_note: HashMap<&std::path::Path, usize> = HashMap::new();
+    for c in chunks {
+        *remaining_per_note.entry(c.note_path.as_path()).or_insert(0) += 1;
+    }
+    let mut report = EmbedProgress {
+        chunks_done: 0,
+        chunks_total: chunks.len(),
+        notes_done: 0,
+        notes_total: remaining_per_note.len(),
+        resumed_chunks: 0,
+        elapsed_secs: 0.0,
+        eta_secs: None,
+    };
+    progress(&report);
+
+    let batches = chunks.chunks(batch_size).enumerate().map(|(i, batch)| {
+        let texts: Vec<String> = batch.iter().map(|c| c.text.clone()).collect();
+        async move {
+            let embeddings = embed_with_retry(embedder, &texts, params).await;
+            (i, embeddings)
+        }
+    });
+    let mut results = stream::iter(batches).buffer_unordered(params.concurrency.max(1));
+
+    let mut out: Vec<Option<Vec<Vec<f32>>>> = vec![None; chunks.len().div_ceil(batch_size)];
+    while let Some((i, embeddings)) = results.next().await {
+        let embeddings = embeddings?;
+        let batch = &chunks[i * batch_size..(i * batch_size + batch_size).min(chunks.len())];
+        for c in batch {
+            let left = remaining_per_note
+                .get_mut(c.note_path.as_path())
+                .expect("every chunk's note is counted");
+            *left -= 1;
+            if *left == 0 {
+                report.notes_done += 1;
+            }
+        }
+        report.chunks_done += batch.len();
+        let elapsed = started.elapsed().as_secs_f64();
+        report.elapsed_secs = elapsed;
+        report.eta_secs = (report.chunks_done > 0).then(|| {
+            let left = (report.chunks_total - report.chunks_done) as f64;
+            elapsed / report.chunks_done as f64 * left
+        });
+        progress(&report);
+        out[i] = Some(embeddings);
+    }
+    Ok(out.into_iter().flatten().flatten().collect())
+}
+
+/// One batch, retried with exponential backoff while the error is transient.
+async fn embed_with_retry(
+    embedder: &impl Embedder,
+    texts: &[String],
+    params: &EmbedParams,
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let mut attempt = 0u32;
+    loop {
+        match embedder.embed_batch(texts).await {
+            Ok(embeddings) if embeddings.len() == texts.len() => return Ok(embeddings),
+            Ok(embeddings) => {
+                return Err(ModelError::BatchSize {
+                    expected: texts.len(),
+                    got: embeddings.len(),
+                })
+            }
+            Err(e) if e.is_transient() && attempt < params.max_retries => {
+                tokio::time::sleep(params.retry_backoff * 2u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::chunks::ChunkKind;
+    use crate::ollama::OllamaError;
+
+    /// Fails the first request of each batch with a transient error; embeds text as its
+    /// length.
+    #[derive(Default)]
+    struct Flaky(Mutex<HashSet<String>>);
+
+    impl Embedder for Flaky {
+        fn model_name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+            Ok(vec![text.len() as f32])
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+            if self.0.lock().unwrap().insert(texts[0].clone()) {
+                let e = ollama_rs::error::OllamaError::Other("busy".to_string());
+                return Err(OllamaError::Request(e).into());
+            }
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        }
+    }
+
+    fn chunk(note: &str, text: &str) -> Chunk {
+        Chunk {
+            text: text.to_string(),
+            kind: ChunkKind::Body,
+            note_path: PathBuf::from(note),
+            index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_retry_and_keep_order() {
+        let chunks: Vec<Chunk> = (1..=7)
+            .map(|n| chunk(if n <= 4 { "a.md" } else { "b.md" }, &"x".repeat(n)))
+            .collect();
+        let params = EmbedParams {
+            batch_size: 3,
+            concurrency: 2,
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(1),
+        };
+        let reports = Mutex::new(Vec::new());
+        let record = |p: &EmbedProgress| reports.lock().unwrap().push(p.clone());
+        let out = embed_chunks(&Flaky::default(), &chunks, &params, &record)
+            .await
+            .unwrap();
+
+        let lens: Vec<f32> = out.iter().map(|e| e[0]).collect();
+        assert_eq!(lens, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        let last = reports.lock().unwrap().last().cloned().unwrap();
+        assert_eq!((last.chunks_done, last.chunks_total), (7, 7));
+        assert_eq!((last.notes_done, last.notes_total), (2, 2));
+        assert_eq!(last.eta_secs, Some(0.0));
+    }
+}
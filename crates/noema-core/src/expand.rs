@@ -0,0 +1,219 @@
+//! Query expansion for short or vague questions.
+//!
+//! Two optional strategies, both using the chat model before retrieval:
+//! - HyDE (hypothetical document embedding): generate a plausible passage answering the
+//!   question and search with its embedding, which sits closer to real notes than the
+//!   embedding of a terse question.
+//! - Paraphrases: generate rewordings of the question and search with each.
+//!
+//! Every variant is searched separately and the rankings are merged with reciprocal rank
+//! fusion; see [crate::persisted_index::PersistedIndex::search_expanded].
+
+use serde::{Deserialize, Serialize};
+
+use crate::provider::{ChatModel, Embedder, GenerateOptions, ModelError};
+use crate::templates::PromptTemplates;
+
+/// Upper bound on paraphrases per question.
+pub const MAX_PARAPHRASES: usize = 5;
+
+/// Which expansions to run. The default runs none.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryExpansion {
+    /// Search with the embedding of a generated hypothetical answer.
+    pub hyde: bool,
+    /// Number of generated paraphrases to search with (at most [MAX_PARAPHRASES]).
+    pub paraphrases: usize,
+}
+
+impl QueryExpansion {
+    pub fn is_enabled(&self) -> bool {
+        self.hyde || self.paraphrases > 0
+    }
+}
+
+/// Generated variants of a question.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExpansionTexts {
+    /// The hypothetical answer passage, if HyDE ran and succeeded.
+    pub hypothetical: Option<String>,
+    pub paraphrases: Vec<String>,
+}
+
+/// One ranking to compute: `text` drives the lexical side of hybrid search, `embedding`
+/// the vector side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Generate the expansions of `question` selected by `expansion`. Both run concurrently; an
+/// expansion whose generation fails is left out rather than failing the search. Prompts
+/// are the `hyde` and `paraphrase` templates of `templates`.
+pub async fn generate_expansions(
+    client: &impl ChatModel,
+    model: &str,
+    templates: &PromptTemplates,
+    question: &str,
+    expansion: &QueryExpansion,
+) -> ExpansionTexts {
+    let n = expansion.paraphrases.min(MAX_PARAPHRASES);
+    let hyde = async {
+        if !expansion.hyde {
+            return None;
+        }
+        let options = GenerateOptions {
+            temperature: Some(0.3),
+            max_tokens: Some(200),
+            ..Default::default()
+        };
+        let prompt = templates.render_hyde(question).ok()?;
+        let passage = client.generate(model, &prompt, &options).await.ok()?;
+        Some(passage.trim().to_string()).filter(|p| !p.is_empty())
+    };
+    let paraphrases = async {
+        if n == 0 {
+            return Vec::new();
+        }
+        let options = GenerateOptions {
+            temperature: Some(0.7),
+            max_tokens: Some(40 * n as i32 + 40),
+            ..Default::default()
+        };
+        let Ok(prompt) = templates.render_paraphrase(question, n) else {
+            return Vec::new();
+        };
+        match client.generate(model, &prompt, &options).await {
+            Ok(text) => parse_paraphrases(&text, question, n),
+            Err(_) => Vec::new(),
+        }
+    };
+    let (hypothetical, paraphrases) = futures_util::join!(hyde, paraphrases);
+    ExpansionTexts {
+        hypothetical,
+        paraphrases,
+    }
+}
+
+/// Embed `question` and its expansions in one batch. The question comes first; the
+/// hypothetical passage is searched lexically with the question's text, since its made-up
+/// details should not drive keyword matches.
+pub async fn embed_expansions(
+    embedder: &impl Embedder,
+    question: &str,
+    texts: &ExpansionTexts,
+) -> Result<Vec<SearchQuery>, ModelError> {
+    let mut inputs = vec![question.to_string()];
+    inputs.extend(texts.hypothetical.iter().cloned());
+    inputs.extend(texts.paraphrases.iter().cloned());
+    let embeddings = embedder.embed_batch(&inputs).await?;
+    if embeddings.len() != inputs.len() {
+        return Err(ModelError::BatchSize {
+            expected: inputs.len(),
+            got: embeddings.len(),
+        });
+    }
+    let mut lexical = vec![question.to_string()];
+    lexical.extend(texts.hypothetical.iter().map(|_| question.to_string()));
+    lexical.extend(texts.paraphrases.iter().cloned());
+    Ok(lexical
+        .into_iter()
+        .zip(embeddings)
+        .map(|(text, embedding)| SearchQuery { text, embedding })
+        .collect())
+}
+
+/// Generate and embed the expansions of `question`: the queries to pass to
+/// [crate::persisted_index::PersistedIndex::search_expanded].
+pub async fn expand_query(
+    client: &impl ChatModel,
+    model: &str,
+    templates: &PromptTemplates,
+    embedder: &impl Embedder,
+    question: &str,
+    expansion: &QueryExpansion,
+) -> Result<Vec<SearchQuery>, ModelError> {
+    let texts = if expansion.is_enabled() {
+        generate_expansions(client, model, templates, question, expansion).await
+    } else {
+        ExpansionTexts::default()
+    };
+    embed_expansions(embedder, question, &texts).await
+}
+
+/// Read up to `n` paraphrases from a model reply, skipping list markers, quotes, blank
+/// lines, repeats and the question itself.
+fn parse_paraphrases(reply: &str, question: &str, n: usize) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for line in reply.lines() {
+        let line = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .trim_start_matches(['.', ')', '-', '*', '•'])
+            .trim()
+            .trim_matches(['"', '\u{201c}', '\u{201d}'])
+            .trim();
+        if line.is_empty()
+            || line.eq_ignore_ascii_case(question.trim())
+            || out.iter().any(|p| p.eq_ignore_ascii_case(line))
+        {
+            continue;
+        }
+        out.push(line.to_string());
+        if out.len() == n {
+            break;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{FakeChat, FakeEmbedder};
+
+    fn scripted(prompt: &str) -> Result<String, ModelError> {
+        Ok(if prompt.starts_with("Write a short passage") {
+            "My landlord kept the deposit.".to_string()
+        } else {
+            "1. dispute with the landlord\n2. \"rental deposit issue\"\n\n- dispute with the landlord\nthat thing with the landlord\n".to_string()
+        })
+    }
+
+    #[tokio::test]
+    async fn expands_with_hyde_and_paraphrases() {
+        let question = "that thing with the landlord";
+        let templates = PromptTemplates::builtin();
+        let (chat, lengths) = (
+            FakeChat(scripted),
+            FakeEmbedder::new(|t| vec![t.len() as f32]),
+        );
+        let expansion = QueryExpansion {
+            hyde: true,
+            paraphrases: 3,
+        };
+        let queries = expand_query(&chat, "m", &templates, &lengths, question, &expansion)
+            .await
+            .unwrap();
+        let texts: Vec<&str> = queries.iter().map(|q| q.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                question,
+                question,
+                "dispute with the landlord",
+                "rental deposit issue"
+            ]
+        );
+        let hyde_len = "My landlord kept the deposit.".len() as f32;
+        assert_eq!(queries[1].embedding, vec![hyde_len]);
+
+        let plain = QueryExpansion::default();
+        let queries = expand_query(&chat, "m", &templates, &lengths, question, &plain)
+            .await
+            .unwrap();
+        assert_eq!(queries.len(), 1);
+    }
+}
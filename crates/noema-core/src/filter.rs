@@ -0,0 +1,173 @@
+//! Filter expressions evaluated inside search, over chunk location and note metadata.
+//!
+//! Filters are plain data so the desktop app can send them as JSON, e.g.
+//! `{"op": "and", "filters": [{"op": "tag", "tag": "money"}, {"op": "date_range", "from": "2024", "to": "2024"}]}`.
+
+use std::path::{Component, Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunks::{Chunk, ChunkKind};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SearchFilter {
+    /// All sub-filters match (an empty list matches everything).
+    And {
+        filters: Vec<SearchFilter>,
+    },
+    /// Any sub-filter matches (an empty list matches nothing).
+    Or {
+        filters: Vec<SearchFilter>,
+    },
+    Not {
+        filter: Box<SearchFilter>,
+    },
+    /// Note path, relative to the notes root, starts with `prefix` (plain string prefix).
+    PathPrefix {
+        prefix: String,
+    },
+    /// Note lives in `folder` (relative to the notes root) or one of its subfolders.
+    Folder {
+        folder: String,
+    },
+    /// Note has this frontmatter tag (case-insensitive, leading `#` optional).
+    Tag {
+        tag: String,
+    },
+    /// Note frontmatter `type` equals `value` (case-insensitive).
+    Type {
+        value: String,
+    },
+    /// Note date within `[from, to]`. Bounds are `YYYY`, `YYYY-MM` or `YYYY-MM-DD` and
+    /// inclusive at their precision, so `from: "2024", to: "2024"` is the whole year.
+    /// Notes without a date never match.
+    DateRange {
+        #[serde(default)]
+        from: Option<String>,
+        #[serde(default)]
+        to: Option<String>,
+    },
+    Kind {
+        kind: ChunkKind,
+    },
+}
+
+impl SearchFilter {
+    /// Combine optional filters with `And`, dropping `None`s. Returns `None` if none are set.
+    pub fn all(filters: impl IntoIterator<Item = Option<SearchFilter>>) -> Option<SearchFilter> {
+        let mut filters: Vec<SearchFilter> = filters.into_iter().flatten().collect();
+        match filters.len() {
+            0 => None,
+            1 => filters.pop(),
+            _ => Some(SearchFilter::And { filters }),
+        }
+    }
+
+    /// Whether `chunk` passes this filter. Relative paths are resolved against `notes_root`.
+    pub fn matches(&self, chunk: &Chunk, notes_root: &Path) -> bool {
+        match self {
+            Self::And { filters } => filters.iter().all(|f| f.matches(chunk, notes_root)),
+            Self::Or { filters } => filters.iter().any(|f| f.matches(chunk, notes_root)),
+            Self::Not { filter } => !filter.matches(chunk, notes_root),
+            Self::PathPrefix { prefix } => relative(&chunk.note_path, notes_root)
+                .to_string_lossy()
+                .starts_with(prefix.trim_start_matches('/')),
+            Self::Folder { folder } => {
+                let folder = Path::new(folder.trim_matches('/'));
+                let rel = relative(&chunk.note_path, notes_root);
+                folder.components().all(|c| matches!(c, Component::CurDir))
+                    || rel.parent().is_some_and(|p| p.starts_with(folder))
+            }
+            Self::Tag { tag } => {
+                let tag = tag.trim().trim_start_matches('#').to_lowercase();
+                chunk.meta.tags.contains(&tag)
+            }
+            Self::Type { value } => chunk
+                .meta
+                .note_type
+                .as_deref()
+                .is_some_and(|t| t.eq_ignore_ascii_case(value.trim())),
+            Self::DateRange { from, to } => {
+                let Some(date) = chunk.meta.date.as_deref() else {
+                    return false;
+                };
+                let after_from = from
+                    .as_deref()
+                    .map(str::trim)
+                    .is_none_or(|f| date.get(..f.len()).is_some_and(|d| d >= f));
+                let before_to = to
+                    .as_deref()
+                    .map(str::trim)
+                    .is_none_or(|t| date.get(..t.len()).is_some_and(|d| d <= t));
+                after_from && before_to
+            }
+            Self::Kind { kind } => chunk.kind == *kind,
+        }
+    }
+}
+
+fn relative<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::chunks::ChunkMeta;
+
+    fn chunk(path: &str, date: Option<&str>, tags: &[&str]) -> Chunk {
+        Chunk {
+            text: "text".to_string(),
+            kind: ChunkKind::Body,
+            note_path: PathBuf::from(path),
+            index: 0,
+            meta: ChunkMeta {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                note_type: Some("journal".to_string()),
+                date: date.map(ToString::to_string),
+            },
+            span: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn folder_and_prefix_are_relative_to_root() {
+        let root = Path::new("/notes");
+        let c = chunk("/notes/work/q1/plan.md", None, &[]);
+        let folder = |f: &str| SearchFilter::Folder {
+            folder: f.to_string(),
+        };
+        assert!(folder("work").matches(&c, root));
+        assert!(folder("work/q1/").matches(&c, root));
+        assert!(!folder("wor").matches(&c, root));
+        assert!(SearchFilter::PathPrefix {
+            prefix: "wor".to_string()
+        }
+        .matches(&c, root));
+    }
+
+    #[test]
+    fn date_range_is_inclusive_at_bound_precision() {
+        let root = Path::new("/notes");
+        let in_2024 = SearchFilter::DateRange {
+            from: Some("2024".to_string()),
+            to: Some("2024".to_string()),
+        };
+        assert!(in_2024.matches(&chunk("/notes/a.md", Some("2024-12-31"), &[]), root));
+        assert!(!in_2024.matches(&chunk("/notes/a.md", Some("2025-01-01"), &[]), root));
+        assert!(!in_2024.matches(&chunk("/notes/a.md", None, &[]), root));
+    }
+
+    #[test]
+    fn parses_json_expression() {
+        let f: SearchFilter = serde_json::from_str(
+            r##"{"op":"and","filters":[{"op":"tag","tag":"#Money"},{"op":"type","value":"Journal"},{"op":"not","filter":{"op":"kind","kind":"title"}}]}"##,
+        )
+        .unwrap();
+        assert!(f.matches(&chunk("/notes/a.md", None, &["money"]), Path::new("/notes")));
+    }
+}
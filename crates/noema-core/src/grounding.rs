@@ -0,0 +1,470 @@
+//! Checking answers against their sources.
+//!
+//! Answers cite numbered excerpts inline as `[1]`, `[2, 3]` or `[1][3]`. [parse_answer]
+//! splits an answer into sentences with the citations of each, separating markers that
+//! point at no excerpt. [ground_answer] then scores how well each sentence is supported by
+//! the excerpts it cites, either by embedding similarity or by asking the chat model with
+//! the `verify` template, and averages that into a groundedness score for the whole answer.
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::provider::{ChatModel, Embedder, GenerateOptions, ModelError};
+use crate::store::normalize;
+use crate::templates::{PromptTemplates, TemplateError};
+
+/// Verification requests sent to the chat model at once by [GroundingMethod::Llm].
+const VERIFY_CONCURRENCY: usize = 4;
+
+/// How sentence support is scored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroundingMethod {
+    /// Do not check answers.
+    Off,
+    /// Cosine similarity between the sentence and its best cited excerpt.
+    #[default]
+    Embedding,
+    /// Ask the chat model whether the cited excerpts support the sentence.
+    Llm,
+}
+
+/// One sentence of an answer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CitedSentence {
+    /// The sentence as written, citation markers included.
+    pub text: String,
+    /// Excerpt numbers (1-based) cited by this sentence that exist.
+    pub citations: Vec<usize>,
+    /// Cited numbers with no matching excerpt.
+    pub invalid_citations: Vec<usize>,
+    /// Support by the cited excerpts, 0–1; 0 when nothing valid is cited, `None` before
+    /// scoring.
+    pub support: Option<f32>,
+}
+
+/// An answer split into sentences with their citations and support.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroundedAnswer {
+    pub sentences: Vec<CitedSentence>,
+    /// Every cited number with no matching excerpt, in order of first use.
+    pub invalid_citations: Vec<usize>,
+    /// Sentences without a valid citation.
+    pub uncited_sentences: usize,
+    /// Mean support over all sentences, 0–1; uncited sentences count as 0.
+    pub groundedness: f32,
+}
+
+/// Split `answer` into sentences and read their citation markers; `sources` is the number of
+/// excerpts the prompt contained. Support is left unscored.
+pub fn parse_answer(answer: &str, sources: usize) -> Vec<CitedSentence> {
+    split_sentences(answer)
+        .into_iter()
+        .map(|text| {
+            let (citations, invalid_citations) = parse_citations(&text)
+                .into_iter()
+                .partition(|n| (1..=sources).contains(n));
+            CitedSentence {
+                text,
+                citations,
+                invalid_citations,
+                support: None,
+            }
+        })
+        .collect()
+}
+
+/// Score each sentence of `answer` against the cited entries of `excerpts` (excerpt `[n]` is
+/// `excerpts[n - 1]`). `chat` and `templates` are only used by [GroundingMethod::Llm].
+pub async fn ground_answer(
+    answer: &str,
+    excerpts: &[String],
+    method: GroundingMethod,
+    embedder: &impl Embedder,
+    chat: &impl ChatModel,
+    chat_model: &str,
+    templates: &PromptTemplates,
+) -> Result<GroundedAnswer, GroundingError> {
+    let mut sentences = parse_answer(answer, excerpts.len());
+    match method {
+        GroundingMethod::Off => {}
+        GroundingMethod::Embedding => {
+            score_by_embedding(&mut sentences, excerpts, embedder).await?;
+        }
+        GroundingMethod::Llm => {
+            score_by_llm(&mut sentences, excerpts, chat, chat_model, templates).await?;
+        }
+    }
+    Ok(summarize(sentences))
+}
+
+/// Support as the best cosine similarity between a sentence and any excerpt it cites.
+async fn score_by_embedding(
+    sentences: &mut [CitedSentence],
+    excerpts: &[String],
+    embedder: &impl Embedder,
+) -> Result<(), ModelError> {
+    let mut cited: Vec<usize> = sentences
+        .iter()
+        .flat_map(|s| s.citations.iter().copied())
+        .collect();
+    cited.sort_unstable();
+    cited.dedup();
+    let cited_sentences: Vec<usize> = (0..sentences.len())
+        .filter(|&i| !sentences[i].citations.is_empty())
+        .collect();
+    if cited.is_empty() {
+        for s in sentences.iter_mut() {
+            s.support = Some(0.0);
+        }
+        return Ok(());
+    }
+
+    let mut texts: Vec<String> = cited.iter().map(|n| excerpts[n - 1].clone()).collect();
+    texts.extend(
+        cited_sentences
+            .iter()
+            .map(|&i| strip_citations(&sentences[i].text)),
+    );
+    let embeddings: Vec<Vec<f32>> = embedder
+        .embed_batch(&texts)
+        .await?
+        .iter()
+        .map(|e| normalize(e))
+        .collect();
+    if embeddings.len() != texts.len() {
+        return Err(ModelError::BatchSize {
+            expected: texts.len(),
+            got: embeddings.len(),
+        });
+    }
+    let (excerpt_embeddings, sentence_embeddings) = embeddings.split_at(cited.len());
+
+    for s in sentences.iter_mut() {
+        s.support = Some(0.0);
+    }
+    for (&i, sentence_embedding) in cited_sentences.iter().zip(sentence_embeddings) {
+        let best = sentences[i]
+            .citations
+            .iter()
+            .map(|n| {
+                let e = &excerpt_embeddings[cited.binary_search(n).expect("cited excerpt")];
+                dot(e, sentence_embedding)
+            })
+            .fold(0.0f32, f32::max);
+        sentences[i].support = Some(best.clamp(0.0, 1.0));
+    }
+    Ok(())
+}
+
+/// Support from the chat model's verdict: yes = 1, partly = 0.5, no = 0. Fails if any
+/// request fails, rather than counting an unchecked sentence as unsupported.
+async fn score_by_llm(
+    sentences: &mut [CitedSentence],
+    excerpts: &[String],
+    chat: &impl ChatModel,
+    chat_model: &str,
+    templates: &PromptTemplates,
+) -> Result<(), GroundingError> {
+    let options = GenerateOptions {
+        temperature: Some(0.0),
+        max_tokens: Some(4),
+        ..Default::default()
+    };
+    let prompts = sentences
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !s.citations.is_empty())
+        .map(|(i, s)| {
+            let cited: Vec<&str> = s
+                .citations
+                .iter()
+                .map(|n| excerpts[n - 1].as_str())
+                .collect();
+            Ok((
+                i,
+                templates.render_verify(&strip_citations(&s.text), &cited)?,
+            ))
+        })
+        .collect::<Result<Vec<(usize, String)>, TemplateError>>()?;
+    let requests = prompts.into_iter().map(|(i, prompt)| {
+        let options = &options;
+        async move {
+            let reply = chat.generate(chat_model, &prompt, options).await?;
+            Ok::<_, GroundingError>((i, parse_verdict(&reply)))
+        }
+    });
+    let verdicts: Vec<(usize, f32)> = stream::iter(requests)
+        .buffer_unordered(VERIFY_CONCURRENCY)
+        .try_collect()
+        .await?;
+    for s in sentences.iter_mut() {
+        s.support = Some(0.0);
+    }
+    for (i, v) in verdicts {
+        sentences[i].support = Some(v);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum GroundingError {
+    #[error(transparent)]
+    Model(#[from] ModelError),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+}
+
+fn parse_verdict(reply: &str) -> f32 {
+    let reply = reply.trim().to_ascii_lowercase();
+    if reply.starts_with("yes") {
+        1.0
+    } else if reply.starts_with("partly") || reply.starts_with("partial") {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+fn summarize(sentences: Vec<CitedSentence>) -> GroundedAnswer {
+    let mut invalid_citations: Vec<usize> = Vec::new();
+    for n in sentences.iter().flat_map(|s| s.invalid_citations.iter()) {
+        if !invalid_citations.contains(n) {
+            invalid_citations.push(*n);
+        }
+    }
+    let uncited_sentences = sentences.iter().filter(|s| s.citations.is_empty()).count();
+    let groundedness = if sentences.is_empty() {
+        0.0
+    } else {
+        let total: f32 = sentences.iter().map(|s| s.support.unwrap_or(0.0)).sum();
+        total / sentences.len() as f32
+    };
+    GroundedAnswer {
+        sentences,
+        invalid_citations,
+        uncited_sentences,
+        groundedness,
+    }
+}
+
+/// Split prose into sentences at `.`, `!` or `?` followed by whitespace, and at line
+/// breaks. Citation markers right after the punctuation (`... fact. [2]`) stay with the
+/// sentence they follow.
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches(['-', '*', '•']).trim();
+        let mut rest = line;
+        while !rest.is_empty() {
+            let end = sentence_end(rest);
+            let sentence = rest[..end].trim();
+            if sentence.chars().any(char::is_alphanumeric) {
+                out.push(sentence.to_string());
+            }
+            rest = rest[end..].trim_start();
+        }
+    }
+    out
+}
+
+/// Byte offset just past the first sentence of `text`.
+fn sentence_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if matches!(bytes[i], b'.' | b'!' | b'?') {
+            let mut end = i + 1;
+            // Keep trailing citation markers with this sentence.
+            loop {
+                let after = text[end..].trim_start();
+                match after.strip_prefix('[').and_then(|a| a.find(']')) {
+                    Some(close) if is_marker(&after[1..close + 1]) => {
+                        end = text.len() - after.len() + close + 2;
+                    }
+                    _ => break,
+                }
+            }
+            if end >= bytes.len() || text[end..].starts_with(char::is_whitespace) {
+                return end;
+            }
+        }
+        i += 1;
+    }
+    text.len()
+}
+
+/// The numbers cited in `sentence`, in order, without repeats. Accepts `[1]`, `[1, 2]`,
+/// `[1][2]` and ranges like `[1-3]`.
+fn parse_citations(sentence: &str) -> Vec<usize> {
+    let mut out = Vec::new();
+    let mut rest = sentence;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+        let inner = &rest[..close];
+        if is_marker(inner) {
+            for part in inner.split(',') {
+                let part = part.trim();
+                let range = match part.split_once(['-', '\u{2013}']) {
+                    Some((a, b)) => a.trim().parse().ok().zip(b.trim().parse().ok()),
+                    None => part.parse::<usize>().ok().map(|n| (n, n)),
+                };
+                if let Some((a, b)) = range {
+                    for n in a..=b.min(a + 20) {
+                        if !out.contains(&n) {
+                            out.push(n);
+                        }
+                    }
+                }
+            }
+        }
+        rest = &rest[close..];
+    }
+    out
+}
+
+fn is_marker(inner: &str) -> bool {
+    !inner.trim().is_empty()
+        && inner
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ',' | ' ' | '-' | '\u{2013}'))
+}
+
+/// `sentence` without its citation markers.
+fn strip_citations(sentence: &str) -> String {
+    let mut out = String::with_capacity(sentence.len());
+    let mut rest = sentence;
+    while let Some(open) = rest.find('[') {
+        let close = rest[open..].find(']').map(|c| open + c);
+        match close {
+            Some(close) if is_marker(&rest[open + 1..close]) => {
+                out.push_str(&rest[..open]);
+                rest = &rest[close + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..open + 1]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{no_chat, FakeChat, FakeEmbedder};
+
+    #[test]
+    fn parses_sentences_and_citations() {
+        let answer = "The deposit was returned in May [1]. Rent went up by 5% [2, 4]. \
+                      The landlord is called Ed.[3][1]\n- No pets allowed [1-2].";
+        let sentences = parse_answer(answer, 3);
+        let texts: Vec<&str> = sentences.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "The deposit was returned in May [1].",
+                "Rent went up by 5% [2, 4].",
+                "The landlord is called Ed.[3][1]",
+                "No pets allowed [1-2].",
+            ]
+        );
+        assert_eq!(sentences[1].citations, vec![2]);
+        assert_eq!(sentences[1].invalid_citations, vec![4]);
+        assert_eq!(sentences[2].citations, vec![3, 1]);
+        assert_eq!(sentences[3].citations, vec![1, 2]);
+        assert_eq!(strip_citations(&sentences[1].text), "Rent went up by 5% .");
+    }
+
+    /// Embeds text on two axes: mentions of "deposit" and everything else.
+    fn topics() -> FakeEmbedder<impl Fn(&str) -> Vec<f32> + Send + Sync> {
+        FakeEmbedder::new(|text: &str| {
+            if text.to_lowercase().contains("deposit") {
+                vec![1.0, 0.0]
+            } else {
+                vec![0.0, 1.0]
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn scores_support_by_embedding() {
+        let excerpts = vec![
+            "The deposit came back in full.".to_string(),
+            "The flat faces the park.".to_string(),
+        ];
+        let answer = "The deposit was returned [1]. It has a balcony [1]. Rent is high [5].";
+        let grounded = ground_answer(
+            answer,
+            &excerpts,
+            GroundingMethod::Embedding,
+            &topics(),
+            &no_chat(),
+            "m",
+            &PromptTemplates::builtin(),
+        )
+        .await
+        .unwrap();
+        let support: Vec<f32> = grounded
+            .sentences
+            .iter()
+            .map(|s| s.support.unwrap())
+            .collect();
+        assert_eq!(support, vec![1.0, 0.0, 0.0]);
+        assert_eq!(grounded.invalid_citations, vec![5]);
+        assert_eq!(grounded.uncited_sentences, 1);
+        assert!((grounded.groundedness - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    /// Says yes to claims about the deposit and fails on anything mentioning rent.
+    fn judge(prompt: &str) -> Result<String, ModelError> {
+        let claim = prompt.rsplit("Claim:").next().unwrap_or_default();
+        if claim.contains("Rent") {
+            Err(ModelError::Unsupported("offline"))
+        } else if claim.contains("deposit") {
+            Ok("Yes".to_string())
+        } else {
+            Ok("No.".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn llm_grounding_reports_failed_requests() {
+        let excerpts = vec!["The deposit came back in full.".to_string()];
+        let grounded = ground_answer(
+            "The deposit was returned [1]. It has a balcony [1]. Pets are fine.",
+            &excerpts,
+            GroundingMethod::Llm,
+            &topics(),
+            &FakeChat(judge),
+            "m",
+            &PromptTemplates::builtin(),
+        )
+        .await
+        .unwrap();
+        let support: Vec<Option<f32>> = grounded.sentences.iter().map(|s| s.support).collect();
+        assert_eq!(support, vec![Some(1.0), Some(0.0), Some(0.0)]);
+
+        let failed = ground_answer(
+            "The deposit was returned [1]. Rent is high [1].",
+            &excerpts,
+            GroundingMethod::Llm,
+            &topics(),
+            &FakeChat(judge),
+            "m",
+            &PromptTemplates::builtin(),
+        )
+        .await;
+        assert!(failed.is_err());
+    }
+}
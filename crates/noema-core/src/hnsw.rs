@@ -0,0 +1,541 @@
+//! Hierarchical navigable small world (HNSW) graph for approximate nearest-neighbour search.
+//!
+//! The graph only stores node links; vectors stay in the owning [crate::store::VectorStore]
+//! and are looked up by node id. Node ids are positions in the store, so the graph must be
+//! told about removals (see [HnswIndex::retain]) before the store compacts its items.
+//! Vectors are expected to be unit length; similarity is the dot product.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::dot;
+
+/// Tunable HNSW parameters. Higher values trade build time and latency for recall.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Max links per node on upper layers. Layer 0 keeps twice as many.
+    pub m: usize,
+    /// Candidate list size while inserting.
+    pub ef_construction: usize,
+    /// Candidate list size while searching. Raised to `k` when smaller.
+    pub ef_search: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+        }
+    }
+}
+
+/// Read access to the vectors a graph was built over, by node id.
+pub(crate) trait VectorSource {
+    fn vector(&self, id: usize) -> &[f32];
+}
+
+/// HNSW graph over the items of a vector store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswIndex {
+    params: HnswParams,
+    /// `links[node][layer]` lists the neighbours of `node` on `layer`.
+    links: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<u32>,
+    /// State of the level generator, kept so rebuilt graphs are reproducible.
+    rng_state: u64,
+}
+
+impl HnswIndex {
+    pub fn new(params: HnswParams) -> Self {
+        Self {
+            params: sanitize(params),
+            links: Vec::new(),
+            entry_point: None,
+            rng_state: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Build a graph over the first `len` vectors of `vectors`.
+    pub(crate) fn build(
+        params: HnswParams,
+        vectors: &(impl VectorSource + ?Sized),
+        len: usize,
+    ) -> Self {
+        let mut index = Self::new(params);
+        for id in 0..len {
+            index.insert(id, vectors);
+        }
+        index
+    }
+
+    pub fn params(&self) -> HnswParams {
+        self.params
+    }
+
+    /// Change the search-time candidate list size without rebuilding.
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.params.ef_search = ef_search.max(1);
+    }
+
+    /// Number of nodes in the graph.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Whether every link points to a node that exists on that layer and the entry point
+    /// is on the top layer. Search trusts this, so graphs read from disk are checked first.
+    pub(crate) fn is_consistent(&self) -> bool {
+        let Some(top) = self.links.iter().map(Vec::len).max() else {
+            return self.entry_point.is_none();
+        };
+        let on_layer = |id: u32, layer: usize| {
+            self.links
+                .get(id as usize)
+                .is_some_and(|layers| layers.len() > layer)
+        };
+        self.links.iter().all(|layers| {
+            !layers.is_empty()
+                && layers
+                    .iter()
+                    .enumerate()
+                    .all(|(layer, ns)| ns.iter().all(|&n| on_layer(n, layer)))
+        }) && self.entry_point.is_some_and(|e| on_layer(e, top - 1))
+    }
+
+    /// Insert node `id`, which must be the next position (`id == self.len()`).
+    pub(crate) fn insert(&mut self, id: usize, vectors: &(impl VectorSource + ?Sized)) {
+        debug_assert_eq!(id, self.links.len());
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(id as u32);
+            return;
+        };
+
+        let query = vectors.vector(id);
+        let top = self.level_of(entry);
+        let mut ep = entry;
+        for layer in (level + 1..=top).rev() {
+            ep = self.greedy_closest(query, ep, layer, vectors);
+        }
+
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(
+                query,
+                ep,
+                self.params.ef_construction,
+                layer,
+                vectors,
+                &|_| true,
+            );
+            let max_links = self.max_links(layer);
+            let selected = select_neighbours(&candidates, max_links, vectors);
+            for &n in &selected {
+                self.links[n as usize][layer].push(id as u32);
+                if self.links[n as usize][layer].len() > max_links {
+                    self.prune(n, layer, vectors);
+                }
+            }
+            self.links[id][layer] = selected;
+            if let Some(best) = candidates.first() {
+                ep = best.id;
+            }
+        }
+
+        if level > top {
+            self.entry_point = Some(id as u32);
+        }
+    }
+
+    /// Approximate top-`k` search. Returns `(node id, similarity)` pairs, best first.
+    pub(crate) fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        vectors: &(impl VectorSource + ?Sized),
+    ) -> Vec<(usize, f32)> {
+        self.search_where(query, k, vectors, &|_| true)
+    }
+
+    /// Like [HnswIndex::search], but only nodes for which `accept` holds are returned.
+    /// Rejected nodes are still traversed, so the graph stays navigable; this works well
+    /// while a reasonable share of nodes is accepted.
+    pub(crate) fn search_where(
+        &self,
+        query: &[f32],
+        k: usize,
+        vectors: &(impl VectorSource + ?Sized),
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        let Some(entry) = self.entry_point else {
+            return Vec::new();
+        };
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut ep = entry;
+        for layer in (1..=self.level_of(entry)).rev() {
+            ep = self.greedy_closest(query, ep, layer, vectors);
+        }
+        let ef = self.params.ef_search.max(k);
+        self.search_layer(query, ep, ef, 0, vectors, accept)
+            .into_iter()
+            .take(k)
+            .map(|c| (c.id as usize, c.sim))
+            .collect()
+    }
+
+    /// Drop every node whose `keep` flag is false and renumber the rest to match a store
+    /// that retains the same items in order. Neighbours that lost links are reconnected
+    /// through the removed nodes' own neighbours. `vectors` must still use the old ids.
+    pub(crate) fn retain(&mut self, keep: &[bool], vectors: &(impl VectorSource + ?Sized)) {
+        debug_assert_eq!(keep.len(), self.links.len());
+        if keep.iter().all(|k| *k) {
+            return;
+        }
+
+        for node in 0..self.links.len() {
+            if !keep[node] {
+                continue;
+            }
+            for layer in 0..self.links[node].len() {
+                let current = &self.links[node][layer];
+                if current.iter().all(|&n| keep[n as usize]) {
+                    continue;
+                }
+                let mut pool: HashSet<u32> = HashSet::new();
+                for &n in current {
+                    if keep[n as usize] {
+                        pool.insert(n);
+                        continue;
+                    }
+                    for &nn in self.links[n as usize].get(layer).into_iter().flatten() {
+                        if keep[nn as usize] && nn as usize != node {
+                            pool.insert(nn);
+                        }
+                    }
+                }
+                let base = vectors.vector(node);
+                let mut candidates: Vec<Candidate> = pool
+                    .into_iter()
+                    .map(|id| Candidate {
+                        id,
+                        sim: dot(base, vectors.vector(id as usize)),
+                    })
+                    .collect();
+                candidates.sort_by(|a, b| b.cmp(a));
+                let max_links = self.max_links(layer);
+                self.links[node][layer] = select_neighbours(&candidates, max_links, vectors);
+            }
+        }
+
+        let mut remap: Vec<Option<u32>> = Vec::with_capacity(keep.len());
+        let mut next = 0u32;
+        for &k in keep {
+            if k {
+                remap.push(Some(next));
+                next += 1;
+            } else {
+                remap.push(None);
+            }
+        }
+
+        let old_links = std::mem::take(&mut self.links);
+        self.links = old_links
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, k)| **k)
+            .map(|(layers, _)| {
+                layers
+                    .into_iter()
+                    .map(|ns| ns.into_iter().filter_map(|n| remap[n as usize]).collect())
+                    .collect()
+            })
+            .collect();
+
+        self.entry_point = match self.entry_point.and_then(|ep| remap[ep as usize]) {
+            Some(ep) => Some(ep),
+            None => (0..self.links.len())
+                .max_by_key(|&n| self.links[n].len())
+                .map(|n| n as u32),
+        };
+    }
+
+    fn level_of(&self, node: u32) -> usize {
+        self.links[node as usize].len() - 1
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn greedy_closest(
+        &self,
+        query: &[f32],
+        start: u32,
+        layer: usize,
+        vectors: &(impl VectorSource + ?Sized),
+    ) -> u32 {
+        let mut best = start;
+        let mut best_sim = dot(query, vectors.vector(start as usize));
+        loop {
+            let mut improved = false;
+            for &n in &self.links[best as usize][layer] {
+                let sim = dot(query, vectors.vector(n as usize));
+                if sim > best_sim {
+                    best = n;
+                    best_sim = sim;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return best;
+            }
+        }
+    }
+
+    /// Beam search on one layer. Returns up to `ef` accepted candidates sorted best first.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry: u32,
+        ef: usize,
+        layer: usize,
+        vectors: &(impl VectorSource + ?Sized),
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = HashSet::from([entry]);
+        // Max-heap of candidates to expand, and min-heap (via Reverse) of the current best.
+        let mut frontier: BinaryHeap<Candidate> = BinaryHeap::new();
+        let mut best: BinaryHeap<std::cmp::Reverse<Candidate>> = BinaryHeap::new();
+        let c = Candidate {
+            id: entry,
+            sim: dot(query, vectors.vector(entry as usize)),
+        };
+        frontier.push(c);
+        if accept(entry as usize) {
+            best.push(std::cmp::Reverse(c));
+        }
+
+        while let Some(current) = frontier.pop() {
+            let worst = best.peek().map(|r| r.0.sim).unwrap_or(f32::NEG_INFINITY);
+            if current.sim < worst && best.len() >= ef {
+                break;
+            }
+            for &n in &self.links[current.id as usize][layer] {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let sim = dot(query, vectors.vector(n as usize));
+                let worst = best.peek().map(|r| r.0.sim).unwrap_or(f32::NEG_INFINITY);
+                if best.len() < ef || sim > worst {
+                    let c = Candidate { id: n, sim };
+                    frontier.push(c);
+                    if accept(n as usize) {
+                        best.push(std::cmp::Reverse(c));
+                        if best.len() > ef {
+                            best.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut out: Vec<Candidate> = best.into_iter().map(|r| r.0).collect();
+        out.sort_by(|a, b| b.cmp(a));
+        out
+    }
+
+    fn prune(&mut self, node: u32, layer: usize, vectors: &(impl VectorSource + ?Sized)) {
+        let base = vectors.vector(node as usize);
+        let mut candidates: Vec<Candidate> = self.links[node as usize][layer]
+            .iter()
+            .map(|&id| Candidate {
+                id,
+                sim: dot(base, vectors.vector(id as usize)),
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        let max_links = self.max_links(layer);
+        self.links[node as usize][layer] = select_neighbours(&candidates, max_links, vectors);
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64*; good enough for level assignment and keeps the crate dependency-free.
+        let mut x = self.rng_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng_state = x;
+        let r = x.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        let unit = ((r >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let ml = 1.0 / (self.params.m as f64).ln();
+        ((-unit.ln() * ml).floor() as usize).min(16)
+    }
+}
+
+/// Pick up to `max` neighbours from `candidates` (sorted best first), preferring ones that
+/// are not already covered by a closer selected neighbour, then filling with the rest.
+fn select_neighbours(
+    candidates: &[Candidate],
+    max: usize,
+    vectors: &(impl VectorSource + ?Sized),
+) -> Vec<u32> {
+    let mut selected: Vec<u32> = Vec::with_capacity(max);
+    let mut skipped: Vec<u32> = Vec::new();
+    for c in candidates {
+        if selected.len() >= max {
+            break;
+        }
+        let v = vectors.vector(c.id as usize);
+        let diverse = selected
+            .iter()
+            .all(|&s| dot(v, vectors.vector(s as usize)) < c.sim);
+        if diverse {
+            selected.push(c.id);
+        } else {
+            skipped.push(c.id);
+        }
+    }
+    for id in skipped {
+        if selected.len() >= max {
+            break;
+        }
+        selected.push(id);
+    }
+    selected
+}
+
+fn sanitize(params: HnswParams) -> HnswParams {
+    HnswParams {
+        m: params.m.max(2),
+        ef_construction: params.ef_construction.max(1),
+        ef_search: params.ef_search.max(1),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    id: u32,
+    sim: f32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sim
+            .total_cmp(&other.sim)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Vecs(Vec<Vec<f32>>);
+
+    impl VectorSource for Vecs {
+        fn vector(&self, id: usize) -> &[f32] {
+            &self.0[id]
+        }
+    }
+
+    fn unit(v: Vec<f32>) -> Vec<f32> {
+        let n = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.into_iter().map(|x| x / n).collect()
+    }
+
+    fn points(n: usize, dim: usize) -> Vecs {
+        let mut seed = 42u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            ((seed >> 33) as f32 / (1u64 << 31) as f32) - 0.5
+        };
+        Vecs(
+            (0..n)
+                .map(|_| unit((0..dim).map(|_| next()).collect()))
+                .collect(),
+        )
+    }
+
+    fn exact(vecs: &Vecs, alive: &[usize], q: &[f32], k: usize) -> Vec<usize> {
+        let mut scored: Vec<(usize, f32)> =
+            alive.iter().map(|&i| (i, dot(q, &vecs.0[i]))).collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn search_recall_is_high() {
+        let vecs = points(600, 16);
+        let index = HnswIndex::build(HnswParams::default(), &vecs, vecs.0.len());
+        let alive: Vec<usize> = (0..vecs.0.len()).collect();
+        let mut hits = 0;
+        for q in 0..50 {
+            let query = &vecs.0[q * 7];
+            let truth = exact(&vecs, &alive, query, 10);
+            let found: Vec<usize> = index
+                .search(query, 10, &vecs)
+                .into_iter()
+                .map(|r| r.0)
+                .collect();
+            hits += truth.iter().filter(|t| found.contains(t)).count();
+        }
+        assert!(hits as f32 / 500.0 > 0.9, "recall too low: {}", hits);
+    }
+
+    #[test]
+    fn retain_renumbers_and_keeps_graph_searchable() {
+        let vecs = points(300, 8);
+        let mut index = HnswIndex::build(HnswParams::default(), &vecs, vecs.0.len());
+        let keep: Vec<bool> = (0..300).map(|i| i % 3 != 0).collect();
+        index.retain(&keep, &vecs);
+
+        let kept = Vecs(
+            vecs.0
+                .iter()
+                .zip(&keep)
+                .filter(|(_, k)| **k)
+                .map(|(v, _)| v.clone())
+                .collect(),
+        );
+        assert_eq!(index.len(), kept.0.len());
+        let alive: Vec<usize> = (0..kept.0.len()).collect();
+        let query = &kept.0[5];
+        let truth = exact(&kept, &alive, query, 5);
+        let found: Vec<usize> = index
+            .search(query, 5, &kept)
+            .into_iter()
+            .map(|r| r.0)
+            .collect();
+        assert_eq!(found[0], truth[0]);
+        assert!(found.iter().all(|&i| i < kept.0.len()));
+    }
+}
@@ -0,0 +1,128 @@
+//! Hybrid retrieval: combines the BM25 ranking and the vector ranking with reciprocal
+//! rank fusion (RRF), so exact-term matches and semantic matches both surface.
+
+use std::collections::HashMap;
+
+use crate::chunks::Chunk;
+use crate::lexical::{Bm25Index, Bm25Params};
+use crate::store::{normalize, VectorStore};
+
+/// Tuning for [hybrid_search].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HybridParams {
+    /// Candidates taken from each ranking before fusion.
+    pub candidates: usize,
+    /// RRF damping constant; larger values flatten the contribution of top ranks.
+    pub rrf_k: f32,
+    pub vector_weight: f32,
+    pub lexical_weight: f32,
+    pub bm25: Bm25Params,
+}
+
+impl Default for HybridParams {
+    fn default() -> Self {
+        Self {
+            candidates: 50,
+            rrf_k: 60.0,
+            vector_weight: 1.0,
+            lexical_weight: 1.0,
+            bm25: Bm25Params::default(),
+        }
+    }
+}
+
+/// Fuse ranked lists of ids. Each list is `(ids best first, weight)`; an id scores
+/// `weight / (rrf_k + rank)` per list it appears in (rank starting at 1). Returns
+/// `(id, fused score)` pairs, best first.
+pub fn reciprocal_rank_fusion(rankings: &[(Vec<usize>, f32)], rrf_k: f32) -> Vec<(usize, f32)> {
+    let mut fused: HashMap<usize, f32> = HashMap::new();
+    for (ids, weight) in rankings {
+        for (rank, id) in ids.iter().enumerate() {
+            *fused.entry(*id).or_insert(0.0) += weight / (rrf_k + rank as f32 + 1.0);
+        }
+    }
+    let mut out: Vec<(usize, f32)> = fused.into_iter().collect();
+    out.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    out
+}
+
+/// Search `store` and `lexical` (which must index the same chunks in the same order) and
+/// fuse the two rankings, considering only chunks for which `accept` holds. Results are
+/// ordered by fused rank; the score of each result is its cosine similarity to the query,
+/// so it stays comparable with [VectorStore::search].
+pub fn hybrid_search(
+    store: &VectorStore,
+    lexical: &Bm25Index,
+    query_text: &str,
+    query_embedding: &[f32],
+    k: usize,
+    params: &HybridParams,
+    accept: &dyn Fn(&Chunk) -> bool,
+) -> Vec<(Chunk, f32)> {
+    let q_norm = normalize(query_embedding);
+    hybrid_search_ids(
+        store,
+        lexical,
+        query_text,
+        query_embedding,
+        k,
+        params,
+        accept,
+    )
+    .into_iter()
+    .map(|(i, _)| (store.chunks()[i].clone(), store.similarity(i, &q_norm)))
+    .collect()
+}
+
+/// Like [hybrid_search], but returns `(position, fused RRF score)` pairs.
+pub(crate) fn hybrid_search_ids(
+    store: &VectorStore,
+    lexical: &Bm25Index,
+    query_text: &str,
+    query_embedding: &[f32],
+    k: usize,
+    params: &HybridParams,
+    accept: &dyn Fn(&Chunk) -> bool,
+) -> Vec<(usize, f32)> {
+    if store.is_empty() || k == 0 {
+        return Vec::new();
+    }
+    let n = params.candidates.max(k);
+    let vector: Vec<usize> = store
+        .search_ids_where(query_embedding, n, accept)
+        .into_iter()
+        .map(|(i, _)| i)
+        .collect();
+    let lexical_ids: Vec<usize> = if lexical.len() == store.len() {
+        let chunks = store.chunks();
+        lexical
+            .search_where(query_text, n, params.bm25, &|i| accept(&chunks[i]))
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut fused = reciprocal_rank_fusion(
+        &[
+            (vector, params.vector_weight),
+            (lexical_ids, params.lexical_weight),
+        ],
+        params.rrf_k,
+    );
+    fused.truncate(k);
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rrf_rewards_agreement() {
+        let fused = reciprocal_rank_fusion(&[(vec![1, 2, 3], 1.0), (vec![3, 4], 1.0)], 60.0);
+        assert_eq!(fused[0].0, 3);
+        assert_eq!(fused.len(), 4);
+    }
+}
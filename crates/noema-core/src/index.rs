@@ -4,14 +4,14 @@ use std::path::Path;
 
 use crate::chunks::{chunk_notes, DEFAULT_MAX_CHARS};
 use crate::notes::{scan_notes, ScanError};
-use crate::ollama::{OllamaClient, OllamaError};
-use crate::store::VectorStore;
+use crate::provider::{Embedder, ModelError};
+use crate::store::{StoreError, VectorStore};
 
 /// Runs the full pipeline: scan notes, chunk, embed, store in memory.
 /// Returns the populated vector store.
 pub async fn build_index(
     root: &Path,
-    client: &OllamaClient,
+    embedder: &impl Embedder,
     max_chars: Option<usize>,
 ) -> Result<VectorStore, IndexError> {
     let notes = scan_notes(root)?;
@@ -23,10 +23,10 @@ pub async fn build_index(
     }
 
     let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
-    let embeddings = client.embed_batch(&texts).await?;
+    let embeddings = embedder.embed_batch(&texts).await?;
 
     let mut store = VectorStore::new();
-    store.add_batch(chunks, embeddings);
+    store.add_batch(chunks, embeddings)?;
     Ok(store)
 }
 
@@ -35,5 +35,7 @@ pub enum IndexError {
     #[error("scan error: {0}")]
     Scan(#[from] ScanError),
     #[error("embedding error: {0}")]
-    Ollama(#[from] OllamaError),
+    Embed(#[from] ModelError),
+    #[error("failed to add embeddings: {0}")]
+    Store(#[from] StoreError),
 }
@@ -0,0 +1,269 @@
+//! BM25 inverted index over chunk text, for exact names, acronyms and rare words that
+//! embeddings tend to miss.
+//!
+//! Document ids are positions in the [crate::store::VectorStore] the index sits next to, so
+//! both must be updated together (see [crate::persisted_index::PersistedIndex::remove_note]).
+
+use std::collections::HashMap;
+
+/// BM25 ranking parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25Params {
+    /// Term frequency saturation.
+    pub k1: f32,
+    /// Document length normalization (0 = none, 1 = full).
+    pub b: f32,
+}
+
+impl Default for Bm25Params {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+/// Inverted index: term → postings of `(doc id, term frequency)`, sorted by doc id.
+#[derive(Debug, Clone, Default)]
+pub struct Bm25Index {
+    postings: HashMap<String, Vec<(u32, u32)>>,
+    doc_lens: Vec<u32>,
+    total_len: u64,
+}
+
+impl Bm25Index {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index over `texts`, one document per text in order.
+    pub fn from_texts<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut index = Self::new();
+        for t in texts {
+            index.add(t);
+        }
+        index
+    }
+
+    /// Number of indexed documents.
+    pub fn len(&self) -> usize {
+        self.doc_lens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc_lens.is_empty()
+    }
+
+    /// Append a document. Its id is the previous [Bm25Index::len].
+    pub fn add(&mut self, text: &str) {
+        let doc = self.doc_lens.len() as u32;
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut len = 0u32;
+        for token in tokenize(text) {
+            *counts.entry(token).or_insert(0) += 1;
+            len += 1;
+        }
+        for (term, tf) in counts {
+            self.postings.entry(term).or_default().push((doc, tf));
+        }
+        self.doc_lens.push(len);
+        self.total_len += len as u64;
+    }
+
+    /// Drop every document whose `keep` flag is false and renumber the rest in order.
+    pub fn retain(&mut self, keep: &[bool]) {
+        debug_assert_eq!(keep.len(), self.doc_lens.len());
+        if keep.iter().all(|k| *k) {
+            return;
+        }
+        let mut remap: Vec<Option<u32>> = Vec::with_capacity(keep.len());
+        let mut next = 0u32;
+        for &k in keep {
+            remap.push(k.then(|| {
+                next += 1;
+                next - 1
+            }));
+        }
+        self.postings.retain(|_, list| {
+            list.retain_mut(|(doc, _)| match remap[*doc as usize] {
+                Some(new) => {
+                    *doc = new;
+                    true
+                }
+                None => false,
+            });
+            !list.is_empty()
+        });
+        let mut flags = keep.iter();
+        self.doc_lens
+            .retain(|_| flags.next().copied().unwrap_or(true));
+        self.total_len = self.doc_lens.iter().map(|l| *l as u64).sum();
+    }
+
+    /// Rank documents against `query`. Returns up to `k` `(doc id, BM25 score)` pairs, best first.
+    pub fn search(&self, query: &str, k: usize, params: Bm25Params) -> Vec<(usize, f32)> {
+        self.search_where(query, k, params, &|_| true)
+    }
+
+    /// Like [Bm25Index::search], but only documents for which `accept` holds are ranked.
+    pub fn search_where(
+        &self,
+        query: &str,
+        k: usize,
+        params: Bm25Params,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        if self.doc_lens.is_empty() || k == 0 {
+            return Vec::new();
+        }
+        let n = self.doc_lens.len() as f32;
+        let avg_len = (self.total_len as f32 / n).max(1.0);
+        let mut terms: Vec<String> = tokenize(query).collect();
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for term in &terms {
+            let Some(list) = self.postings.get(term) else {
+                continue;
+            };
+            let df = list.len() as f32;
+            let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for &(doc, tf) in list {
+                if !accept(doc as usize) {
+                    continue;
+                }
+                let tf = tf as f32;
+                let len = self.doc_lens[doc as usize] as f32;
+                let norm = params.k1 * (1.0 - params.b + params.b * len / avg_len);
+                *scores.entry(doc).or_insert(0.0) += idf * tf * (params.k1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut ranked: Vec<(usize, f32)> = scores
+            .into_iter()
+            .map(|(doc, s)| (doc as usize, s))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        ranked
+    }
+
+    /// Compact encoding used by the binary index file: doc lengths, then each term with
+    /// delta-encoded postings, all as LEB128 varints.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_varint(&mut out, self.doc_lens.len() as u64);
+        for len in &self.doc_lens {
+            put_varint(&mut out, *len as u64);
+        }
+        put_varint(&mut out, self.postings.len() as u64);
+        for (term, list) in &self.postings {
+            put_varint(&mut out, term.len() as u64);
+            out.extend_from_slice(term.as_bytes());
+            put_varint(&mut out, list.len() as u64);
+            let mut prev = 0u32;
+            for &(doc, tf) in list {
+                put_varint(&mut out, (doc - prev) as u64);
+                put_varint(&mut out, tf as u64);
+                prev = doc;
+            }
+        }
+        out
+    }
+
+    /// Inverse of [Bm25Index::encode]. Returns `None` on malformed input.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut pos = 0usize;
+        let docs = get_varint(bytes, &mut pos)? as usize;
+        let mut doc_lens = Vec::with_capacity(docs.min(bytes.len()));
+        for _ in 0..docs {
+            doc_lens.push(get_varint(bytes, &mut pos)? as u32);
+        }
+        let terms = get_varint(bytes, &mut pos)? as usize;
+        let mut postings = HashMap::with_capacity(terms.min(bytes.len()));
+        for _ in 0..terms {
+            let len = get_varint(bytes, &mut pos)? as usize;
+            let term = std::str::from_utf8(bytes.get(pos..pos.checked_add(len)?)?).ok()?;
+            pos += len;
+            let count = get_varint(bytes, &mut pos)? as usize;
+            let mut list = Vec::with_capacity(count.min(bytes.len()));
+            let mut doc = 0u32;
+            for _ in 0..count {
+                doc = doc.checked_add(get_varint(bytes, &mut pos)? as u32)?;
+                let tf = get_varint(bytes, &mut pos)? as u32;
+                if doc as usize >= docs {
+                    return None;
+                }
+                list.push((doc, tf));
+            }
+            postings.insert(term.to_string(), list);
+        }
+        let total_len = doc_lens.iter().map(|l| *l as u64).sum();
+        Some(Self {
+            postings,
+            doc_lens,
+            total_len,
+        })
+    }
+}
+
+/// Lowercased alphanumeric tokens. Keeps digits and non-ASCII letters so codenames,
+/// dosages and accented words stay searchable.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn get_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        v |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rare_term_ranks_first() {
+        let idx = Bm25Index::from_texts([
+            "Meeting notes about the roadmap and the budget.",
+            "Started sertraline 50mg today, check in with Dr. Lee.",
+            "Budget review for the roadmap.",
+        ]);
+        let hits = idx.search("sertraline dose", 3, Bm25Params::default());
+        assert_eq!(hits[0].0, 1);
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn retain_renumbers_and_roundtrips() {
+        let mut idx = Bm25Index::from_texts(["alpha beta", "gamma", "beta delta"]);
+        idx.retain(&[false, true, true]);
+        assert_eq!(idx.len(), 2);
+        let hits = idx.search("beta", 5, Bm25Params::default());
+        assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![1]);
+
+        let decoded = Bm25Index::decode(&idx.encode()).unwrap();
+        assert_eq!(decoded.search("gamma", 5, Bm25Params::default())[0].0, 0);
+    }
+}
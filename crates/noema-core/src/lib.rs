@@ -4,37 +4,126 @@
 //! in its own app data directory (see [app_data]).
 
 pub mod app_data;
+pub mod binary_index;
+pub mod chat;
 pub mod chunks;
 pub mod config;
+pub mod documents;
+pub mod embed_cache;
+pub mod embed_pipeline;
+pub mod expand;
+pub mod filter;
+pub mod grounding;
+pub mod hnsw;
+pub mod hybrid;
 pub mod index;
+pub mod lexical;
+pub mod links;
+#[cfg(feature = "local-embed")]
+pub mod local_embed;
 pub mod memory;
+pub mod mmr;
 pub mod notes;
 pub mod ollama;
+pub mod openai;
 pub mod persisted_index;
+pub mod prompt;
+pub mod provider;
+pub mod quantize;
+pub mod rerank;
+pub mod search;
+pub mod staging;
 pub mod store;
+pub mod templates;
+#[cfg(test)]
+mod test_support;
+pub mod vault;
 pub mod watcher;
 
 pub use app_data::app_data_dir;
-pub use chunks::{chunk_note, chunk_notes, Chunk, ChunkKind, DEFAULT_MAX_CHARS};
+pub use binary_index::{is_binary_index, BINARY_INDEX_MAGIC, BINARY_INDEX_VERSION};
+pub use chat::{
+    chat_messages, condense_question, ChatError, ChatMessage, ChatRole, ChatSession,
+    ChatSessionSummary, SessionStore, MAX_HISTORY_MESSAGES,
+};
+pub use chunks::{
+    chunk_note, chunk_notes, Chunk, ChunkKind, ChunkMeta, SourceSpan, DEFAULT_MAX_CHARS,
+};
 pub use config::{
-    get_notes_root, load_config, set_model_config, set_notes_root, unset_model_config, Config,
-    ConfigError, ModelConfig,
+    get_notes_root, load_config, set_model_config, set_notes_root, unset_model_config,
+    AnswerConfig, CacheConfig, Config, ConfigError, IndexingConfig, ModelConfig, RerankConfig,
+    SearchConfig,
+};
+pub use documents::{
+    extraction_key, DocumentExtractor, ExtractError, ExtractedDocument, ExtractionCache,
+    HtmlExtractor, PdfExtractor, DEFAULT_EXTRACTION_CACHE_BYTES,
+};
+pub use embed_cache::{
+    cache_key, default_embed_cache_path, CachedEmbedder, EmbedCacheError, EmbedSource,
+    EmbeddingCache, DEFAULT_EMBED_CACHE_BYTES,
+};
+pub use embed_pipeline::{
+    embed_chunks, no_progress, EmbedParams, EmbedProgress, ProgressFn, DEFAULT_EMBED_BATCH_SIZE,
+    DEFAULT_EMBED_CONCURRENCY, DEFAULT_EMBED_MAX_RETRIES,
+};
+pub use expand::{
+    embed_expansions, expand_query, generate_expansions, ExpansionTexts, QueryExpansion,
+    SearchQuery, MAX_PARAPHRASES,
+};
+pub use filter::SearchFilter;
+pub use grounding::{
+    ground_answer, parse_answer, CitedSentence, GroundedAnswer, GroundingError, GroundingMethod,
 };
+pub use hnsw::{HnswIndex, HnswParams};
+pub use hybrid::{hybrid_search, reciprocal_rank_fusion, HybridParams};
 pub use index::{build_index, IndexError};
+pub use lexical::{Bm25Index, Bm25Params};
+pub use links::{LinkEdge, LinkGraph, UnresolvedLink};
+#[cfg(feature = "local-embed")]
+pub use local_embed::{HashEmbedder, LOCAL_EMBED_DIM, LOCAL_EMBED_MODEL};
 pub use memory::{
     build_memory_overview, extract_note_signals, LifeArea, MemoryCard, MemoryOverview,
     MemoryWeights, NoteMemorySignals,
 };
-pub use notes::{scan_notes, Note, ScanError};
+pub use mmr::{mmr_select, MMR_POOL_FACTOR};
+pub use notes::{
+    location_at, parse_links, scan_notes, scan_notes_with, FormatRegistry, LinkKind, LocationMark,
+    MarkdownFormat, MdxFormat, Note, NoteFormat, NoteLink, NoteScan, OrgFormat, ScanError,
+    SourceLocation, TextFormat,
+};
 pub use ollama::{
-    OllamaClient, OllamaError, DEFAULT_BASE_URL, DEFAULT_CHAT_MODEL, DEFAULT_EMBED_MODEL,
+    ModelDetails, ModelKind, OllamaClient, OllamaError, DEFAULT_BASE_URL, DEFAULT_CHAT_MODEL,
+    DEFAULT_EMBED_MODEL,
 };
+pub use openai::{OpenAiClient, OpenAiError, DEFAULT_OPENAI_BASE_URL};
 pub use persisted_index::{
-    build_persisted_index, default_index_path, update_persisted_index, BuildPersistedIndexError,
-    IndexSettings, NoteState, PersistedIndex, PersistedIndexError, UpdatePersistedIndexError,
-    UpdatePersistedIndexStats, INDEX_SCHEMA_VERSION,
+    build_persisted_index, build_persisted_index_with, default_index_path, legacy_index_path,
+    update_persisted_index, update_persisted_index_with, BuildPersistedIndexError,
+    EmbeddingMismatch, IndexSettings, NoteState, PersistedIndex, PersistedIndexError,
+    UpdatePersistedIndexError, UpdatePersistedIndexStats, INDEX_SCHEMA_VERSION,
+};
+pub use prompt::{
+    context_length_for, estimate_tokens, format_excerpt, ContextFit, PromptBudget,
+    DEFAULT_ANSWER_TOKENS, DEFAULT_CONTEXT_LENGTH,
+};
+pub use provider::{
+    stream_completion, ChatModel, Embedder, EmbeddingProbe, GenerateOptions, ModelBackend,
+    ModelClient, ModelError, TokenStream,
+};
+pub use quantize::Quantization;
+pub use rerank::{
+    rerank, LlmReranker, OllamaReranker, RerankError, RerankMode, RerankParams, Reranked, Reranker,
+};
+pub use search::{SearchHit, SearchOptions};
+pub use staging::{
+    build_persisted_index_resumable, CheckpointStatus, IndexStaging, DEFAULT_CHECKPOINT_CHUNKS,
 };
 pub use store::{IndexedChunk, StoreError, VectorStore};
+pub use templates::{PromptTemplates, TemplateError, TemplateKind, TemplateSource};
+pub use vault::{
+    active_index_path, active_vault, add_vault, list_vaults, remove_vault, switch_vault,
+    vault_index_path, Vault, VaultConfig, DEFAULT_VAULT_NAME,
+};
 pub use watcher::{watch_notes, WatchError};
 
 /// Returns a short status string. Used to verify the backend is wired up.
@@ -0,0 +1,341 @@
+//! Link graph of a vault: outgoing links of each note resolved to notes, and the reverse.
+//!
+//! Resolution follows Obsidian. A link names a path relative to the vault root, relative to
+//! the linking note's folder, or just enough of the end of a path to identify a note
+//! (`[[Rent]]` for `finance/Rent.md`). Matching ignores case and a missing note extension
+//! (`[[Meeting]]` for `Meeting.org`; `.md` is tried first). When several notes match, the
+//! one in the linking note's folder wins, then the one with the shortest path. Markdown
+//! links try the linking note's folder first, wikilinks the root.
+//!
+//! Links to attachments that are not notes (`![[diagram.png]]`) are left out of the graph
+//! rather than reported as unresolved.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::notes::{LinkKind, Note, NoteLink};
+
+/// A link from one note to another. Paths are relative to the vault root, with `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LinkEdge {
+    pub source: String,
+    pub target: String,
+    pub link: NoteLink,
+}
+
+/// A link that names no note in the vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnresolvedLink {
+    pub source: String,
+    pub link: NoteLink,
+}
+
+/// Resolved links between the notes of a vault.
+#[derive(Debug, Clone, Default)]
+pub struct LinkGraph {
+    /// All notes, sorted.
+    notes: Vec<String>,
+    edges: Vec<LinkEdge>,
+    /// Edge indices by source and by target note.
+    outgoing: BTreeMap<String, Vec<usize>>,
+    incoming: BTreeMap<String, Vec<usize>>,
+    unresolved: Vec<UnresolvedLink>,
+}
+
+impl LinkGraph {
+    /// Resolve the links of `notes`, which live under `root`.
+    pub fn build(root: &Path, notes: &[Note]) -> Self {
+        let paths: Vec<String> = notes.iter().map(|n| relative_key(root, &n.path)).collect();
+        let resolver = Resolver::new(&paths);
+        let mut graph = LinkGraph::default();
+        for (note, source) in notes.iter().zip(&paths) {
+            for link in &note.links {
+                match resolver.resolve(source, link) {
+                    Some(target) => {
+                        let i = graph.edges.len();
+                        graph.outgoing.entry(source.clone()).or_default().push(i);
+                        graph.incoming.entry(target.clone()).or_default().push(i);
+                        graph.edges.push(LinkEdge {
+                            source: source.clone(),
+                            target,
+                            link: link.clone(),
+                        });
+                    }
+                    None if is_attachment(&link.target) => {}
+                    None => graph.unresolved.push(UnresolvedLink {
+                        source: source.clone(),
+                        link: link.clone(),
+                    }),
+                }
+            }
+        }
+        graph.notes = paths;
+        graph.notes.sort();
+        graph
+    }
+
+    /// Links from `note` (a vault-relative path) to other notes, in order of appearance.
+    pub fn outlinks(&self, note: &str) -> Vec<&LinkEdge> {
+        self.edges_at(&self.outgoing, note)
+    }
+
+    /// Links to `note` from notes (including itself), by source note.
+    pub fn backlinks(&self, note: &str) -> Vec<&LinkEdge> {
+        let mut edges = self.edges_at(&self.incoming, note);
+        edges.sort_by(|a, b| a.source.cmp(&b.source));
+        edges
+    }
+
+    /// Notes that neither link to nor are linked from another note, sorted.
+    pub fn orphans(&self) -> Vec<&str> {
+        let connected = |map: &BTreeMap<String, Vec<usize>>, note: &str| {
+            map.get(note).is_some_and(|ids| {
+                ids.iter()
+                    .any(|&i| self.edges[i].source != self.edges[i].target)
+            })
+        };
+        self.notes
+            .iter()
+            .map(String::as_str)
+            .filter(|n| !connected(&self.outgoing, n) && !connected(&self.incoming, n))
+            .collect()
+    }
+
+    /// Links that name no note, by source note and position.
+    pub fn unresolved(&self) -> &[UnresolvedLink] {
+        &self.unresolved
+    }
+
+    fn edges_at(&self, map: &BTreeMap<String, Vec<usize>>, note: &str) -> Vec<&LinkEdge> {
+        map.get(note)
+            .map(|ids| ids.iter().map(|&i| &self.edges[i]).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Lookup of notes by lowercased path and by lowercased file name.
+struct Resolver<'a> {
+    by_path: HashMap<String, &'a str>,
+    by_name: HashMap<String, Vec<&'a str>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(paths: &'a [String]) -> Self {
+        let mut by_path = HashMap::new();
+        let mut by_name: HashMap<String, Vec<&str>> = HashMap::new();
+        for p in paths {
+            let lower = p.to_lowercase();
+            let name = lower.rsplit('/').next().unwrap_or(&lower).to_string();
+            by_name.entry(name).or_default().push(p.as_str());
+            by_path.insert(lower, p.as_str());
+        }
+        Self { by_path, by_name }
+    }
+
+    fn resolve(&self, source: &str, link: &NoteLink) -> Option<String> {
+        let target = link.target.replace('\\', "/").to_lowercase();
+        let mut candidates = vec![target.clone()];
+        let has_note_extension = target
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| NOTE_EXTENSIONS.contains(&ext));
+        if !has_note_extension {
+            candidates.extend(
+                NOTE_EXTENSIONS
+                    .iter()
+                    .map(|ext| format!("{}.{}", target, ext)),
+            );
+        }
+        let folder = source.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let folder_lower = folder.to_lowercase();
+
+        let from_root = || {
+            candidates.iter().find_map(|c| {
+                let key = normalize(c.trim_start_matches('/'))?;
+                self.by_path.get(&key).copied()
+            })
+        };
+        let from_folder = || {
+            candidates.iter().find_map(|c| {
+                let key = normalize(&format!("{}/{}", folder_lower, c))?;
+                self.by_path.get(&key).copied()
+            })
+        };
+        let found = match link.kind {
+            LinkKind::Wiki => from_root().or_else(from_folder),
+            LinkKind::Markdown | LinkKind::Org => from_folder().or_else(from_root),
+        };
+        if let Some(found) = found {
+            return Some(found.to_string());
+        }
+
+        // Shortest path ending in the target: same folder first, then fewest components.
+        candidates.iter().find_map(|c| {
+            let c = c.trim_start_matches("./").trim_start_matches('/');
+            let name = c.rsplit('/').next().unwrap_or(c);
+            let suffix = format!("/{}", c);
+            self.by_name
+                .get(name)?
+                .iter()
+                .filter(|p| {
+                    let lower = p.to_lowercase();
+                    lower == c || lower.ends_with(&suffix)
+                })
+                .min_by_key(|p| {
+                    let dir = p.rsplit_once('/').map_or("", |(dir, _)| dir);
+                    (dir != folder, p.matches('/').count(), p.to_string())
+                })
+                .map(|p| p.to_string())
+        })
+    }
+}
+
+/// `root`-relative path of `path` with `/` separators.
+fn relative_key(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Resolve `.` and `..` in a `/`-separated path; `None` if it climbs out of the root.
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Extensions of the built-in note formats, in the order links without one try them.
+const NOTE_EXTENSIONS: &[&str] = &["md", "markdown", "mdx", "txt", "org"];
+
+/// Whether `target` names a non-note file such as an image: it has a short alphanumeric
+/// extension that no built-in note format reads. Dotted names like `Meeting 2024.01.05`
+/// are not attachments.
+fn is_attachment(target: &str) -> bool {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => {
+            !stem.is_empty()
+                && !NOTE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e))
+                && (1..=5).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                && ext.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::notes::parse_links;
+
+    fn note(root: &Path, path: &str, body: &str) -> Note {
+        Note {
+            path: root.join(path),
+            raw: body.to_string(),
+            frontmatter: None,
+            body: body.to_string(),
+            links: parse_links(body, 1),
+            locations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn resolves_obsidian_links_into_a_graph() {
+        let root = PathBuf::from("/vault");
+        let notes = vec![
+            note(
+                &root,
+                "Home.md",
+                "[[Rent]], [[projects/Noema|the app]], [[Missing]]",
+            ),
+            note(
+                &root,
+                "finance/Rent.md",
+                "Back to [home](../Home.md). ![[receipt.png]]",
+            ),
+            note(&root, "archive/finance/Rent.md", "Old rent notes."),
+            note(
+                &root,
+                "projects/Noema.md",
+                "See [[rent]] and [[Noema#Roadmap]].",
+            ),
+            note(&root, "projects/Ideas.md", "Nothing links here."),
+            note(&root, "archive/Rent 2019.md", "[[finance/rent]]"),
+        ];
+        let graph = LinkGraph::build(&root, &notes);
+
+        let targets = |note: &str| -> Vec<String> {
+            graph
+                .outlinks(note)
+                .iter()
+                .map(|e| e.target.clone())
+                .collect()
+        };
+        assert_eq!(
+            targets("Home.md"),
+            vec!["finance/Rent.md", "projects/Noema.md"]
+        );
+        assert_eq!(targets("finance/Rent.md"), vec!["Home.md"]);
+        assert_eq!(
+            targets("projects/Noema.md"),
+            vec!["finance/Rent.md", "projects/Noema.md"]
+        );
+        assert_eq!(targets("archive/Rent 2019.md"), vec!["finance/Rent.md"]);
+
+        let sources: Vec<&str> = graph
+            .backlinks("finance/Rent.md")
+            .iter()
+            .map(|e| e.source.as_str())
+            .collect();
+        assert_eq!(
+            sources,
+            vec!["Home.md", "archive/Rent 2019.md", "projects/Noema.md"]
+        );
+        assert_eq!(
+            graph.orphans(),
+            vec!["archive/finance/Rent.md", "projects/Ideas.md"]
+        );
+        assert_eq!(graph.unresolved().len(), 1);
+        assert_eq!(graph.unresolved()[0].link.target, "Missing");
+    }
+
+    #[test]
+    fn resolves_links_to_other_note_formats() {
+        let root = PathBuf::from("/vault");
+        let notes = vec![
+            note(
+                &root,
+                "Home.md",
+                "[[Meeting]], [[Draft]] and [Plan](journal/plan)",
+            ),
+            note(&root, "work/Meeting.org", "Agenda."),
+            note(&root, "journal/Draft.mdx", "Draft."),
+            note(&root, "journal/plan.txt", "Plan."),
+        ];
+        let graph = LinkGraph::build(&root, &notes);
+        let targets: Vec<&str> = graph
+            .outlinks("Home.md")
+            .iter()
+            .map(|e| e.target.as_str())
+            .collect();
+        assert_eq!(
+            targets,
+            vec!["work/Meeting.org", "journal/Draft.mdx", "journal/plan.txt"]
+        );
+        assert!(graph.unresolved().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+//! Built-in embedder that needs no model server.
+//!
+//! [HashEmbedder] maps text to a fixed-size vector by feature hashing: lowercased words and
+//! the character trigrams of each word are hashed into [LOCAL_EMBED_DIM] buckets with a
+//! hash-derived sign, weighted by sublinear term frequency, then normalized. Trigrams make
+//! inflections and typos land near each other ("invoice" / "invoices"). It captures word
+//! overlap, not meaning, so it is a fallback for when no embedding server is running and a
+//! deterministic embedder for tests, not a replacement for a real model.
+//!
+//! Selected with `embed_backend = "local"`; indexes built with it record
+//! [LOCAL_EMBED_MODEL] as their embedding model.
+
+use std::collections::HashMap;
+
+use crate::provider::{Embedder, ModelError};
+
+/// Name recorded in index settings. Bump the version when the features change, so that
+/// indexes built with the old features are reported as mismatched.
+pub const LOCAL_EMBED_MODEL: &str = "noema-hash-v1";
+/// Length of the embeddings.
+pub const LOCAL_EMBED_DIM: usize = 384;
+
+/// Weight of a whole word relative to one of its trigrams.
+const WORD_WEIGHT: f32 = 1.0;
+const TRIGRAM_WEIGHT: f32 = 0.5;
+
+/// Deterministic hashed bag-of-words-and-trigrams embedder.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashEmbedder;
+
+impl HashEmbedder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Embedding of `text`, unit length unless `text` has no words (then all zeros).
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut counts: HashMap<String, (f32, f32)> = HashMap::new();
+        for word in words(text) {
+            counts
+                .entry(format!("w:{}", word))
+                .or_insert((WORD_WEIGHT, 0.0))
+                .1 += 1.0;
+            let padded: Vec<char> = format!("#{}#", word).chars().collect();
+            for gram in padded.windows(3) {
+                let gram: String = gram.iter().collect();
+                counts
+                    .entry(format!("t:{}", gram))
+                    .or_insert((TRIGRAM_WEIGHT, 0.0))
+                    .1 += 1.0;
+            }
+        }
+
+        let mut v = vec![0.0f32; LOCAL_EMBED_DIM];
+        for (feature, (weight, count)) in counts {
+            let h = fnv1a(feature.as_bytes());
+            let bucket = (h % LOCAL_EMBED_DIM as u64) as usize;
+            let sign = if (h >> 63) == 0 { 1.0 } else { -1.0 };
+            v[bucket] += sign * weight * (1.0 + count.ln());
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            v.iter_mut().for_each(|x| *x /= norm);
+        }
+        v
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn model_name(&self) -> &str {
+        LOCAL_EMBED_MODEL
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        Ok(self.embed_text(text))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        Ok(texts.iter().map(|t| self.embed_text(t)).collect())
+    }
+}
+
+/// Lowercased runs of letters and digits.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// 64-bit FNV-1a: stable across platforms and releases, unlike the std hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::scan_notes;
+    use crate::persisted_index::{build_persisted_index, IndexSettings};
+    use crate::search::SearchOptions;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn embeds_deterministically_by_word_overlap() {
+        let e = HashEmbedder::new();
+        let a = e.embed_text("Paid the plumber's invoice for the kitchen sink");
+        assert_eq!(a.len(), LOCAL_EMBED_DIM);
+        assert_eq!(
+            a,
+            e.embed_text("Paid the plumber's invoice for the kitchen sink")
+        );
+        assert!((cosine(&a, &a) - 1.0).abs() < 1e-5);
+
+        let near = e.embed_text("kitchen sink invoices");
+        let far = e.embed_text("Notes on Byzantine church architecture");
+        assert!(cosine(&a, &near) > cosine(&a, &far) + 0.2);
+        assert!(e.embed_text("  -- ").iter().all(|x| *x == 0.0));
+    }
+
+    #[tokio::test]
+    async fn indexes_and_searches_without_a_server() {
+        let dir = std::env::temp_dir().join(format!("noema-local-embed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("plumber.md"),
+            "# Plumber\n\nThe kitchen sink leaked again; the plumber charged 180 euros.\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("trip.md"),
+            "# Lisbon trip\n\nTrams, pastéis de nata and the Alfama at night.\n",
+        )
+        .unwrap();
+
+        let settings = IndexSettings {
+            notes_root: dir.to_string_lossy().into_owned(),
+            max_chars: 512,
+            ollama_url: String::new(),
+            embed_model: LOCAL_EMBED_MODEL.to_string(),
+            embed_backend: crate::provider::ModelBackend::Local,
+            ann: None,
+            quantization: Default::default(),
+            embed_dim: None,
+            embed_digest: None,
+        };
+        let notes = scan_notes(&dir).unwrap();
+        let idx = build_persisted_index(notes, &HashEmbedder, settings)
+            .await
+            .unwrap();
+        assert_eq!(idx.settings.embed_dim, Some(LOCAL_EMBED_DIM));
+
+        let question = "how much did the plumber cost";
+        let query = HashEmbedder.embed_text(question);
+        let hits = idx.search(question, &query, 1, &SearchOptions::new());
+        assert!(hits[0].0.note_path.ends_with("plumber.md"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
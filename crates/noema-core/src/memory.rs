@@ -588,6 +588,8 @@ mod tests {
                 extra: Default::default(),
             }),
             body: body.to_string(),
+            links: Vec::new(),
+            locations: Vec::new(),
         }
     }
 
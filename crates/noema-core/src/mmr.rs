@@ -0,0 +1,62 @@
+//! Maximal marginal relevance (MMR): reorders search candidates so each pick is relevant
+//! to the query but not redundant with the picks before it. Useful because overlapping
+//! chunks of the same section tend to crowd the top of a plain similarity ranking.
+
+/// Candidate pool fetched per requested result before MMR selection.
+pub const MMR_POOL_FACTOR: usize = 4;
+
+/// Select up to `k` of `candidates` (`(id, relevance)` pairs) by MMR. Each step picks the
+/// candidate maximizing `lambda * relevance - (1 - lambda) * max similarity to the picks so
+/// far`, where `similarity(a, b)` compares two candidates. `lambda = 1` keeps the
+/// relevance order; `lambda = 0` maximizes diversity. Returned scores are the relevance.
+pub fn mmr_select(
+    candidates: &[(usize, f32)],
+    k: usize,
+    lambda: f32,
+    similarity: impl Fn(usize, usize) -> f32,
+) -> Vec<(usize, f32)> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let mut remaining: Vec<(usize, f32)> = candidates.to_vec();
+    // Highest similarity of each remaining candidate to anything already selected.
+    let mut redundancy: Vec<f32> = vec![f32::NEG_INFINITY; remaining.len()];
+    let mut selected: Vec<(usize, f32)> = Vec::with_capacity(k.min(remaining.len()));
+
+    while selected.len() < k && !remaining.is_empty() {
+        let mmr = |i: usize| {
+            let penalty = if selected.is_empty() {
+                0.0
+            } else {
+                redundancy[i]
+            };
+            lambda * remaining[i].1 - (1.0 - lambda) * penalty
+        };
+        let best = (0..remaining.len())
+            .max_by(|&a, &b| mmr(a).total_cmp(&mmr(b)).then(b.cmp(&a)))
+            .expect("remaining is not empty");
+        let pick = remaining.swap_remove(best);
+        redundancy.swap_remove(best);
+        for (i, (id, _)) in remaining.iter().enumerate() {
+            redundancy[i] = redundancy[i].max(similarity(pick.0, *id));
+        }
+        selected.push(pick);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_duplicates_are_pushed_down() {
+        // 0 and 1 are near-identical; 2 is less relevant but different.
+        let candidates = [(0, 0.9), (1, 0.89), (2, 0.7)];
+        let sim = |a: usize, b: usize| match (a.min(b), a.max(b)) {
+            (0, 1) => 0.99,
+            _ => 0.1,
+        };
+        let ids = |r: Vec<(usize, f32)>| r.into_iter().map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(ids(mmr_select(&candidates, 2, 0.5, sim)), vec![0, 2]);
+        assert_eq!(ids(mmr_select(&candidates, 2, 1.0, sim)), vec![0, 1]);
+    }
+}
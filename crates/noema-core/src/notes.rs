@@ -1,13 +1,29 @@
-//! Discovering and parsing markdown notes from a user-chosen directory.
+//! Discovering and parsing notes from a user-chosen directory.
 //!
 //! The notes root is chosen by the user; we only read and index it.
+//!
+//! Which files are notes, and how they are parsed, is decided by a [FormatRegistry]: one
+//! [NoteFormat] per syntax, selected by file extension. Built in are Markdown (`md`,
+//! `markdown`), MDX (`mdx`), plain text (`txt`) and Org (`org`). Documents such as PDFs and
+//! saved web pages are read through a [DocumentExtractor] instead (see [crate::documents]);
+//! their notes hold the extracted text.
+//!
+//! Outgoing links are parsed along with the content: `[[wikilinks]]` (with `|alias`,
+//! `#heading` and the `![[embed]]` form) and relative markdown links. They are resolved to
+//! notes in [crate::links].
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
+use crate::documents::{
+    extraction_key, DocumentExtractor, ExtractError, ExtractedDocument, ExtractionCache,
+    HtmlExtractor, PdfExtractor,
+};
+
 /// Parsed YAML frontmatter for a note. We keep a few common fields (title, date, tags, type)
 /// and preserve any extra keys as a raw map for future use.
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
@@ -26,21 +42,516 @@ pub struct NoteFrontmatter {
 #[derive(Debug, Clone)]
 pub struct Note {
     pub path: PathBuf,
-    /// Raw file content.
+    /// Raw file content; for documents, the extracted text.
     pub raw: String,
     /// Parsed YAML frontmatter, if present at the top of the file.
     pub frontmatter: Option<NoteFrontmatter>,
     /// Content without YAML frontmatter (the main markdown body).
     pub body: String,
+    /// Outgoing links in the body, in order.
+    pub links: Vec<NoteLink>,
+    /// Page or heading starts in `raw`, for documents. Empty for text notes, whose chunks
+    /// are located by line.
+    pub locations: Vec<LocationMark>,
+}
+
+impl Note {
+    /// Note for the document at `path` with extracted content `doc`.
+    pub fn from_document(path: &Path, doc: ExtractedDocument) -> Self {
+        let frontmatter = doc.title.map(|title| NoteFrontmatter {
+            title: Some(title),
+            ..Default::default()
+        });
+        Note {
+            path: path.to_path_buf(),
+            body: doc.text.clone(),
+            raw: doc.text,
+            frontmatter,
+            links: Vec::new(),
+            locations: doc.locations,
+        }
+    }
+}
+
+/// Where in a document a piece of text is, in the document's own terms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceLocation {
+    /// 1-based page number.
+    Page(u32),
+    /// Text of the nearest heading above.
+    Heading(String),
+}
+
+impl SourceLocation {
+    /// E.g. `page 4 of insurance.pdf`.
+    pub fn describe(&self, file_name: &str) -> String {
+        format!("{} of {}", self, file_name)
+    }
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceLocation::Page(n) => write!(f, "page {}", n),
+            SourceLocation::Heading(h) => write!(f, "section \"{}\"", h),
+        }
+    }
+}
+
+/// `location` applies to the text from `start_byte` up to the next mark.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocationMark {
+    pub start_byte: usize,
+    pub location: SourceLocation,
+}
+
+/// The location in effect at byte `at`, from `marks` in order.
+pub fn location_at(marks: &[LocationMark], at: usize) -> Option<&SourceLocation> {
+    let i = marks.partition_point(|m| m.start_byte <= at);
+    marks[..i].last().map(|m| &m.location)
+}
+
+/// Syntax of a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[target]]`
+    Wiki,
+    /// `[text](target)`
+    Markdown,
+    /// Org `[[file:target][text]]`
+    Org,
+}
+
+/// An outgoing link as written in a note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteLink {
+    /// Link target without heading or alias, e.g. `Foo`, `projects/Foo.md` or `../a.md`.
+    pub target: String,
+    /// Heading or block reference after `#`, if any.
+    pub heading: Option<String>,
+    /// Display text: the `|alias` of a wikilink or the text of a markdown link.
+    pub alias: Option<String>,
+    /// `![[...]]` or `![...](...)`: the target is embedded rather than linked.
+    pub embed: bool,
+    pub kind: LinkKind,
+    /// 1-based line in the note file.
+    pub line: usize,
+}
+
+/// A note syntax: which files it covers and how their properties, body and links are read.
+pub trait NoteFormat: Send + Sync {
+    /// Short name, e.g. `markdown`.
+    fn name(&self) -> &'static str;
+
+    /// Lowercase file extensions without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Split `raw` into properties and body. The body must be a suffix of `raw` (leading
+    /// properties removed, nothing else), so that link lines and chunk spans still point
+    /// into the file.
+    fn split(&self, raw: &str) -> (Option<NoteFrontmatter>, String);
+
+    /// Outgoing links in `body`, whose first line is line `first_line` of the file.
+    fn links(&self, body: &str, first_line: usize) -> Vec<NoteLink> {
+        parse_links(body, first_line)
+    }
+
+    /// Parse the file at `path` with content `raw`.
+    fn parse(&self, path: &Path, raw: String) -> Note {
+        let (frontmatter, body) = self.split(&raw);
+        debug_assert!(raw.ends_with(&body), "{} body is not a suffix", self.name());
+        // The body is a suffix of the raw text; count the lines before it.
+        let first_line = raw[..raw.len() - body.len()].matches('\n').count() + 1;
+        let links = self.links(&body, first_line);
+        Note {
+            path: path.to_path_buf(),
+            raw,
+            frontmatter,
+            body,
+            links,
+            locations: Vec::new(),
+        }
+    }
+}
+
+/// Markdown with optional YAML frontmatter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownFormat;
+
+impl NoteFormat for MarkdownFormat {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["md", "markdown"]
+    }
+
+    fn split(&self, raw: &str) -> (Option<NoteFrontmatter>, String) {
+        parse_frontmatter(raw)
+    }
+}
+
+/// MDX: Markdown whose leading `import` / `export` statements are not part of the text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MdxFormat;
+
+impl NoteFormat for MdxFormat {
+    fn name(&self) -> &'static str {
+        "mdx"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["mdx"]
+    }
+
+    fn split(&self, raw: &str) -> (Option<NoteFrontmatter>, String) {
+        let (frontmatter, body) = parse_frontmatter(raw);
+        let mut rest = body.as_str();
+        while let Some(line) = rest.lines().next() {
+            let t = line.trim_start();
+            if !(t.is_empty() || t.starts_with("import ") || t.starts_with("export ")) {
+                break;
+            }
+            rest = rest[line.len()..].trim_start_matches(['\r', '\n']);
+        }
+        (frontmatter, rest.to_string())
+    }
+}
+
+/// Plain text: the whole file is the body.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextFormat;
+
+impl NoteFormat for TextFormat {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["txt"]
+    }
+
+    fn split(&self, raw: &str) -> (Option<NoteFrontmatter>, String) {
+        (None, raw.to_string())
+    }
+}
+
+/// Org-mode. The header before the first outline heading supplies the properties:
+/// `#+TITLE`, `#+DATE` and `#+FILETAGS` keywords, and a file-level `:PROPERTIES:` drawer
+/// whose `:TITLE:`, `:DATE:`, `:TAGS:` and `:TYPE:` fill the same fields and whose other
+/// keys go to [NoteFrontmatter::extra]. Links are `[[file:target::heading][text]]`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrgFormat;
+
+impl NoteFormat for OrgFormat {
+    fn name(&self) -> &'static str {
+        "org"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["org"]
+    }
+
+    fn split(&self, raw: &str) -> (Option<NoteFrontmatter>, String) {
+        let mut fm = NoteFrontmatter::default();
+        let mut found = false;
+        let mut in_drawer = false;
+        let mut rest = raw;
+        while let Some(line) = rest.lines().next() {
+            let t = line.trim();
+            if in_drawer {
+                if t.eq_ignore_ascii_case(":END:") {
+                    in_drawer = false;
+                } else if let Some((key, value)) = org_property(t) {
+                    set_org_property(&mut fm, &key, value);
+                    found = true;
+                }
+            } else if t.eq_ignore_ascii_case(":PROPERTIES:") {
+                in_drawer = true;
+            } else if let Some((key, value)) = org_keyword(t) {
+                set_org_property(&mut fm, &key, value);
+                found = true;
+            } else if !(t.is_empty() || t == "#" || t.starts_with("# ")) {
+                break;
+            }
+            rest = rest[line.len()..].trim_start_matches(['\r', '\n']);
+        }
+        (found.then_some(fm), rest.to_string())
+    }
+
+    fn links(&self, body: &str, first_line: usize) -> Vec<NoteLink> {
+        parse_org_links(body, first_line)
+    }
+}
+
+/// `#+KEY: value` as lowercase key and value.
+fn org_keyword(line: &str) -> Option<(String, &str)> {
+    let (key, value) = line.strip_prefix("#+")?.split_once(':')?;
+    (!key.is_empty() && !key.contains(char::is_whitespace))
+        .then(|| (key.to_lowercase(), value.trim()))
+}
+
+/// `:KEY: value` in a property drawer as lowercase key and value.
+fn org_property(line: &str) -> Option<(String, &str)> {
+    let (key, value) = line.strip_prefix(':')?.split_once(':')?;
+    (!key.is_empty() && !key.contains(char::is_whitespace))
+        .then(|| (key.to_lowercase(), value.trim()))
+}
+
+fn set_org_property(fm: &mut NoteFrontmatter, key: &str, value: &str) {
+    match key {
+        "title" => fm.title = Some(value.to_string()),
+        // Org timestamps: `<2024-03-05 Tue>` or `[2024-03-05 Tue 10:00]`.
+        "date" => {
+            let date = value.trim_matches(['<', '>', '[', ']']);
+            fm.date = Some(date.split_whitespace().next().unwrap_or(date).to_string());
+        }
+        // `:work:finance:` for FILETAGS, words or commas for a TAGS property.
+        "filetags" | "tags" => fm.tags.extend(
+            value
+                .split([':', ',', ' '])
+                .filter(|t| !t.is_empty())
+                .map(str::to_string),
+        ),
+        "type" => fm.kind = Some(value.to_string()),
+        _ => {
+            fm.extra.insert(
+                key.to_string(),
+                serde_yaml::Value::String(value.to_string()),
+            );
+        }
+    }
+}
+
+/// Org links to local files: `[[file:path]]`, `[[file:path::heading][text]]`, or a bare
+/// relative path. Other link types (`id:`, `https:`, internal `*Heading` targets) are skipped.
+fn parse_org_links(text: &str, first_line: usize) -> Vec<NoteLink> {
+    let mut links = Vec::new();
+    let mut in_block = false;
+    for (i, line) in text.lines().enumerate() {
+        let upper = line.trim_start().to_ascii_uppercase();
+        if upper.starts_with("#+BEGIN_") {
+            in_block = true;
+        } else if upper.starts_with("#+END_") {
+            in_block = false;
+        }
+        if in_block {
+            continue;
+        }
+        let mut from = 0;
+        while let Some(open) = line[from..].find("[[").map(|i| from + i) {
+            let Some(close) = line[open + 2..].find("]]").map(|i| open + 2 + i) else {
+                break;
+            };
+            from = close + 2;
+            let inner = &line[open + 2..close];
+            let (dest, text) = match inner.split_once("][") {
+                Some((d, t)) => (d, Some(t.trim().to_string()).filter(|t| !t.is_empty())),
+                None => (inner, None),
+            };
+            let dest = match dest.strip_prefix("file:") {
+                Some(path) => path,
+                None if dest.starts_with("./") || dest.starts_with("../") => dest,
+                None => continue,
+            };
+            let (path, heading) = match dest.split_once("::") {
+                Some((p, h)) => (p, Some(h.trim_start_matches('*').trim().to_string())),
+                None => (dest, None),
+            };
+            if path.trim().is_empty() {
+                continue;
+            }
+            links.push(NoteLink {
+                target: path.trim().to_string(),
+                heading: heading.filter(|h| !h.is_empty()),
+                alias: text,
+                embed: false,
+                kind: LinkKind::Org,
+                line: first_line + i,
+            });
+        }
+    }
+    links
 }
 
-/// Scans `root` for all `.md` files and returns their path and content.
-/// Does not follow symlinks into directories (walkdir default).
+/// The note formats and document extractors to read, by file extension.
+#[derive(Clone)]
+pub struct FormatRegistry {
+    formats: Vec<Arc<dyn NoteFormat>>,
+    extractors: Vec<Arc<dyn DocumentExtractor>>,
+    /// Extensions to read; `None` reads every extension a format covers.
+    enabled: Option<BTreeSet<String>>,
+    cache: Option<ExtractionCache>,
+}
+
+impl Default for FormatRegistry {
+    /// All built-in formats and extractors, all extensions enabled, no extraction cache.
+    fn default() -> Self {
+        Self::empty()
+            .with_format(MarkdownFormat)
+            .with_format(MdxFormat)
+            .with_format(TextFormat)
+            .with_format(OrgFormat)
+            .with_extractor(PdfExtractor)
+            .with_extractor(HtmlExtractor)
+    }
+}
+
+impl std::fmt::Debug for FormatRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.formats.iter().map(|fmt| fmt.name()).collect();
+        let extractors: Vec<&str> = self.extractors.iter().map(|e| e.name()).collect();
+        f.debug_struct("FormatRegistry")
+            .field("formats", &names)
+            .field("extractors", &extractors)
+            .field("enabled", &self.enabled)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl FormatRegistry {
+    /// A registry with no formats.
+    pub fn empty() -> Self {
+        Self {
+            formats: Vec::new(),
+            extractors: Vec::new(),
+            enabled: None,
+            cache: None,
+        }
+    }
+
+    /// Add `format`. For an extension claimed by several formats, the last added wins.
+    pub fn with_format(mut self, format: impl NoteFormat + 'static) -> Self {
+        self.formats.push(Arc::new(format));
+        self
+    }
+
+    /// Add a document extractor. Text formats win over extractors for the same extension.
+    pub fn with_extractor(mut self, extractor: impl DocumentExtractor + 'static) -> Self {
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    /// Keep extraction results in `cache`.
+    pub fn with_extraction_cache(mut self, cache: ExtractionCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Only read files with these extensions (case-insensitive, leading dot optional).
+    pub fn with_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.enabled = Some(
+            extensions
+                .into_iter()
+                .map(|e| e.as_ref().trim().trim_start_matches('.').to_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
+        );
+        self
+    }
+
+    /// Extensions that are read: covered by a format or extractor and enabled. Sorted.
+    pub fn extensions(&self) -> Vec<&'static str> {
+        let all: BTreeSet<&'static str> = self
+            .formats
+            .iter()
+            .flat_map(|f| f.extensions().iter().copied())
+            .chain(
+                self.extractors
+                    .iter()
+                    .flat_map(|e| e.extensions().iter().copied()),
+            )
+            .filter(|e| self.enabled.as_ref().is_none_or(|en| en.contains(*e)))
+            .collect();
+        all.into_iter().collect()
+    }
+
+    /// The format that reads `path`, if its extension is enabled.
+    pub fn format_for(&self, path: &Path) -> Option<&dyn NoteFormat> {
+        let ext = self.enabled_extension(path)?;
+        self.formats
+            .iter()
+            .rev()
+            .find(|f| f.extensions().contains(&ext.as_str()))
+            .map(|f| f.as_ref())
+    }
+
+    /// The extractor that reads `path`, if its extension is enabled.
+    pub fn extractor_for(&self, path: &Path) -> Option<&dyn DocumentExtractor> {
+        let ext = self.enabled_extension(path)?;
+        self.extractors
+            .iter()
+            .rev()
+            .find(|e| e.extensions().contains(&ext.as_str()))
+            .map(|e| e.as_ref())
+    }
+
+    fn enabled_extension(&self, path: &Path) -> Option<String> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match &self.enabled {
+            Some(en) if !en.contains(&ext) => None,
+            _ => Some(ext),
+        }
+    }
+
+    /// Read and parse the note at `path`; `Ok(None)` if no enabled format or extractor
+    /// covers it.
+    pub fn read(&self, path: &Path) -> Result<Option<Note>, ScanError> {
+        if let Some(format) = self.format_for(path) {
+            let raw = std::fs::read_to_string(path)
+                .map_err(|e| ScanError::Read(path.to_path_buf(), e))?;
+            return Ok(Some(format.parse(path, raw)));
+        }
+        let Some(extractor) = self.extractor_for(path) else {
+            return Ok(None);
+        };
+        let bytes = std::fs::read(path).map_err(|e| ScanError::Read(path.to_path_buf(), e))?;
+        let key = extraction_key(extractor.name(), &bytes);
+        if let Some(doc) = self.cache.as_ref().and_then(|c| c.get(&key)) {
+            return Ok(Some(Note::from_document(path, doc)));
+        }
+        let doc = extractor
+            .extract(&bytes)
+            .map_err(|e| ScanError::Extract(path.to_path_buf(), e))?;
+        if let Some(cache) = &self.cache {
+            // Best effort: a failed write only costs extracting the file again next time.
+            let _ = cache.put(&key, &doc);
+        }
+        Ok(Some(Note::from_document(path, doc)))
+    }
+}
+
+/// Result of [scan_notes_with].
+#[derive(Debug, Default)]
+pub struct NoteScan {
+    pub notes: Vec<Note>,
+    /// Files that could not be read or whose text could not be extracted.
+    pub skipped: Vec<(PathBuf, ScanError)>,
+}
+
+/// Scans `root` for notes in any built-in format and returns their path and content.
+/// Does not follow symlinks into directories (walkdir default). Files that cannot be read
+/// are left out; [scan_notes_with] reports them.
 pub fn scan_notes(root: &Path) -> Result<Vec<Note>, ScanError> {
+    Ok(scan_notes_with(root, &FormatRegistry::default())?.notes)
+}
+
+/// Scans `root` for files that a format in `formats` reads. A file that cannot be read
+/// (e.g. a text note that is not UTF-8) or whose text cannot be extracted is skipped and
+/// reported in [NoteScan::skipped] rather than failing the scan.
+pub fn scan_notes_with(root: &Path, formats: &FormatRegistry) -> Result<NoteScan, ScanError> {
     if !root.is_dir() {
         return Err(ScanError::NotADirectory(root.to_path_buf()));
     }
-    let mut notes = Vec::new();
+    let mut scan = NoteScan::default();
     for entry in WalkDir::new(root)
         .follow_links(false)
         .into_iter()
@@ -48,19 +559,18 @@ pub fn scan_notes(root: &Path) -> Result<Vec<Note>, ScanError> {
     {
         let entry = entry.map_err(|e| ScanError::Walk(e.to_string()))?;
         let path = entry.path();
-        if path.extension().map_or(false, |e| e == "md") && path.is_file() {
-            let raw = std::fs::read_to_string(path)
-                .map_err(|e| ScanError::Read(path.to_path_buf(), e))?;
-            let (frontmatter, body) = parse_frontmatter(&raw);
-            notes.push(Note {
-                path: path.to_path_buf(),
-                raw,
-                frontmatter,
-                body,
-            });
+        if path.is_file() {
+            match formats.read(path) {
+                Ok(Some(note)) => scan.notes.push(note),
+                Ok(None) => {}
+                Err(e @ (ScanError::Read(..) | ScanError::Extract(..))) => {
+                    scan.skipped.push((path.to_path_buf(), e))
+                }
+                Err(e) => return Err(e),
+            }
         }
     }
-    Ok(notes)
+    Ok(scan)
 }
 
 fn is_hidden(entry: &walkdir::DirEntry) -> bool {
@@ -101,10 +611,272 @@ fn parse_frontmatter(content: &str) -> (Option<NoteFrontmatter>, String) {
     }
 }
 
+/// Outgoing links in `text`, whose first line is line `first_line` of the file. Links in
+/// fenced code blocks and inline code are ignored, as are external URLs and links to a
+/// heading of the same note.
+pub fn parse_links(text: &str, first_line: usize) -> Vec<NoteLink> {
+    let mut links = Vec::new();
+    let mut fence: Option<&str> = None;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        let line_no = first_line + i;
+        let mut line = strip_inline_code(line);
+        parse_wikilinks(&mut line, line_no, &mut links);
+        parse_markdown_links(&line, line_no, &mut links);
+    }
+    links
+}
+
+/// `line` with the contents of backtick code spans blanked out.
+fn strip_inline_code(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_code = false;
+    for c in line.chars() {
+        if c == '`' {
+            in_code = !in_code;
+            out.push(c);
+        } else if in_code {
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Collect `[[...]]` links and blank them out of `line`, so they are not read again as
+/// markdown links.
+fn parse_wikilinks(line: &mut String, line_no: usize, links: &mut Vec<NoteLink>) {
+    let mut from = 0;
+    while let Some(open) = line[from..].find("[[").map(|i| from + i) {
+        let Some(close) = line[open + 2..].find("]]").map(|i| open + 2 + i) else {
+            break;
+        };
+        let inner = &line[open + 2..close];
+        let embed = line[..open].ends_with('!');
+        let (target, alias) = match inner.split_once('|') {
+            Some((t, a)) => (t, Some(a.trim().to_string()).filter(|a| !a.is_empty())),
+            None => (inner, None),
+        };
+        if let Some((target, heading)) = split_heading(target) {
+            links.push(NoteLink {
+                target,
+                heading,
+                alias,
+                embed,
+                kind: LinkKind::Wiki,
+                line: line_no,
+            });
+        }
+        let start = if embed { open - 1 } else { open };
+        line.replace_range(start..close + 2, &" ".repeat(close + 2 - start));
+        from = close + 2;
+    }
+}
+
+fn parse_markdown_links(line: &str, line_no: usize, links: &mut Vec<NoteLink>) {
+    let mut from = 0;
+    while let Some(mid) = line[from..].find("](").map(|i| from + i) {
+        from = mid + 2;
+        let Some(open) = line[..mid].rfind('[') else {
+            continue;
+        };
+        let rest = &line[mid + 2..];
+        let dest = match rest.strip_prefix('<') {
+            Some(angled) => match angled.find('>') {
+                Some(end) => &angled[..end],
+                None => continue,
+            },
+            None => match rest.find(')') {
+                Some(end) => rest[..end].split_whitespace().next().unwrap_or(""),
+                None => continue,
+            },
+        };
+        if dest.is_empty() || dest.contains("://") || dest.starts_with("mailto:") {
+            continue;
+        }
+        let text = line[open + 1..mid].trim();
+        if let Some((target, heading)) = split_heading(&percent_decode(dest)) {
+            links.push(NoteLink {
+                target,
+                heading,
+                alias: Some(text.to_string()).filter(|t| !t.is_empty()),
+                embed: line[..open].ends_with('!'),
+                kind: LinkKind::Markdown,
+                line: line_no,
+            });
+        }
+    }
+}
+
+/// Split `target#heading`. `None` for a link to a heading of the same note.
+fn split_heading(target: &str) -> Option<(String, Option<String>)> {
+    let (path, heading) = match target.split_once('#') {
+        Some((p, h)) => (p, Some(h.trim().to_string()).filter(|h| !h.is_empty())),
+        None => (target, None),
+    };
+    let path = path.trim();
+    (!path.is_empty()).then(|| (path.to_string(), heading))
+}
+
+/// Decode `%XX` escapes (e.g. `%20` in markdown link targets); malformed escapes are kept.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parses_wikilinks_and_markdown_links() {
+        let text = "See [[Rent|my rent]] and ![[diagram.png]] or [[projects/Noema#Roadmap]].
+                    ```
+[[not a link]]
+```
+                    A [relative](../travel/Lisbon%20trip.md#Day%202), `[[code]]`,                     [web](https://example.com) and [[#Same note]].";
+        let links = parse_links(text, 5);
+        type Summary<'a> = (
+            &'a str,
+            Option<&'a str>,
+            Option<&'a str>,
+            bool,
+            LinkKind,
+            usize,
+        );
+        let summary: Vec<Summary> = links
+            .iter()
+            .map(|l| {
+                (
+                    l.target.as_str(),
+                    l.heading.as_deref(),
+                    l.alias.as_deref(),
+                    l.embed,
+                    l.kind,
+                    l.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Rent", None, Some("my rent"), false, LinkKind::Wiki, 5),
+                ("diagram.png", None, None, true, LinkKind::Wiki, 5),
+                (
+                    "projects/Noema",
+                    Some("Roadmap"),
+                    None,
+                    false,
+                    LinkKind::Wiki,
+                    5
+                ),
+                (
+                    "../travel/Lisbon trip.md",
+                    Some("Day 2"),
+                    Some("relative"),
+                    false,
+                    LinkKind::Markdown,
+                    9
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_org_properties_and_links() {
+        let raw = "#+TITLE: Rent\n#+DATE: <2024-03-05 Tue>\n#+FILETAGS: :finance:home:\n\
+                   :PROPERTIES:\n:ID: 8a1f\n:TYPE: log\n:END:\n\n\
+                   * March\nPaid, see [[file:../bank/March.org::*Transfers][statement]] \
+                   and [[https://example.com][site]].\n";
+        let note = OrgFormat.parse(Path::new("rent.org"), raw.to_string());
+        let fm = note.frontmatter.unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Rent"));
+        assert_eq!(fm.date.as_deref(), Some("2024-03-05"));
+        assert_eq!(fm.tags, vec!["finance", "home"]);
+        assert_eq!(fm.kind.as_deref(), Some("log"));
+        assert_eq!(fm.extra["id"], serde_yaml::Value::String("8a1f".into()));
+        assert!(note.body.starts_with("* March\n"));
+        assert_eq!(note.links.len(), 1);
+        assert_eq!(note.links[0].target, "../bank/March.org");
+        assert_eq!(note.links[0].heading.as_deref(), Some("Transfers"));
+        assert_eq!(note.links[0].line, 10);
+    }
+
+    #[test]
+    fn registry_reads_enabled_extensions() {
+        let dir = std::env::temp_dir().join(format!("noema-formats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, text) in [
+            ("a.md", "# A"),
+            ("b.MDX", "import X from './x'\n\n# B"),
+            ("c.txt", "plain"),
+            ("d.org", "#+TITLE: D"),
+            ("e.png", "binary"),
+        ] {
+            std::fs::write(dir.join(name), text).unwrap();
+        }
+        let names = |formats: &FormatRegistry| -> Vec<String> {
+            let mut notes = scan_notes_with(&dir, formats).unwrap().notes;
+            notes.sort_by(|a, b| a.path.cmp(&b.path));
+            notes
+                .iter()
+                .map(|n| n.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        let all = FormatRegistry::default();
+        assert_eq!(names(&all), vec!["a.md", "b.MDX", "c.txt", "d.org"]);
+        assert_eq!(all.read(&dir.join("b.MDX")).unwrap().unwrap().body, "# B");
+        let some = FormatRegistry::default().with_extensions([".md", "ORG"]);
+        assert_eq!(names(&some), vec!["a.md", "d.org"]);
+        assert_eq!(some.extensions(), vec!["md", "org"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn skips_unreadable_notes() {
+        let dir = std::env::temp_dir().join(format!("noema-unreadable-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ok.md"), "# Fine").unwrap();
+        std::fs::write(dir.join("latin1.txt"), b"caf\xe9 cr\xe8me").unwrap();
+
+        let scan = scan_notes_with(&dir, &FormatRegistry::default()).unwrap();
+        assert_eq!(scan.notes.len(), 1);
+        assert_eq!(scan.skipped.len(), 1);
+        let (path, err) = &scan.skipped[0];
+        assert_eq!(path.file_name().unwrap(), "latin1.txt");
+        assert!(matches!(err, ScanError::Read(..)));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn strip_frontmatter_plain() {
         let s = "Hello world.";
@@ -130,4 +902,6 @@ pub enum ScanError {
     Walk(String),
     #[error("read error for {0}: {1}")]
     Read(PathBuf, std::io::Error),
+    #[error("could not extract text from {0}: {1}")]
+    Extract(PathBuf, ExtractError),
 }
@@ -1,16 +1,50 @@
 //! Ollama client for embeddings and completion. Wraps ollama-rs with a simple API.
 
+use futures_util::StreamExt;
+use ollama_rs::generation::chat::request::ChatMessageRequest;
+use ollama_rs::generation::chat::{ChatMessage as OllamaChatMessage, MessageRole};
 use ollama_rs::generation::completion::request::GenerationRequest;
 use ollama_rs::generation::embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest};
+use ollama_rs::models::{ModelInfo, ModelOptions};
 use ollama_rs::Ollama;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::chat::{ChatMessage, ChatRole};
+use crate::provider::{
+    ChatModel, Embedder, EmbeddingProbe, GenerateOptions, ModelError, TokenStream, EMBED_PROBE_TEXT,
+};
+
 pub const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
 pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
 /// Reasonable default chat/completion model. Can be overridden at call sites.
 pub const DEFAULT_CHAT_MODEL: &str = "llama3.1";
 
-/// Thin wrapper around Ollama for embedding and (future) completion.
+/// What a model can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    Completion,
+    Embedding,
+}
+
+/// A model installed on an Ollama server, from `/api/tags` and `/api/show`. Fields the server
+/// does not report are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelDetails {
+    pub name: String,
+    /// Architecture, e.g. `llama` or `nomic-bert`.
+    pub family: Option<String>,
+    /// Parameter count in Ollama's notation, e.g. `8.0B` or `137M`.
+    pub parameter_size: Option<String>,
+    /// Context window in tokens.
+    pub context_length: Option<u64>,
+    pub kind: ModelKind,
+    /// Size on disk in bytes.
+    pub size_bytes: u64,
+}
+
+/// Thin wrapper around Ollama for embedding and completion.
 #[derive(Debug, Clone)]
 pub struct OllamaClient {
     inner: Ollama,
@@ -71,7 +105,20 @@ impl OllamaClient {
 
     /// Generate a completion from a prompt using the given model.
     pub async fn generate(&self, model: &str, prompt: &str) -> Result<String, OllamaError> {
-        let req = GenerationRequest::new(model.to_string(), prompt.to_string());
+        self.generate_with(model, prompt, &GenerateOptions::default())
+            .await
+    }
+
+    /// Generate a short completion with sampling options, e.g. for scoring or rewriting
+    /// where a deterministic, bounded answer is wanted.
+    pub async fn generate_with(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, OllamaError> {
+        let req = GenerationRequest::new(model.to_string(), prompt.to_string())
+            .options(model_options(options));
         let res = self
             .inner
             .generate(req)
@@ -79,6 +126,250 @@ impl OllamaClient {
             .map_err(OllamaError::Request)?;
         Ok(res.response)
     }
+
+    /// Generate a completion, yielding text fragments as the model produces them. The
+    /// stream ends when the model is done; dropping it cancels the request.
+    pub async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<TokenStream, OllamaError> {
+        let req = GenerationRequest::new(model.to_string(), prompt.to_string())
+            .options(model_options(options));
+        let stream = self
+            .inner
+            .generate_stream(req)
+            .await
+            .map_err(OllamaError::Request)?;
+        let tokens = stream.filter_map(|chunk| async move {
+            match chunk {
+                Ok(responses) => {
+                    let text: String = responses.into_iter().map(|r| r.response).collect();
+                    (!text.is_empty()).then_some(Ok(text))
+                }
+                Err(e) => Some(Err(OllamaError::Request(e).into())),
+            }
+        });
+        Ok(Box::pin(tokens))
+    }
+
+    /// Generate the next assistant message of a conversation via the chat endpoint.
+    pub async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<String, OllamaError> {
+        let messages = messages
+            .iter()
+            .map(|m| {
+                let role = match m.role {
+                    ChatRole::System => MessageRole::System,
+                    ChatRole::User => MessageRole::User,
+                    ChatRole::Assistant => MessageRole::Assistant,
+                };
+                OllamaChatMessage::new(role, m.content.clone())
+            })
+            .collect();
+        let req =
+            ChatMessageRequest::new(model.to_string(), messages).options(model_options(options));
+        let res = self
+            .inner
+            .send_chat_messages(req)
+            .await
+            .map_err(OllamaError::Request)?;
+        Ok(res.message.content)
+    }
+
+    /// All installed models with their capabilities, in the server's order. A model whose
+    /// details cannot be read is still listed, with its kind guessed from the name.
+    pub async fn list_models(&self) -> Result<Vec<ModelDetails>, OllamaError> {
+        let local = self
+            .inner
+            .list_local_models()
+            .await
+            .map_err(OllamaError::Request)?;
+        let shown = local
+            .iter()
+            .map(|m| self.inner.show_model_info(m.name.clone()));
+        let infos = futures_util::future::join_all(shown).await;
+        Ok(local
+            .into_iter()
+            .zip(infos)
+            .map(|(m, info)| model_details(m.name, m.size, info.ok().as_ref()))
+            .collect())
+    }
+
+    /// Check that the embedding model is installed and answers, and measure its output
+    /// dimension.
+    pub async fn probe_embedding(&self) -> Result<EmbeddingProbe, OllamaError> {
+        let (embedding, digest) = futures_util::join!(
+            self.embed(EMBED_PROBE_TEXT),
+            self.model_digest(&self.embed_model)
+        );
+        Ok(EmbeddingProbe {
+            model: self.embed_model.clone(),
+            dimension: embedding?.len(),
+            digest,
+        })
+    }
+
+    /// Digest of the installed model `name`, from `/api/tags` (ollama-rs leaves it out).
+    /// `None` if the server does not list the model or cannot be reached.
+    pub async fn model_digest(&self, name: &str) -> Option<String> {
+        #[derive(Deserialize)]
+        struct Tags {
+            models: Vec<Tag>,
+        }
+        #[derive(Deserialize)]
+        struct Tag {
+            name: String,
+            #[serde(default)]
+            digest: String,
+        }
+        let url = self.inner.url().join("api/tags").ok()?;
+        let tags: Tags = reqwest::get(url.as_str()).await.ok()?.json().await.ok()?;
+        let wanted = canonical_model_name(name);
+        tags.models
+            .into_iter()
+            .find(|m| canonical_model_name(&m.name) == wanted)
+            .map(|m| m.digest)
+            .filter(|d| !d.is_empty())
+    }
+
+    /// Details of one installed model.
+    pub async fn show_model(&self, name: &str) -> Result<ModelDetails, OllamaError> {
+        let info = self
+            .inner
+            .show_model_info(name.to_string())
+            .await
+            .map_err(OllamaError::Request)?;
+        Ok(model_details(name.to_string(), 0, Some(&info)))
+    }
+}
+
+/// `name` with Ollama's implicit `:latest` tag spelled out, so `nomic-embed-text` and
+/// `nomic-embed-text:latest` compare equal.
+pub(crate) fn canonical_model_name(name: &str) -> String {
+    let name = name.trim();
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+/// Read family, size, context length and kind from `/api/show` output. `model_info` keys are
+/// GGUF metadata: `general.architecture`, `general.parameter_count` and
+/// `<architecture>.context_length`.
+fn model_details(name: String, size_bytes: u64, info: Option<&ModelInfo>) -> ModelDetails {
+    let meta = info.map(|i| &i.model_info);
+    let family = meta
+        .and_then(|m| m.get("general.architecture"))
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let parameter_size = meta
+        .and_then(|m| m.get("general.parameter_count"))
+        .and_then(|v| v.as_u64())
+        .map(format_parameter_count);
+    let context_length = family.as_ref().and_then(|f| {
+        meta?
+            .get(&format!("{}.context_length", f))
+            .and_then(|v| v.as_u64())
+    });
+    let capabilities = info.map(|i| i.capabilities.as_slice()).unwrap_or_default();
+    let kind = if capabilities.iter().any(|c| c == "completion") {
+        ModelKind::Completion
+    } else if capabilities.iter().any(|c| c == "embedding") {
+        ModelKind::Embedding
+    } else {
+        // Older servers report no capabilities; embedding models are BERT-style encoders.
+        let lower = name.to_ascii_lowercase();
+        let bert = family.as_deref().is_some_and(|f| f.ends_with("bert"));
+        if bert || lower.contains("embed") {
+            ModelKind::Embedding
+        } else {
+            ModelKind::Completion
+        }
+    };
+    ModelDetails {
+        name,
+        family,
+        parameter_size,
+        context_length,
+        kind,
+        size_bytes,
+    }
+}
+
+/// `8030261248` → `8.0B`, `136727040` → `137M`.
+fn format_parameter_count(n: u64) -> String {
+    let n = n as f64;
+    if n >= 1e9 {
+        format!("{:.1}B", n / 1e9)
+    } else if n >= 1e6 {
+        format!("{:.0}M", n / 1e6)
+    } else {
+        format!("{:.0}K", n / 1e3)
+    }
+}
+
+impl Embedder for OllamaClient {
+    fn model_name(&self) -> &str {
+        &self.embed_model
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        Ok(OllamaClient::embed(self, text).await?)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        Ok(OllamaClient::embed_batch(self, texts).await?)
+    }
+}
+
+impl ChatModel for OllamaClient {
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        Ok(self.generate_with(model, prompt, options).await?)
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<TokenStream, ModelError> {
+        Ok(OllamaClient::generate_stream(self, model, prompt, options).await?)
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        Ok(OllamaClient::chat(self, model, messages, options).await?)
+    }
+}
+
+fn model_options(options: &GenerateOptions) -> ModelOptions {
+    let mut model_options = ModelOptions::default();
+    if let Some(t) = options.temperature {
+        model_options = model_options.temperature(t);
+    }
+    if let Some(n) = options.max_tokens {
+        model_options = model_options.num_predict(n);
+    }
+    if let Some(n) = options.context_length {
+        model_options = model_options.num_ctx(n);
+    }
+    model_options
 }
 
 #[derive(Debug, Error)]
@@ -88,3 +379,34 @@ pub enum OllamaError {
     #[error("Ollama request failed: {0}")]
     Request(#[from] ollama_rs::error::OllamaError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_details_from_show_output() {
+        let info: ModelInfo = serde_json::from_str(
+            r#"{"model_info":{"general.architecture":"llama","general.parameter_count":8030261248,
+                "llama.context_length":131072},"capabilities":["completion","tools"]}"#,
+        )
+        .unwrap();
+        let details = model_details("llama3.1:8b".to_string(), 42, Some(&info));
+        assert_eq!(details.family.as_deref(), Some("llama"));
+        assert_eq!(details.parameter_size.as_deref(), Some("8.0B"));
+        assert_eq!(details.context_length, Some(131072));
+        assert_eq!(details.kind, ModelKind::Completion);
+
+        let old: ModelInfo = serde_json::from_str(
+            r#"{"model_info":{"general.architecture":"nomic-bert","general.parameter_count":136727040}}"#,
+        )
+        .unwrap();
+        let details = model_details("nomic-embed-text".to_string(), 0, Some(&old));
+        assert_eq!(details.parameter_size.as_deref(), Some("137M"));
+        assert_eq!(details.kind, ModelKind::Embedding);
+        assert_eq!(
+            model_details("mxbai-embed-large".to_string(), 0, None).kind,
+            ModelKind::Embedding
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Client for OpenAI-compatible HTTP APIs, as served locally by llama.cpp server, LM Studio,
+//! vLLM and others. Uses `/embeddings` and `/chat/completions` (streamed as server-sent
+//! events); plain prompts are sent as a single user message.
+
+use std::collections::VecDeque;
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use url::Url;
+
+use crate::chat::{ChatMessage, ChatRole};
+use crate::ollama::DEFAULT_EMBED_MODEL;
+use crate::provider::{ChatModel, Embedder, GenerateOptions, ModelError, TokenStream};
+
+/// Default base URL: a llama.cpp server on its default port.
+pub const DEFAULT_OPENAI_BASE_URL: &str = "http://localhost:8080/v1";
+
+/// Client for one OpenAI-compatible server.
+#[derive(Debug, Clone)]
+pub struct OpenAiClient {
+    http: reqwest::Client,
+    /// Base URL including the API prefix, without trailing slash (e.g. `.../v1`).
+    base_url: String,
+    api_key: Option<String>,
+    embed_model: String,
+}
+
+impl OpenAiClient {
+    /// Create from a base URL such as `http://localhost:1234/v1`.
+    pub fn from_url(url: &str) -> Result<Self, OpenAiError> {
+        Url::parse(url)?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url: url.trim_end_matches('/').to_string(),
+            api_key: None,
+            embed_model: DEFAULT_EMBED_MODEL.to_string(),
+        })
+    }
+
+    /// Send `key` as a bearer token. Most local servers do not need one.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into()).filter(|k| !k.is_empty());
+        self
+    }
+
+    pub fn with_embed_model(mut self, model: impl Into<String>) -> Self {
+        self.embed_model = model.into();
+        self
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, OpenAiError> {
+        let mut embeddings = self.embed_batch(&[text.to_string()]).await?;
+        Ok(embeddings.pop().unwrap_or_default())
+    }
+
+    /// Embed multiple strings in one request. Returns one embedding per input.
+    pub async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, OpenAiError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let body = json!({ "model": self.embed_model, "input": texts });
+        let res: EmbeddingsResponse = self.post("embeddings", &body).await?.json().await?;
+        embeddings_in_order(res, texts.len())
+    }
+
+    /// Generate the next assistant message of a conversation.
+    pub async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<String, OpenAiError> {
+        let body = chat_request(model, messages, options, false);
+        let res: ChatResponse = self.post("chat/completions", &body).await?.json().await?;
+        res.choices
+            .into_iter()
+            .next()
+            .map(|c| c.message.content.unwrap_or_default())
+            .ok_or_else(|| OpenAiError::InvalidResponse("no choices".to_string()))
+    }
+
+    /// Like [OpenAiClient::chat], yielding text fragments as they arrive.
+    pub async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<TokenStream, OpenAiError> {
+        let body = chat_request(model, messages, options, true);
+        let bytes = Box::pin(self.post("chat/completions", &body).await?.bytes_stream());
+        let state = (bytes, Vec::<u8>::new(), VecDeque::<String>::new(), false);
+        let tokens = futures_util::stream::unfold(
+            state,
+            |(mut bytes, mut buf, mut pending, mut done)| async move {
+                loop {
+                    if let Some(token) = pending.pop_front() {
+                        return Some((Ok(token), (bytes, buf, pending, done)));
+                    }
+                    if done {
+                        return None;
+                    }
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => {
+                            buf.extend_from_slice(&chunk);
+                            while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+                                let line: Vec<u8> = buf.drain(..=end).collect();
+                                match parse_sse_line(&String::from_utf8_lossy(&line)) {
+                                    SseLine::Token(token) => pending.push_back(token),
+                                    SseLine::Done => done = true,
+                                    SseLine::Other => {}
+                                }
+                            }
+                        }
+                        Some(Err(e)) => {
+                            let err = ModelError::from(OpenAiError::from(e));
+                            return Some((Err(err), (bytes, buf, pending, true)));
+                        }
+                        None => done = true,
+                    }
+                }
+            },
+        );
+        Ok(Box::pin(tokens))
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<reqwest::Response, OpenAiError> {
+        let mut req = self
+            .http
+            .post(format!("{}/{}", self.base_url, path))
+            .json(body);
+        if let Some(key) = &self.api_key {
+            req = req.bearer_auth(key);
+        }
+        let res = req.send().await?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            return Err(OpenAiError::Status {
+                status: status.as_u16(),
+                body,
+            });
+        }
+        Ok(res)
+    }
+}
+
+impl Embedder for OpenAiClient {
+    fn model_name(&self) -> &str {
+        &self.embed_model
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        Ok(OpenAiClient::embed(self, text).await?)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        Ok(OpenAiClient::embed_batch(self, texts).await?)
+    }
+}
+
+impl ChatModel for OpenAiClient {
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        Ok(OpenAiClient::chat(self, model, &[ChatMessage::user(prompt)], options).await?)
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<TokenStream, ModelError> {
+        Ok(self
+            .chat_stream(model, &[ChatMessage::user(prompt)], options)
+            .await?)
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        Ok(OpenAiClient::chat(self, model, messages, options).await?)
+    }
+}
+
+fn chat_request(
+    model: &str,
+    messages: &[ChatMessage],
+    options: &GenerateOptions,
+    stream: bool,
+) -> Value {
+    let messages: Vec<Value> = messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                ChatRole::System => "system",
+                ChatRole::User => "user",
+                ChatRole::Assistant => "assistant",
+            };
+            json!({ "role": role, "content": m.content })
+        })
+        .collect();
+    let mut body = json!({ "model": model, "messages": messages, "stream": stream });
+    if let Some(t) = options.temperature {
+        body["temperature"] = json!(t);
+    }
+    if let Some(n) = options.max_tokens {
+        body["max_tokens"] = json!(n);
+    }
+    body
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// The embeddings of a response sorted by input index; servers may return them out of order.
+fn embeddings_in_order(
+    res: EmbeddingsResponse,
+    expected: usize,
+) -> Result<Vec<Vec<f32>>, OpenAiError> {
+    if res.data.len() != expected {
+        return Err(OpenAiError::InvalidResponse(format!(
+            "expected {} embeddings, got {}",
+            expected,
+            res.data.len()
+        )));
+    }
+    let mut data = res.data;
+    data.sort_by_key(|d| d.index);
+    Ok(data.into_iter().map(|d| d.embedding).collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatChoiceMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoiceMessage {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChunk {
+    choices: Vec<ChatStreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChoice {
+    delta: ChatChoiceMessage,
+}
+
+#[derive(Debug, PartialEq)]
+enum SseLine {
+    Token(String),
+    Done,
+    /// Comments, keep-alives, empty deltas and anything unparseable.
+    Other,
+}
+
+/// Read one line of a streamed chat completion.
+fn parse_sse_line(line: &str) -> SseLine {
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return SseLine::Other;
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return SseLine::Done;
+    }
+    let Ok(chunk) = serde_json::from_str::<ChatStreamChunk>(data) else {
+        return SseLine::Other;
+    };
+    chunk
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|c| c.delta.content)
+        .filter(|t| !t.is_empty())
+        .map_or(SseLine::Other, SseLine::Token)
+}
+
+#[derive(Debug, Error)]
+pub enum OpenAiError {
+    #[error("invalid server URL: {0}")]
+    ParseUrl(#[from] url::ParseError),
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server returned status {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("unexpected server response: {0}")]
+    InvalidResponse(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stream_lines() {
+        let line = r#"data: {"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#;
+        assert_eq!(parse_sse_line(line), SseLine::Token("Hel".to_string()));
+        assert_eq!(parse_sse_line("data: [DONE]\n"), SseLine::Done);
+        assert_eq!(parse_sse_line(": keep-alive"), SseLine::Other);
+        let role_only = r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#;
+        assert_eq!(parse_sse_line(role_only), SseLine::Other);
+    }
+
+    #[test]
+    fn orders_embeddings_by_index() {
+        let res: EmbeddingsResponse = serde_json::from_str(
+            r#"{"data":[{"index":1,"embedding":[0.0,1.0]},{"index":0,"embedding":[1.0,0.0]}]}"#,
+        )
+        .unwrap();
+        let embeddings = embeddings_in_order(res, 2).unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+}
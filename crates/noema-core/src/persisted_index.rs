@@ -1,9 +1,11 @@
 //! Persisted on-disk index format (metadata + vector store).
 //!
-//! This is the format written to `index.json` in the app data directory. Keeping this in
-//! `noema-core` ensures the desktop app uses a stable, versioned representation.
+//! The index is written to `index.bin` in the app data directory using the binary format in
+//! [crate::binary_index]. Older versions wrote `index.json`; that format is still readable
+//! and is migrated on first load. Keeping this in `noema-core` ensures the desktop app uses
+//! a stable, versioned representation.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
@@ -13,10 +15,20 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::app_data::app_data_dir;
-use crate::chunks::chunk_note;
+use crate::binary_index;
+use crate::chunks::{chunk_note, Chunk, ChunkKind};
+use crate::embed_pipeline::{embed_chunks, no_progress, EmbedParams, ProgressFn};
+use crate::expand::SearchQuery;
+use crate::hnsw::HnswParams;
+use crate::hybrid::{hybrid_search_ids, reciprocal_rank_fusion, HybridParams};
+use crate::lexical::Bm25Index;
+use crate::mmr::{mmr_select, MMR_POOL_FACTOR};
 use crate::notes::{Note, ScanError};
-use crate::ollama::{OllamaClient, OllamaError};
-use crate::store::VectorStore;
+use crate::ollama::canonical_model_name;
+use crate::provider::{Embedder, ModelBackend, ModelError};
+use crate::quantize::Quantization;
+use crate::search::{SearchHit, SearchOptions};
+use crate::store::{normalize, StoreError, VectorStore};
 
 /// Bump this when the persisted on-disk schema changes incompatibly.
 pub const INDEX_SCHEMA_VERSION: u32 = 2;
@@ -26,8 +38,73 @@ pub const INDEX_SCHEMA_VERSION: u32 = 2;
 pub struct IndexSettings {
     pub notes_root: String,
     pub max_chars: usize,
+    /// Base URL of the embedding server (named for the original Ollama-only format).
     pub ollama_url: String,
     pub embed_model: String,
+    /// API spoken by the embedding server.
+    #[serde(default)]
+    pub embed_backend: ModelBackend,
+    /// HNSW parameters when the store keeps an approximate search graph; `None` means exact only.
+    #[serde(default)]
+    pub ann: Option<HnswParams>,
+    /// Compression of the embeddings for the exact scan (rescored at full precision).
+    #[serde(default)]
+    pub quantization: Quantization,
+    /// Length of the embeddings. Taken from the store when loading an index written before
+    /// it was recorded.
+    #[serde(default)]
+    pub embed_dim: Option<usize>,
+    /// Digest of the embedding model as reported by the server, if it reports one.
+    #[serde(default)]
+    pub embed_digest: Option<String>,
+}
+
+impl IndexSettings {
+    /// Check that queries embedded with `model`, producing `dim`-long vectors, can be
+    /// searched against this index. `digest` is the model's current digest, if known; a
+    /// model replaced under the same name is also a mismatch.
+    pub fn check_embedder(
+        &self,
+        model: &str,
+        dim: Option<usize>,
+        digest: Option<&str>,
+    ) -> Result<(), EmbeddingMismatch> {
+        let same_name = canonical_model_name(&self.embed_model) == canonical_model_name(model);
+        let same_dim = match (self.embed_dim, dim) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        if !same_name || !same_dim {
+            return Err(EmbeddingMismatch::Model {
+                index_model: self.embed_model.clone(),
+                index_dim: self.embed_dim,
+                query_model: model.to_string(),
+                query_dim: dim,
+            });
+        }
+        if let (Some(was), Some(now)) = (self.embed_digest.as_deref(), digest) {
+            if was != now {
+                return Err(EmbeddingMismatch::Digest {
+                    model: self.embed_model.clone(),
+                    index_digest: was.to_string(),
+                    current_digest: now.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an index built with `other` embeds with the same model as one built with these.
+    pub(crate) fn same_embedder(&self, other: &IndexSettings) -> bool {
+        self.embed_backend == other.embed_backend
+            && other
+                .check_embedder(
+                    &self.embed_model,
+                    self.embed_dim,
+                    self.embed_digest.as_deref(),
+                )
+                .is_ok()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -49,34 +126,331 @@ pub struct PersistedIndex {
     pub store: VectorStore,
     /// Note file states tracked for incremental updates.
     pub note_states: BTreeMap<String, NoteState>,
+    /// BM25 index over the same chunks as `store`, in the same order. Not part of the JSON
+    /// format; rebuilt from chunk text when missing.
+    #[serde(skip)]
+    pub lexical: Bm25Index,
 }
 
 impl PersistedIndex {
-    /// Save to a JSON file.
+    /// Save in the binary format. The file is replaced atomically.
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistedIndexError> {
+        binary_index::write(self, path.as_ref())
+    }
+
+    /// Save in the legacy JSON format.
+    pub fn save_json_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistedIndexError> {
         let file = File::create(path)?;
         let writer = BufWriter::new(file);
         serde_json::to_writer(writer, self).map_err(PersistedIndexError::Serialize)
     }
 
-    /// Load from a JSON file.
+    /// Load from a binary or legacy JSON file, detected by the file header.
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, PersistedIndexError> {
+        let path = path.as_ref();
+        if binary_index::is_binary_index(path)? {
+            return binary_index::read(path);
+        }
         let file = File::open(path)?;
         let reader = BufReader::new(file);
-        let idx = serde_json::from_reader(reader).map_err(PersistedIndexError::Deserialize)?;
+        let mut idx: Self =
+            serde_json::from_reader(reader).map_err(PersistedIndexError::Deserialize)?;
+        idx.ensure_derived();
+        Ok(idx)
+    }
+
+    /// Load the index at `path`. If it does not exist yet but a legacy JSON index does,
+    /// load that one and write it to `path` in the binary format first.
+    pub fn load_or_migrate<P: AsRef<Path>, L: AsRef<Path>>(
+        path: P,
+        legacy_path: L,
+    ) -> Result<Self, PersistedIndexError> {
+        let path = path.as_ref();
+        let legacy_path = legacy_path.as_ref();
+        if path.exists() || !legacy_path.exists() {
+            return Self::load_from_file(path);
+        }
+        let idx = Self::load_from_file(legacy_path)?;
+        idx.save_to_file(path)?;
         Ok(idx)
     }
+
+    /// Add chunks and their embeddings to both the vector store and the lexical index.
+    /// Nothing is added if an embedding's length differs from the index's.
+    pub fn add_chunks(
+        &mut self,
+        chunks: Vec<Chunk>,
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<(), StoreError> {
+        let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+        self.store.add_batch(chunks, embeddings)?;
+        for text in &texts {
+            self.lexical.add(text);
+        }
+        Ok(())
+    }
+
+    /// Title of each indexed note that has one, by note path as stored in its chunks. Read
+    /// from the title chunks, so no note has to be opened.
+    pub fn note_titles(&self) -> HashMap<PathBuf, String> {
+        self.store
+            .chunks()
+            .iter()
+            .filter(|c| c.kind == ChunkKind::Title)
+            .map(|c| (c.note_path.clone(), c.text.clone()))
+            .collect()
+    }
+
+    /// Remove all chunks of a note from both the vector store and the lexical index.
+    /// Returns the number of chunks removed. Does not touch `note_states`.
+    pub fn remove_note(&mut self, note_path: &Path) -> usize {
+        self.retain_notes(|p| p != note_path)
+    }
+
+    /// Keep only the chunks of notes for which `keep` returns true. Returns the number of
+    /// chunks removed. Does not touch `note_states`.
+    pub(crate) fn retain_notes(&mut self, keep: impl Fn(&Path) -> bool) -> usize {
+        let keep = self.store.keep_mask(|c| keep(&c.note_path));
+        self.lexical.retain(&keep);
+        self.store.retain(&keep)
+    }
+
+    /// Search this index with filters, optional lexical fusion and optional MMR
+    /// diversification. See [SearchOptions]. Relative filter paths resolve against the
+    /// index's notes root.
+    pub fn search(
+        &self,
+        query_text: &str,
+        query_embedding: &[f32],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<(Chunk, f32)> {
+        self.search_hits(query_text, query_embedding, k, options)
+            .into_iter()
+            .map(|hit| (hit.chunk, hit.score))
+            .collect()
+    }
+
+    fn search_hits(
+        &self,
+        query_text: &str,
+        query_embedding: &[f32],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<SearchHit> {
+        let n = pool_size(k, options);
+        let hits = self.candidate_ids(query_text, query_embedding, n, options);
+        self.finish_search(hits, k, options)
+    }
+
+    /// Search with several queries, e.g. a question and its expansions from
+    /// [crate::expand::expand_query], and merge the rankings with reciprocal rank fusion.
+    /// Results are ordered by fused rank; each score is the best cosine similarity over the
+    /// queries. With a single query this is [PersistedIndex::search].
+    pub fn search_expanded(
+        &self,
+        queries: &[SearchQuery],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<(Chunk, f32)> {
+        self.search_expanded_hits(queries, k, options)
+            .into_iter()
+            .map(|hit| (hit.chunk, hit.score))
+            .collect()
+    }
+
+    /// Like [PersistedIndex::search_expanded], but also returns each result's fused
+    /// relevance, for cutoffs that should not drop lexical matches.
+    pub fn search_expanded_hits(
+        &self,
+        queries: &[SearchQuery],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<SearchHit> {
+        if let [query] = queries {
+            return self.search_hits(&query.text, &query.embedding, k, options);
+        }
+        let n = pool_size(k, options);
+        let mut best: HashMap<usize, f32> = HashMap::new();
+        let rankings: Vec<(Vec<usize>, f32)> = queries
+            .iter()
+            .map(|q| {
+                let hits = self.candidate_ids(&q.text, &q.embedding, n, options);
+                for hit in &hits {
+                    let b = best.entry(hit.id).or_insert(hit.similarity);
+                    *b = b.max(hit.similarity);
+                }
+                (hits.into_iter().map(|hit| hit.id).collect(), 1.0)
+            })
+            .collect();
+        let rrf_k = options
+            .hybrid
+            .map_or(HybridParams::default().rrf_k, |h| h.rrf_k);
+        let mut fused = reciprocal_rank_fusion(&rankings, rrf_k);
+        fused.truncate(n);
+        let hits = Candidate::from_fused(fused, |i| best[&i]);
+        self.finish_search(hits, k, options)
+    }
+
+    /// The best `n` chunks for one query, with filter and hybrid fusion applied.
+    fn candidate_ids(
+        &self,
+        query_text: &str,
+        query_embedding: &[f32],
+        n: usize,
+        options: &SearchOptions,
+    ) -> Vec<Candidate> {
+        let root = Path::new(&self.settings.notes_root);
+        let accept = |c: &Chunk| options.filter.as_ref().is_none_or(|f| f.matches(c, root));
+        let by_similarity = |hits: Vec<(usize, f32)>| {
+            hits.into_iter()
+                .map(|(id, sim)| Candidate {
+                    id,
+                    relevance: sim,
+                    similarity: sim,
+                })
+                .collect()
+        };
+        match options.hybrid.as_ref() {
+            Some(params) => {
+                let fused = hybrid_search_ids(
+                    &self.store,
+                    &self.lexical,
+                    query_text,
+                    query_embedding,
+                    n,
+                    params,
+                    &accept,
+                );
+                let q_norm = normalize(query_embedding);
+                Candidate::from_fused(fused, |i| self.store.similarity(i, &q_norm))
+            }
+            None if options.filter.is_none() => {
+                by_similarity(self.store.search_ids(query_embedding, n))
+            }
+            None => by_similarity(self.store.search_ids_where(query_embedding, n, &accept)),
+        }
+    }
+
+    /// Apply MMR if requested and resolve ids to chunks. MMR weighs each candidate's rank
+    /// score against its cosine similarity to the picks so far.
+    fn finish_search(
+        &self,
+        hits: Vec<Candidate>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Vec<SearchHit> {
+        let mut order: Vec<usize> = (0..hits.len()).collect();
+        if let Some(lambda) = options.mmr_lambda {
+            let relevance: Vec<(usize, f32)> = hits
+                .iter()
+                .enumerate()
+                .map(|(pos, hit)| (pos, hit.relevance))
+                .collect();
+            order = mmr_select(&relevance, k, lambda, |a, b| {
+                self.store.item_similarity(hits[a].id, hits[b].id)
+            })
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        }
+        order.truncate(k);
+        let chunks = self.store.chunks();
+        order
+            .into_iter()
+            .map(|pos| SearchHit {
+                chunk: chunks[hits[pos].id].clone(),
+                score: hits[pos].similarity,
+                relevance: hits[pos].relevance,
+            })
+            .collect()
+    }
+
+    /// Check that queries embedded with `model` into `dim`-long vectors can be searched
+    /// against this index; see [IndexSettings::check_embedder].
+    pub fn check_embedder(&self, model: &str, dim: usize) -> Result<(), EmbeddingMismatch> {
+        self.settings.check_embedder(model, Some(dim), None)
+    }
+
+    /// Rebuild the lexical index and quantized codes if they are out of step with the
+    /// store and settings (e.g. after loading an index written before they existed).
+    pub(crate) fn ensure_derived(&mut self) {
+        if self.settings.embed_dim.is_none() {
+            self.settings.embed_dim = self.store.dimension();
+        }
+        if self.lexical.len() != self.store.len() {
+            self.lexical =
+                Bm25Index::from_texts(self.store.chunks().iter().map(|c| c.text.as_str()));
+        }
+        self.store.set_quantization(self.settings.quantization);
+    }
+}
+
+/// A search candidate before MMR.
+struct Candidate {
+    id: usize,
+    /// Ranking score, best first: the cosine for plain vector search, or the fused RRF score
+    /// relative to the best candidate after hybrid or expansion fusion.
+    relevance: f32,
+    /// Cosine similarity to the query.
+    similarity: f32,
+}
+
+impl Candidate {
+    /// Candidates from an RRF ranking, with `similarity` looked up per id.
+    fn from_fused(fused: Vec<(usize, f32)>, similarity: impl Fn(usize) -> f32) -> Vec<Self> {
+        let top = fused.first().map_or(1.0, |&(_, score)| score);
+        fused
+            .into_iter()
+            .map(|(id, score)| Candidate {
+                id,
+                relevance: if top > 0.0 { score / top } else { 0.0 },
+                similarity: similarity(id),
+            })
+            .collect()
+    }
+}
+
+/// Candidates to fetch per query: `k`, or a larger pool for MMR to choose from.
+fn pool_size(k: usize, options: &SearchOptions) -> usize {
+    match options.mmr_lambda {
+        Some(_) => k.saturating_mul(MMR_POOL_FACTOR),
+        None => k,
+    }
 }
 
-/// Default on-disk index path: `<app_data_dir>/index.json`.
+/// Default on-disk index path: `<app_data_dir>/index.bin`.
 pub fn default_index_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("index.bin"))
+}
+
+/// Path of the JSON index written by older versions: `<app_data_dir>/index.json`.
+pub fn legacy_index_path() -> Option<PathBuf> {
     app_data_dir().map(|d| d.join("index.json"))
 }
 
 pub async fn build_persisted_index(
     notes: Vec<Note>,
-    client: &OllamaClient,
+    embedder: &impl Embedder,
     settings: IndexSettings,
+) -> Result<PersistedIndex, BuildPersistedIndexError> {
+    build_persisted_index_with(
+        notes,
+        embedder,
+        settings,
+        &EmbedParams::default(),
+        &no_progress,
+    )
+    .await
+}
+
+/// Like [build_persisted_index], embedding with `params` and reporting to `progress`.
+pub async fn build_persisted_index_with(
+    notes: Vec<Note>,
+    embedder: &impl Embedder,
+    mut settings: IndexSettings,
+    params: &EmbedParams,
+    progress: ProgressFn<'_>,
 ) -> Result<PersistedIndex, BuildPersistedIndexError> {
     let now = unix_now_secs();
     let mut store = VectorStore::new();
@@ -90,10 +464,11 @@ pub async fn build_persisted_index(
             settings,
             store,
             note_states,
+            lexical: Bm25Index::new(),
         });
     }
 
-    // Chunk and embed all notes in one batch.
+    // Chunk all notes, then embed in batches.
     let mut chunks = Vec::new();
     for n in &notes {
         chunks.extend(chunk_note(n, settings.max_chars));
@@ -110,15 +485,20 @@ pub async fn build_persisted_index(
             settings,
             store,
             note_states,
+            lexical: Bm25Index::new(),
         });
     }
 
-    let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
-    let embeddings = client
-        .embed_batch(&texts)
+    let embeddings = embed_chunks(embedder, &chunks, params, progress)
         .await
         .map_err(BuildPersistedIndexError::from)?;
-    store.add_batch(chunks, embeddings);
+    let lexical = Bm25Index::from_texts(chunks.iter().map(|c| c.text.as_str()));
+    store.set_quantization(settings.quantization);
+    store.add_batch(chunks, embeddings)?;
+    if let Some(params) = settings.ann {
+        store.enable_ann(params);
+    }
+    settings.embed_dim = store.dimension();
 
     Ok(PersistedIndex {
         schema_version: INDEX_SCHEMA_VERSION,
@@ -127,6 +507,7 @@ pub async fn build_persisted_index(
         settings,
         store,
         note_states,
+        lexical,
     })
 }
 
@@ -134,7 +515,26 @@ pub async fn build_persisted_index(
 pub async fn update_persisted_index(
     index: &mut PersistedIndex,
     notes: Vec<Note>,
-    client: &OllamaClient,
+    embedder: &impl Embedder,
+) -> Result<UpdatePersistedIndexStats, UpdatePersistedIndexError> {
+    update_persisted_index_with(
+        index,
+        notes,
+        embedder,
+        &EmbedParams::default(),
+        &no_progress,
+    )
+    .await
+}
+
+/// Like [update_persisted_index], embedding changed notes with `params` and reporting to
+/// `progress`.
+pub async fn update_persisted_index_with(
+    index: &mut PersistedIndex,
+    notes: Vec<Note>,
+    embedder: &impl Embedder,
+    params: &EmbedParams,
+    progress: ProgressFn<'_>,
 ) -> Result<UpdatePersistedIndexStats, UpdatePersistedIndexError> {
     let mut current_paths: BTreeSet<String> = BTreeSet::new();
     let mut changed_notes: Vec<Note> = Vec::new();
@@ -156,7 +556,7 @@ pub async fn update_persisted_index(
         if !current_paths.contains(&old) {
             deleted += 1;
             index.note_states.remove(&old);
-            index.store.remove_note(Path::new(&old));
+            index.remove_note(Path::new(&old));
         }
     }
 
@@ -169,7 +569,7 @@ pub async fn update_persisted_index(
         // Remove existing chunks first.
         for n in &changed_notes {
             changed += 1;
-            removed_chunks += index.store.remove_note(&n.path);
+            removed_chunks += index.remove_note(&n.path);
         }
 
         let mut chunks = Vec::new();
@@ -183,15 +583,16 @@ pub async fn update_persisted_index(
         }
 
         if !chunks.is_empty() {
-            let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
-            let embeddings = client
-                .embed_batch(&texts)
+            let embeddings = embed_chunks(embedder, &chunks, params, progress)
                 .await
                 .map_err(UpdatePersistedIndexError::from)?;
             added_chunks = chunks.len();
-            index.store.add_batch(chunks, embeddings);
+            index.add_chunks(chunks, embeddings)?;
         }
     }
+    if index.settings.embed_dim.is_none() {
+        index.settings.embed_dim = index.store.dimension();
+    }
 
     index.updated_at_unix = unix_now_secs();
 
@@ -213,14 +614,14 @@ pub struct UpdatePersistedIndexStats {
     pub total_chunks: usize,
 }
 
-fn unix_now_secs() -> i64 {
+pub(crate) fn unix_now_secs() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs() as i64
 }
 
-fn note_state(path: &Path) -> Option<NoteState> {
+pub(crate) fn note_state(path: &Path) -> Option<NoteState> {
     let md = std::fs::metadata(path).ok()?;
     let modified = md.modified().ok()?;
     let modified_unix_ms = modified
@@ -241,6 +642,50 @@ pub enum PersistedIndexError {
     Serialize(serde_json::Error),
     #[error("failed to deserialize index: {0}")]
     Deserialize(serde_json::Error),
+    #[error("invalid index file: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported index file version: {0}")]
+    UnsupportedVersion(u32),
+}
+
+/// Queries would be embedded with a different model than the index was built with, which
+/// makes similarity scores meaningless.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EmbeddingMismatch {
+    #[error(
+        "index built with {}, config uses {}. Rebuild the index, or set embed_model back to {index_model}.",
+        describe_model(.index_model, *.index_dim),
+        describe_model(.query_model, *.query_dim)
+    )]
+    Model {
+        index_model: String,
+        index_dim: Option<usize>,
+        query_model: String,
+        query_dim: Option<usize>,
+    },
+    #[error(
+        "{model} changed since the index was built (digest {} → {}). Rebuild the index.",
+        short_digest(.index_digest),
+        short_digest(.current_digest)
+    )]
+    Digest {
+        model: String,
+        index_digest: String,
+        current_digest: String,
+    },
+}
+
+/// `nomic-embed-text (768d)`, or just the name when the dimension is unknown.
+fn describe_model(model: &str, dim: Option<usize>) -> String {
+    match dim {
+        Some(dim) => format!("{} ({}d)", model, dim),
+        None => model.to_string(),
+    }
+}
+
+fn short_digest(digest: &str) -> &str {
+    let digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+    digest.get(..12).unwrap_or(digest)
 }
 
 #[derive(Debug, Error)]
@@ -248,11 +693,138 @@ pub enum BuildPersistedIndexError {
     #[error("scan error: {0}")]
     Scan(#[from] ScanError),
     #[error("embedding error: {0}")]
-    Ollama(#[from] OllamaError),
+    Embed(#[from] ModelError),
+    #[error("failed to write checkpoint: {0}")]
+    Checkpoint(#[from] PersistedIndexError),
+    #[error("failed to add embeddings: {0}")]
+    Store(#[from] StoreError),
 }
 
 #[derive(Debug, Error)]
 pub enum UpdatePersistedIndexError {
     #[error("embedding error: {0}")]
-    Ollama(#[from] OllamaError),
+    Embed(#[from] ModelError),
+    #[error("failed to add embeddings: {0}")]
+    Store(#[from] StoreError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> IndexSettings {
+        IndexSettings {
+            notes_root: "/notes".to_string(),
+            max_chars: 512,
+            ollama_url: "http://localhost:11434".to_string(),
+            embed_model: "nomic-embed-text".to_string(),
+            embed_backend: Default::default(),
+            ann: None,
+            quantization: Quantization::None,
+            embed_dim: Some(768),
+            embed_digest: Some(
+                "sha256:0a109f422b47e3a30ba2b10eca18548e944e8a23073ee3f3e947efcf3c45e59f"
+                    .to_string(),
+            ),
+        }
+    }
+
+    #[test]
+    fn detects_embedding_model_mismatch() {
+        let settings = settings();
+        assert!(settings
+            .check_embedder("nomic-embed-text:latest", Some(768), None)
+            .is_ok());
+
+        let err = settings
+            .check_embedder("all-minilm", Some(384), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "index built with nomic-embed-text (768d), config uses all-minilm (384d). \
+             Rebuild the index, or set embed_model back to nomic-embed-text."
+        );
+        assert!(matches!(
+            settings.check_embedder("nomic-embed-text", Some(1024), None),
+            Err(EmbeddingMismatch::Model { .. })
+        ));
+        let err = settings
+            .check_embedder("nomic-embed-text", Some(768), Some("sha256:970aa74c0a90"))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("digest 0a109f422b47 → 970aa74c0a90"));
+    }
+
+    fn empty_index() -> PersistedIndex {
+        PersistedIndex {
+            schema_version: INDEX_SCHEMA_VERSION,
+            created_at_unix: 0,
+            updated_at_unix: 0,
+            settings: settings(),
+            store: VectorStore::new(),
+            note_states: BTreeMap::new(),
+            lexical: Bm25Index::new(),
+        }
+    }
+
+    fn chunk(path: &str, text: &str) -> Chunk {
+        Chunk {
+            text: text.to_string(),
+            kind: Default::default(),
+            note_path: PathBuf::from(path),
+            index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn note_titles_come_from_title_chunks() {
+        let mut index = empty_index();
+        let title = Chunk {
+            kind: ChunkKind::Title,
+            ..chunk("a.md", "Lease")
+        };
+        let chunks = vec![
+            title,
+            chunk("a.md", "Rent is due."),
+            chunk("b.md", "Untitled"),
+        ];
+        index.add_chunks(chunks, vec![vec![1.0, 0.0]; 3]).unwrap();
+        let titles = index.note_titles();
+        assert_eq!(titles.len(), 1);
+        assert_eq!(titles[Path::new("a.md")], "Lease");
+    }
+
+    #[test]
+    fn mmr_keeps_lexical_only_hits_after_fusion() {
+        let mut index = empty_index();
+        index
+            .add_chunks(
+                vec![
+                    chunk("a.md", "quarterly planning notes"),
+                    chunk("b.md", "planning the next quarter"),
+                    chunk("c.md", "notes on planning"),
+                    chunk("l.md", "ticket XJ4411 escalation"),
+                ],
+                vec![
+                    vec![1.0, 0.1, 0.0],
+                    vec![0.9, 0.4, 0.0],
+                    vec![0.8, 0.6, 0.0],
+                    vec![0.0, 0.0, 1.0],
+                ],
+            )
+            .unwrap();
+
+        let options = SearchOptions::new()
+            .with_hybrid(HybridParams::default())
+            .with_mmr(Some(1.0));
+        let hits = index.search("XJ4411", &[1.0, 0.0, 0.0], 2, &options);
+        let paths: Vec<_> = hits.iter().map(|(c, _)| c.note_path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("l.md"), PathBuf::from("a.md")]);
+        // Scores stay cosine similarities.
+        assert!(hits[0].1.abs() < 1e-6);
+    }
 }
@@ -0,0 +1,197 @@
+//! Prompt assembly within the chat model's context window.
+//!
+//! Retrieved excerpts are added by score, best first, as long as the estimated size of the
+//! prompt stays within the window minus the space reserved for the answer. Excerpts that do
+//! not fit are reported instead of being silently cut off by the server.
+
+use crate::chunks::{Chunk, ChunkKind};
+
+/// Context window assumed when neither the config nor the server says otherwise; also
+/// Ollama's default `num_ctx`.
+pub const DEFAULT_CONTEXT_LENGTH: usize = 4096;
+/// Tokens kept free for the answer.
+pub const DEFAULT_ANSWER_TOKENS: usize = 512;
+
+/// Rough token count of `text`, erring on the high side: about four ASCII characters per
+/// token, one token per other character (CJK, emoji), and at least 4/3 tokens per word.
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.bytes().filter(u8::is_ascii).count();
+    let other = text.chars().filter(|c| !c.is_ascii()).count();
+    let words = text.split_whitespace().count();
+    (ascii.div_ceil(4) + other).max((words * 4).div_ceil(3))
+}
+
+/// The window to budget for: the configured length, else the model's own context length
+/// capped at [DEFAULT_CONTEXT_LENGTH] (Ollama only allocates its default window unless told
+/// otherwise), else [DEFAULT_CONTEXT_LENGTH].
+pub fn context_length_for(configured: Option<usize>, model_max: Option<u64>) -> usize {
+    configured.filter(|n| *n > 0).unwrap_or_else(|| {
+        model_max
+            .map(|n| (n as usize).min(DEFAULT_CONTEXT_LENGTH))
+            .unwrap_or(DEFAULT_CONTEXT_LENGTH)
+    })
+}
+
+/// Token budget of one prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptBudget {
+    /// Context window of the model in tokens.
+    pub context_length: usize,
+    /// Tokens reserved for the answer.
+    pub answer_tokens: usize,
+}
+
+/// The excerpts chosen by [PromptBudget::fit_sources].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextFit {
+    /// Numbered excerpts of the kept sources, as [format_excerpt] writes them.
+    pub context: String,
+    /// Input indices of the kept sources in input order; excerpt `[n]` is `kept[n - 1]`.
+    pub kept: Vec<usize>,
+    /// Input indices of the sources that did not fit, best score first.
+    pub dropped: Vec<usize>,
+    /// Estimated tokens of the fixed text plus the context.
+    pub prompt_tokens: usize,
+}
+
+impl PromptBudget {
+    pub fn new(context_length: usize) -> Self {
+        Self {
+            context_length,
+            answer_tokens: DEFAULT_ANSWER_TOKENS.min(context_length / 4),
+        }
+    }
+
+    pub fn with_answer_tokens(mut self, n: usize) -> Self {
+        self.answer_tokens = n;
+        self
+    }
+
+    /// Tokens left for excerpts once `fixed` (instructions, history, question) is in.
+    pub fn available(&self, fixed: &str) -> usize {
+        self.context_length
+            .saturating_sub(self.answer_tokens)
+            .saturating_sub(estimate_tokens(fixed))
+    }
+
+    /// Choose excerpts from `sources` greedily by score to fill what `fixed` leaves of the
+    /// budget. A source too large for the remaining space is skipped and smaller ones are
+    /// still tried. If not even the best source fits, it is kept truncated so the answer
+    /// has some context.
+    pub fn fit_sources(&self, fixed: &str, sources: &[(Chunk, f32)]) -> ContextFit {
+        let available = self.available(fixed);
+        let mut by_score: Vec<usize> = (0..sources.len()).collect();
+        by_score.sort_by(|a, b| sources[*b].1.total_cmp(&sources[*a].1));
+
+        // Numbers are assigned after selection; size every excerpt with the widest one.
+        let widest = sources.len().max(1);
+        let mut used = 0usize;
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        for i in by_score {
+            let tokens = estimate_tokens(&format_excerpt(widest, &sources[i].0, None));
+            if used + tokens <= available {
+                used += tokens;
+                kept.push(i);
+            } else {
+                dropped.push(i);
+            }
+        }
+
+        let mut truncated = None;
+        if kept.is_empty() && !dropped.is_empty() {
+            let best = dropped.remove(0);
+            truncated = Some(truncate_to_tokens(&sources[best].0, available));
+            kept.push(best);
+        }
+        kept.sort_unstable();
+
+        let mut context = String::new();
+        for (n, &i) in kept.iter().enumerate() {
+            context.push_str(&format_excerpt(n + 1, &sources[i].0, truncated.as_deref()));
+        }
+        ContextFit {
+            prompt_tokens: estimate_tokens(fixed) + estimate_tokens(&context),
+            context,
+            kept,
+            dropped,
+        }
+    }
+}
+
+/// One numbered excerpt of the context: `[n][title|body]`, then the text (or `text` instead
+/// of the chunk's own, when given).
+pub fn format_excerpt(n: usize, chunk: &Chunk, text: Option<&str>) -> String {
+    let kind = match chunk.kind {
+        ChunkKind::Title => "title",
+        ChunkKind::Body => "body",
+    };
+    format!("[{}][{}]\n{}\n\n", n, kind, text.unwrap_or(&chunk.text))
+}
+
+/// The start of the chunk's text that fits in `tokens`, with its excerpt header.
+fn truncate_to_tokens(chunk: &Chunk, tokens: usize) -> String {
+    let mut text: String = chunk.text.clone();
+    while !text.is_empty() && estimate_tokens(&format_excerpt(1, chunk, Some(&text))) > tokens {
+        let keep = text.chars().count() * 9 / 10;
+        text = text.chars().take(keep).collect();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn chunk(text: &str) -> Chunk {
+        Chunk {
+            text: text.to_string(),
+            kind: ChunkKind::Body,
+            note_path: PathBuf::from("a.md"),
+            index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn estimates_tokens_conservatively() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_tokens("a b c"), 4);
+        assert_eq!(estimate_tokens("日本語"), 3);
+        assert_eq!(
+            context_length_for(None, Some(131072)),
+            DEFAULT_CONTEXT_LENGTH
+        );
+        assert_eq!(context_length_for(Some(16384), Some(131072)), 16384);
+        assert_eq!(context_length_for(None, Some(2048)), 2048);
+    }
+
+    #[test]
+    fn fills_budget_by_score_and_reports_dropped() {
+        let long = "word ".repeat(60);
+        let sources = vec![
+            (chunk("short and relevant"), 0.5),
+            (chunk(&long), 0.9),
+            (chunk("tiny"), 0.1),
+            (chunk(&long), 0.7),
+        ];
+        // Room for one long excerpt (~85 tokens) plus the short ones.
+        let budget = PromptBudget::new(150).with_answer_tokens(20);
+        let fit = budget.fit_sources("Question: why?", &sources);
+        assert_eq!(fit.kept, vec![0, 1, 2]);
+        assert_eq!(fit.dropped, vec![3]);
+        assert!(fit.context.starts_with("[1][body]\nshort and relevant"));
+        assert!(fit.context.contains("[3][body]\ntiny"));
+        assert!(fit.prompt_tokens <= 130);
+
+        let tight = PromptBudget::new(40).with_answer_tokens(10);
+        let fit = tight.fit_sources("Question: why?", &sources[1..2]);
+        assert_eq!(fit.kept, vec![0]);
+        assert!(fit.prompt_tokens <= 30);
+    }
+}
@@ -0,0 +1,471 @@
+//! Model backends behind provider-agnostic traits.
+//!
+//! [Embedder] turns text into vectors; [ChatModel] generates text. The index and ask
+//! pipelines are generic over these traits. [OllamaClient] and [OpenAiClient] (llama.cpp
+//! server, LM Studio, vLLM and other OpenAI-compatible servers) implement both, and
+//! [ModelClient] picks one of them at runtime from the `[models]` config. With the
+//! `local-embed` feature, the built-in [crate::local_embed::HashEmbedder] is a third,
+//! embedding-only backend that needs no server.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::chat::ChatMessage;
+use crate::config::ModelConfig;
+#[cfg(feature = "local-embed")]
+use crate::local_embed::{HashEmbedder, LOCAL_EMBED_DIM};
+use crate::ollama::{OllamaClient, OllamaError, DEFAULT_BASE_URL, DEFAULT_EMBED_MODEL};
+use crate::openai::{OpenAiClient, OpenAiError, DEFAULT_OPENAI_BASE_URL};
+use crate::persisted_index::IndexSettings;
+
+/// Which HTTP API a model server speaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelBackend {
+    #[default]
+    Ollama,
+    /// OpenAI-compatible `/v1` API.
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Built-in embedder, no server (embeddings only; needs the `local-embed` feature).
+    Local,
+}
+
+impl ModelBackend {
+    /// Base URL used when none is configured.
+    pub fn default_url(self) -> &'static str {
+        match self {
+            ModelBackend::Ollama => DEFAULT_BASE_URL,
+            ModelBackend::OpenAi => DEFAULT_OPENAI_BASE_URL,
+            ModelBackend::Local => "",
+        }
+    }
+}
+
+/// Sampling options for generation. Unset fields use the model defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerateOptions {
+    pub temperature: Option<f32>,
+    /// Maximum number of tokens to generate.
+    pub max_tokens: Option<i32>,
+    /// Context window to load the model with (Ollama `num_ctx`). OpenAI-compatible servers
+    /// fix the window at startup and ignore this.
+    pub context_length: Option<u64>,
+}
+
+const LOCAL_NO_CHAT: &str = "the local backend only computes embeddings; set chat_backend to \
+ollama or openai";
+#[cfg(not(feature = "local-embed"))]
+const LOCAL_DISABLED: &str = "the local embedding backend is not available in this build \
+(feature local-embed)";
+
+/// Text embedded to probe an embedding model.
+pub(crate) const EMBED_PROBE_TEXT: &str = "dimension probe";
+
+/// What an embedding model turned out to be when probed; see [ModelClient::probe_embedding].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingProbe {
+    pub model: String,
+    /// Length of the model's embeddings.
+    pub dimension: usize,
+    /// Digest of the installed model, on servers that report one (Ollama).
+    pub digest: Option<String>,
+}
+
+/// Text fragments of a streamed completion, in order. Dropping the stream cancels the
+/// request.
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String, ModelError>> + Send>>;
+
+/// Computes embeddings with one fixed model.
+pub trait Embedder: Send + Sync {
+    /// Name of the embedding model, as recorded in index settings.
+    fn model_name(&self) -> &str;
+
+    fn embed(&self, text: &str) -> impl Future<Output = Result<Vec<f32>, ModelError>> + Send;
+
+    /// One embedding per input, in input order.
+    fn embed_batch(
+        &self,
+        texts: &[String],
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, ModelError>> + Send;
+}
+
+/// Generates text with a model chosen per call.
+pub trait ChatModel: Send + Sync {
+    /// Complete a single prompt.
+    fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> impl Future<Output = Result<String, ModelError>> + Send;
+
+    /// Complete a single prompt, yielding text as it is produced.
+    fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> impl Future<Output = Result<TokenStream, ModelError>> + Send;
+
+    /// Generate the next assistant message of a conversation.
+    fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> impl Future<Output = Result<String, ModelError>> + Send;
+}
+
+/// Complete `prompt` as a stream, passing each text fragment to `on_token` as it arrives.
+/// Returns the whole text. Dropping the future stops generation; the fragments passed on
+/// so far are all that was generated.
+pub async fn stream_completion<M: ChatModel>(
+    chat: &M,
+    model: &str,
+    prompt: &str,
+    options: &GenerateOptions,
+    mut on_token: impl FnMut(&str) + Send,
+) -> Result<String, ModelError> {
+    let mut tokens = chat.generate_stream(model, prompt, options).await?;
+    let mut text = String::new();
+    while let Some(token) = tokens.next().await {
+        let token = token?;
+        on_token(&token);
+        text.push_str(&token);
+    }
+    Ok(text)
+}
+
+/// A client for whichever backend is configured.
+#[derive(Debug, Clone)]
+pub enum ModelClient {
+    Ollama(OllamaClient),
+    OpenAi(OpenAiClient),
+    #[cfg(feature = "local-embed")]
+    Local(HashEmbedder),
+}
+
+impl ModelClient {
+    /// Connect to `url` with `backend`. The embedding model defaults to
+    /// [DEFAULT_EMBED_MODEL]; see [ModelClient::with_embed_model]. The local backend
+    /// ignores `url` and always embeds with its own model.
+    pub fn new(
+        backend: ModelBackend,
+        url: &str,
+        api_key: Option<&str>,
+    ) -> Result<Self, ModelError> {
+        Ok(match backend {
+            ModelBackend::Ollama => ModelClient::Ollama(OllamaClient::from_url(url)?),
+            ModelBackend::OpenAi => {
+                let client = OpenAiClient::from_url(url)?.with_embed_model(DEFAULT_EMBED_MODEL);
+                ModelClient::OpenAi(match api_key {
+                    Some(key) => client.with_api_key(key),
+                    None => client,
+                })
+            }
+            #[cfg(feature = "local-embed")]
+            ModelBackend::Local => ModelClient::Local(HashEmbedder::new()),
+            #[cfg(not(feature = "local-embed"))]
+            ModelBackend::Local => return Err(ModelError::Unsupported(LOCAL_DISABLED)),
+        })
+    }
+
+    /// The embedding client described by `models`.
+    pub fn for_embedding(models: &ModelConfig) -> Result<Self, ModelError> {
+        let backend = models.embed_backend.unwrap_or_default();
+        let url = models.embed_url.as_deref().unwrap_or(backend.default_url());
+        let model = models.embed_model.as_deref().unwrap_or(DEFAULT_EMBED_MODEL);
+        Ok(Self::new(backend, url, models.api_key.as_deref())?.with_embed_model(model))
+    }
+
+    /// The chat client described by `models`. Fails for the embedding-only local backend.
+    pub fn for_chat(models: &ModelConfig) -> Result<Self, ModelError> {
+        let backend = models.chat_backend.unwrap_or_default();
+        if backend == ModelBackend::Local {
+            return Err(ModelError::Unsupported(LOCAL_NO_CHAT));
+        }
+        let url = models.chat_url.as_deref().unwrap_or(backend.default_url());
+        Self::new(backend, url, models.api_key.as_deref())
+    }
+
+    /// The embedding client an index was built with, to embed queries against it.
+    pub fn for_index(settings: &IndexSettings, api_key: Option<&str>) -> Result<Self, ModelError> {
+        Ok(
+            Self::new(settings.embed_backend, &settings.ollama_url, api_key)?
+                .with_embed_model(&settings.embed_model),
+        )
+    }
+
+    pub fn with_embed_model(self, model: impl Into<String>) -> Self {
+        match self {
+            ModelClient::Ollama(c) => ModelClient::Ollama(c.with_embed_model(model)),
+            ModelClient::OpenAi(c) => ModelClient::OpenAi(c.with_embed_model(model)),
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(e) => ModelClient::Local(e),
+        }
+    }
+
+    /// Check that the embedding model is available by embedding a short text, and report
+    /// its dimension and (on Ollama) digest.
+    pub async fn probe_embedding(&self) -> Result<EmbeddingProbe, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => Ok(c.probe_embedding().await?),
+            ModelClient::OpenAi(c) => Ok(EmbeddingProbe {
+                model: c.model_name().to_string(),
+                dimension: Embedder::embed(c, EMBED_PROBE_TEXT).await?.len(),
+                digest: None,
+            }),
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(e) => Ok(EmbeddingProbe {
+                model: e.model_name().to_string(),
+                dimension: LOCAL_EMBED_DIM,
+                digest: None,
+            }),
+        }
+    }
+
+    pub fn backend(&self) -> ModelBackend {
+        match self {
+            ModelClient::Ollama(_) => ModelBackend::Ollama,
+            ModelClient::OpenAi(_) => ModelBackend::OpenAi,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(_) => ModelBackend::Local,
+        }
+    }
+}
+
+impl Embedder for ModelClient {
+    fn model_name(&self) -> &str {
+        match self {
+            ModelClient::Ollama(c) => c.model_name(),
+            ModelClient::OpenAi(c) => c.model_name(),
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(e) => e.model_name(),
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => Embedder::embed(c, text).await,
+            ModelClient::OpenAi(c) => Embedder::embed(c, text).await,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(e) => Embedder::embed(e, text).await,
+        }
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => Embedder::embed_batch(c, texts).await,
+            ModelClient::OpenAi(c) => Embedder::embed_batch(c, texts).await,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(e) => Embedder::embed_batch(e, texts).await,
+        }
+    }
+}
+
+impl ChatModel for ModelClient {
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => ChatModel::generate(c, model, prompt, options).await,
+            ModelClient::OpenAi(c) => ChatModel::generate(c, model, prompt, options).await,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(_) => Err(ModelError::Unsupported(LOCAL_NO_CHAT)),
+        }
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<TokenStream, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => ChatModel::generate_stream(c, model, prompt, options).await,
+            ModelClient::OpenAi(c) => ChatModel::generate_stream(c, model, prompt, options).await,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(_) => Err(ModelError::Unsupported(LOCAL_NO_CHAT)),
+        }
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        match self {
+            ModelClient::Ollama(c) => ChatModel::chat(c, model, messages, options).await,
+            ModelClient::OpenAi(c) => ChatModel::chat(c, model, messages, options).await,
+            #[cfg(feature = "local-embed")]
+            ModelClient::Local(_) => Err(ModelError::Unsupported(LOCAL_NO_CHAT)),
+        }
+    }
+}
+
+impl ModelError {
+    /// Whether retrying the same request may succeed: connection problems, timeouts, server
+    /// overload. Bad URLs and malformed responses are not transient.
+    pub fn is_transient(&self) -> bool {
+        use ollama_rs::error::OllamaError as Inner;
+        match self {
+            ModelError::Ollama(OllamaError::ParseUrl(_)) => false,
+            ModelError::Ollama(OllamaError::Request(e)) => matches!(
+                e,
+                Inner::ReqwestError(_) | Inner::InternalError(_) | Inner::Other(_)
+            ),
+            ModelError::OpenAi(OpenAiError::Http(e)) => !e.is_decode() && !e.is_builder(),
+            ModelError::OpenAi(OpenAiError::Status { status, .. }) => {
+                *status == 429 || *status >= 500
+            }
+            ModelError::OpenAi(_) => false,
+            ModelError::BatchSize { .. } => false,
+            ModelError::Unsupported(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ModelError {
+    #[error(transparent)]
+    Ollama(#[from] OllamaError),
+    #[error(transparent)]
+    OpenAi(#[from] OpenAiError),
+    #[error("embedding server returned {got} embeddings for {expected} texts")]
+    BatchSize { expected: usize, got: usize },
+    #[error("{0}")]
+    Unsupported(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures_util::future::{AbortHandle, Abortable};
+
+    use super::*;
+    use crate::test_support::FakeChat;
+
+    #[tokio::test]
+    async fn streams_tokens_in_order_until_aborted() {
+        let chat = FakeChat(|_: &str| Ok("The deposit came back.".to_string()));
+        let options = GenerateOptions::default();
+        let mut seen = Vec::new();
+        let text = stream_completion(&chat, "m", "q", &options, |t| seen.push(t.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(seen, ["The ", "deposit ", "came ", "back."]);
+        assert_eq!(text, "The deposit came back.");
+
+        let (handle, registration) = AbortHandle::new_pair();
+        let seen = Mutex::new(Vec::new());
+        let on_token = |t: &str| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(t.to_string());
+            if seen.len() == 2 {
+                handle.abort();
+            }
+        };
+        let run = stream_completion(&chat, "m", "q", &options, on_token);
+        assert!(Abortable::new(run, registration).await.is_err());
+        assert_eq!(*seen.lock().unwrap(), ["The ", "deposit "]);
+    }
+
+    #[test]
+    fn classifies_transient_errors() {
+        use ollama_rs::error::OllamaError as Inner;
+
+        let transient = |e: ModelError| e.is_transient();
+        let status = |status| OpenAiError::Status {
+            status,
+            body: String::new(),
+        };
+        let json = serde_json::from_str::<u8>("x").unwrap_err();
+        let builder = reqwest::Client::new().get("no url").build().unwrap_err();
+
+        assert!(transient(
+            OllamaError::Request(Inner::Other("overloaded".into())).into()
+        ));
+        assert!(!transient(
+            OllamaError::Request(Inner::JsonError(json)).into()
+        ));
+        assert!(!transient(
+            OllamaError::ParseUrl(url::ParseError::EmptyHost).into()
+        ));
+        assert!(transient(status(429).into()));
+        assert!(transient(status(503).into()));
+        assert!(!transient(status(400).into()));
+        assert!(!transient(OpenAiError::Http(builder).into()));
+        assert!(!transient(
+            OpenAiError::InvalidResponse("no data".into()).into()
+        ));
+        assert!(!transient(ModelError::BatchSize {
+            expected: 2,
+            got: 1
+        }));
+        assert!(!transient(ModelError::Unsupported(LOCAL_NO_CHAT)));
+    }
+
+    #[test]
+    fn selects_backend_from_model_config() {
+        let defaults = ModelConfig::default();
+        let embed = ModelClient::for_embedding(&defaults).unwrap();
+        assert_eq!(embed.backend(), ModelBackend::Ollama);
+        assert_eq!(embed.model_name(), DEFAULT_EMBED_MODEL);
+        assert_eq!(
+            ModelClient::for_chat(&defaults).unwrap().backend(),
+            ModelBackend::Ollama
+        );
+
+        let openai = ModelConfig {
+            embed_backend: Some(ModelBackend::OpenAi),
+            embed_model: Some("text-embed".to_string()),
+            chat_backend: Some(ModelBackend::OpenAi),
+            chat_url: Some("http://localhost:8080/v1".to_string()),
+            api_key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let embed = ModelClient::for_embedding(&openai).unwrap();
+        assert_eq!(embed.backend(), ModelBackend::OpenAi);
+        assert_eq!(embed.model_name(), "text-embed");
+        assert_eq!(
+            ModelClient::for_chat(&openai).unwrap().backend(),
+            ModelBackend::OpenAi
+        );
+
+        let local = ModelConfig {
+            embed_backend: Some(ModelBackend::Local),
+            chat_backend: Some(ModelBackend::Local),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ModelClient::for_chat(&local),
+            Err(ModelError::Unsupported(LOCAL_NO_CHAT))
+        ));
+        let embed = ModelClient::for_embedding(&local);
+        #[cfg(feature = "local-embed")]
+        assert_eq!(embed.unwrap().backend(), ModelBackend::Local);
+        #[cfg(not(feature = "local-embed"))]
+        assert!(matches!(
+            embed,
+            Err(ModelError::Unsupported(LOCAL_DISABLED))
+        ));
+
+        let bad_url = ModelConfig {
+            chat_url: Some("not a url".to_string()),
+            ..Default::default()
+        };
+        let err = ModelClient::for_chat(&bad_url).unwrap_err();
+        assert!(matches!(err, ModelError::Ollama(OllamaError::ParseUrl(_))));
+        assert!(!err.is_transient());
+    }
+}
@@ -0,0 +1,285 @@
+//! Compressed copies of the store's embeddings for a cheap first search pass.
+//!
+//! The quantized codes stay in memory while the full-precision vectors can stay in the
+//! memory-mapped index file: a search scans the codes, then rescores only the best
+//! candidates against the full vectors (see [crate::store::VectorStore::set_quantization]).
+//!
+//! - `Int8`: one signed byte per dimension plus one f32 scale per vector (~4x smaller).
+//! - `Binary`: one sign bit per dimension, compared by Hamming distance (~32x smaller).
+
+use serde::{Deserialize, Serialize};
+
+/// How embeddings are compressed for the first search pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantization {
+    /// Full precision only.
+    #[default]
+    None,
+    /// Scalar quantization to int8.
+    Int8,
+    /// One bit per dimension.
+    Binary,
+}
+
+impl Quantization {
+    pub fn is_none(&self) -> bool {
+        *self == Quantization::None
+    }
+
+    /// Candidates kept per requested result for full-precision rescoring. Coarser codes
+    /// need a wider net to keep recall close to an exact scan.
+    pub(crate) fn rescore_factor(self) -> usize {
+        match self {
+            Quantization::None => 1,
+            Quantization::Int8 => 4,
+            Quantization::Binary => 10,
+        }
+    }
+}
+
+/// Quantized codes for every vector in a store, in store order. The dimension is fixed by
+/// the first pushed vector, as in the full-precision buffer.
+#[derive(Debug, Clone)]
+pub(crate) enum QuantizedVectors {
+    Int8 {
+        dim: usize,
+        scales: Vec<f32>,
+        codes: Vec<i8>,
+    },
+    Binary {
+        dim: usize,
+        len: usize,
+        bits: Vec<u64>,
+    },
+}
+
+impl QuantizedVectors {
+    /// Empty codes for `mode`, or `None` for full precision.
+    pub(crate) fn new(mode: Quantization) -> Option<Self> {
+        match mode {
+            Quantization::None => None,
+            Quantization::Int8 => Some(Self::Int8 {
+                dim: 0,
+                scales: Vec::new(),
+                codes: Vec::new(),
+            }),
+            Quantization::Binary => Some(Self::Binary {
+                dim: 0,
+                len: 0,
+                bits: Vec::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn mode(&self) -> Quantization {
+        match self {
+            Self::Int8 { .. } => Quantization::Int8,
+            Self::Binary { .. } => Quantization::Binary,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Int8 { scales, .. } => scales.len(),
+            Self::Binary { len, .. } => *len,
+        }
+    }
+
+    /// Append the codes for one vector. Rows must all have the same length.
+    pub(crate) fn push(&mut self, v: &[f32]) {
+        match self {
+            Self::Int8 { dim, scales, codes } => {
+                if scales.is_empty() {
+                    *dim = v.len();
+                }
+                let max = v.iter().fold(0f32, |m, x| m.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                scales.push(scale);
+                codes.extend(
+                    (0..*dim).map(|j| (v.get(j).copied().unwrap_or(0.0) / scale).round() as i8),
+                );
+            }
+            Self::Binary { dim, len, bits } => {
+                if *len == 0 {
+                    *dim = v.len();
+                }
+                bits.extend(sign_bits(v, *dim));
+                *len += 1;
+            }
+        }
+    }
+
+    /// Keep only the rows whose flag is true, preserving order.
+    pub(crate) fn retain(&mut self, keep: &[bool]) {
+        match self {
+            Self::Int8 { dim, scales, codes } => {
+                retain_rows(codes, *dim, keep);
+                retain_rows(scales, 1, keep);
+            }
+            Self::Binary { dim, len, bits } => {
+                retain_rows(bits, dim.div_ceil(64), keep);
+                *len = keep.iter().filter(|k| **k).count();
+            }
+        }
+    }
+
+    /// Approximate similarity of every kept row to a unit-length `query`, unsorted.
+    /// Int8 scores approximate the cosine; binary scores are `1 - 2 * hamming / dim`.
+    pub(crate) fn scan(&self, query: &[f32], keep: Option<&[bool]>) -> Vec<(usize, f32)> {
+        let kept = |i: &usize| keep.is_none_or(|keep| keep[*i]);
+        match self {
+            Self::Int8 { dim, scales, codes } => (0..scales.len())
+                .filter(kept)
+                .map(|i| {
+                    let row = &codes[i * dim..(i + 1) * dim];
+                    let sum: f32 = row.iter().zip(query).map(|(c, q)| *c as f32 * q).sum();
+                    (i, sum * scales[i])
+                })
+                .collect(),
+            Self::Binary { dim, len, bits } => {
+                let words = dim.div_ceil(64);
+                let q: Vec<u64> = sign_bits(query, *dim).collect();
+                let dim = (*dim).max(1) as f32;
+                (0..*len)
+                    .filter(kept)
+                    .map(|i| {
+                        let row = &bits[i * words..(i + 1) * words];
+                        let diff: u32 = row.iter().zip(&q).map(|(a, b)| (a ^ b).count_ones()).sum();
+                        (i, 1.0 - 2.0 * diff as f32 / dim)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Bytes of the binary index section: mode tag, dimension, row count, then the codes.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Self::Int8 { dim, scales, codes } => {
+                out.push(1);
+                out.extend_from_slice(&(*dim as u64).to_le_bytes());
+                out.extend_from_slice(&(scales.len() as u64).to_le_bytes());
+                for s in scales {
+                    out.extend_from_slice(&s.to_le_bytes());
+                }
+                out.extend(codes.iter().map(|c| *c as u8));
+            }
+            Self::Binary { dim, len, bits } => {
+                out.push(2);
+                out.extend_from_slice(&(*dim as u64).to_le_bytes());
+                out.extend_from_slice(&(*len as u64).to_le_bytes());
+                for w in bits {
+                    out.extend_from_slice(&w.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    /// Inverse of [QuantizedVectors::encode]. Returns `None` on malformed input.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&tag, rest) = bytes.split_first()?;
+        let u64_at = |at: usize| -> Option<usize> {
+            Some(u64::from_le_bytes(rest.get(at..at + 8)?.try_into().ok()?) as usize)
+        };
+        let dim = u64_at(0)?;
+        let len = u64_at(8)?;
+        let body = &rest[16..];
+        match tag {
+            1 => {
+                let scales_len = len.checked_mul(4)?;
+                if body.len() != scales_len.checked_add(len.checked_mul(dim)?)? {
+                    return None;
+                }
+                let scales = body[..scales_len]
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                let codes = body[scales_len..].iter().map(|b| *b as i8).collect();
+                Some(Self::Int8 { dim, scales, codes })
+            }
+            2 => {
+                if body.len() != len.checked_mul(dim.div_ceil(64))?.checked_mul(8)? {
+                    return None;
+                }
+                let bits = body
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                    .collect();
+                Some(Self::Binary { dim, len, bits })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sign bits of the first `dim` values of `v`, packed into `dim.div_ceil(64)` words.
+fn sign_bits(v: &[f32], dim: usize) -> impl Iterator<Item = u64> + '_ {
+    (0..dim.div_ceil(64)).map(move |w| {
+        (0..64)
+            .filter(|b| v.get(w * 64 + b).is_some_and(|x| *x > 0.0))
+            .fold(0u64, |word, b| word | (1 << b))
+    })
+}
+
+fn retain_rows<T: Copy>(data: &mut Vec<T>, width: usize, keep: &[bool]) {
+    let mut write = 0usize;
+    for (row, &k) in keep.iter().enumerate() {
+        if k {
+            if write != row {
+                data.copy_within(row * width..(row + 1) * width, write * width);
+            }
+            write += 1;
+        }
+    }
+    data.truncate(write * width);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::normalize;
+
+    fn rows() -> Vec<Vec<f32>> {
+        (0..40)
+            .map(|i| {
+                let v: Vec<f32> = (0..70)
+                    .map(|j| ((i * 7 + j * 13) % 17) as f32 - 8.0)
+                    .collect();
+                normalize(&v)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn codes_rank_the_query_row_first() {
+        let rows = rows();
+        for mode in [Quantization::Int8, Quantization::Binary] {
+            let mut q = QuantizedVectors::new(mode).unwrap();
+            for r in &rows {
+                q.push(r);
+            }
+            let mut scored = q.scan(&rows[11], None);
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            assert_eq!(scored[0].0, 11, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn retain_and_roundtrip() {
+        let rows = rows();
+        for mode in [Quantization::Int8, Quantization::Binary] {
+            let mut q = QuantizedVectors::new(mode).unwrap();
+            for r in &rows {
+                q.push(r);
+            }
+            let keep: Vec<bool> = (0..rows.len()).map(|i| i % 3 != 0).collect();
+            q.retain(&keep);
+            let decoded = QuantizedVectors::decode(&q.encode()).unwrap();
+            assert_eq!(decoded.len(), keep.iter().filter(|k| **k).count());
+            assert_eq!(decoded.scan(&rows[1], None), q.scan(&rows[1], None));
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Second-stage reranking: rescore the top candidates of a search with a slower, more
+//! accurate model before picking the final top-k.
+//!
+//! [Reranker] is the extension point; [LlmReranker] implements it with any [ChatModel],
+//! either a dedicated reranker model answering yes/no or a general LLM asked for a 0–10
+//! relevance grade with the `rerank` template. [rerank] applies a reranker with a timeout
+//! and falls back to the original order if it fails.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::chunks::Chunk;
+use crate::ollama::OllamaClient;
+use crate::provider::{ChatModel, GenerateOptions, ModelError};
+use crate::templates::{PromptTemplates, TemplateError};
+
+/// Default number of candidates sent to the reranker.
+pub const DEFAULT_RERANK_TOP_N: usize = 20;
+/// Default time budget for reranking one query.
+pub const DEFAULT_RERANK_TIMEOUT: Duration = Duration::from_secs(20);
+/// Scoring requests sent to the model at once by [LlmReranker].
+const RERANK_CONCURRENCY: usize = 4;
+/// Score of a document whose reply could not be read: neither promoted nor demoted.
+const NEUTRAL_RELEVANCE: f32 = 0.5;
+
+/// Scores documents for relevance to a query.
+pub trait Reranker {
+    /// One relevance score in `[0, 1]` per document, in input order.
+    fn score(
+        &self,
+        query: &str,
+        documents: &[&str],
+    ) -> impl Future<Output = Result<Vec<f32>, RerankError>> + Send;
+}
+
+/// How [LlmReranker] asks the model for relevance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerankMode {
+    /// A reranker model (e.g. a Qwen3 reranker) that answers "yes" or "no".
+    Model,
+    /// A general chat model asked to grade relevance from 0 to 10.
+    #[default]
+    Prompt,
+}
+
+/// Reranker backed by a language model. Documents are scored a few at a time; a reply
+/// without a readable score counts as [NEUTRAL_RELEVANCE], and only fails the rerank if no
+/// reply could be read.
+#[derive(Debug, Clone)]
+pub struct LlmReranker<C> {
+    client: C,
+    model: String,
+    mode: RerankMode,
+    templates: PromptTemplates,
+}
+
+/// [LlmReranker] with a local Ollama model.
+pub type OllamaReranker = LlmReranker<OllamaClient>;
+
+impl<C: ChatModel> LlmReranker<C> {
+    pub fn new(client: C, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+            mode: RerankMode::default(),
+            templates: PromptTemplates::builtin(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: RerankMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Templates to take the `rerank` prompt from; default the built-ins.
+    pub fn with_templates(mut self, templates: PromptTemplates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    fn prompt(&self, query: &str, document: &str) -> Result<String, TemplateError> {
+        match self.mode {
+            RerankMode::Model => Ok(format!(
+                "Judge whether the Document meets the requirements based on the Query. \
+                 Note that the answer can only be \"yes\" or \"no\".\n\n\
+                 <Query>: {}\n<Document>: {}\n<Answer>:",
+                query, document
+            )),
+            RerankMode::Prompt => self.templates.render_rerank(query, document),
+        }
+    }
+}
+
+impl<C: ChatModel> Reranker for LlmReranker<C> {
+    fn score(
+        &self,
+        query: &str,
+        documents: &[&str],
+    ) -> impl Future<Output = Result<Vec<f32>, RerankError>> + Send {
+        let options = GenerateOptions {
+            temperature: Some(0.0),
+            max_tokens: Some(4),
+            ..Default::default()
+        };
+        let prompts: Result<Vec<String>, TemplateError> = documents
+            .iter()
+            .map(|doc| self.prompt(query, doc))
+            .collect();
+        async move {
+            let requests = prompts?.into_iter().map(|prompt| async move {
+                let reply = self.client.generate(&self.model, &prompt, &options).await?;
+                Ok::<_, RerankError>((parse_relevance(&reply, self.mode), reply))
+            });
+            let replies: Vec<(Option<f32>, String)> = stream::iter(requests)
+                .buffered(RERANK_CONCURRENCY)
+                .try_collect()
+                .await?;
+            if replies.iter().all(|(score, _)| score.is_none()) {
+                if let Some((_, reply)) = replies.into_iter().next() {
+                    return Err(RerankError::Unparseable(reply));
+                }
+                return Ok(Vec::new());
+            }
+            Ok(replies
+                .into_iter()
+                .map(|(score, _)| score.unwrap_or(NEUTRAL_RELEVANCE))
+                .collect())
+        }
+    }
+}
+
+/// Tuning for [rerank].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RerankParams {
+    /// Candidates sent to the reranker; the rest are dropped.
+    pub top_n: usize,
+    pub timeout: Duration,
+}
+
+impl Default for RerankParams {
+    fn default() -> Self {
+        Self {
+            top_n: DEFAULT_RERANK_TOP_N,
+            timeout: DEFAULT_RERANK_TIMEOUT,
+        }
+    }
+}
+
+/// Result of [rerank].
+#[derive(Debug)]
+pub struct Reranked {
+    /// Hits best first, with their original search scores.
+    pub hits: Vec<(Chunk, f32)>,
+    /// Reranker scores parallel to `hits`, or `None` if the original order was kept.
+    pub relevance: Option<Vec<f32>>,
+    /// Why the reranker was skipped, if it was.
+    pub error: Option<RerankError>,
+}
+
+/// Rescore the first `params.top_n` of `hits` with `reranker` and sort them by its scores
+/// (ties keep search order). On error or timeout, returns `hits` unchanged.
+pub async fn rerank<R: Reranker>(
+    reranker: &R,
+    query: &str,
+    hits: Vec<(Chunk, f32)>,
+    params: &RerankParams,
+) -> Reranked {
+    let n = hits.len().min(params.top_n);
+    let documents: Vec<&str> = hits[..n].iter().map(|(c, _)| c.text.as_str()).collect();
+    let scores = match tokio::time::timeout(params.timeout, reranker.score(query, &documents)).await
+    {
+        Ok(Ok(scores)) if scores.len() == n => scores,
+        Ok(Ok(_)) => return fallback(hits, RerankError::Unparseable("score count".into())),
+        Ok(Err(e)) => return fallback(hits, e),
+        Err(_) => return fallback(hits, RerankError::Timeout(params.timeout)),
+    };
+
+    let mut scored: Vec<((Chunk, f32), f32)> = hits.into_iter().take(n).zip(scores).collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (hits, relevance) = scored.into_iter().unzip();
+    Reranked {
+        hits,
+        relevance: Some(relevance),
+        error: None,
+    }
+}
+
+fn fallback(hits: Vec<(Chunk, f32)>, error: RerankError) -> Reranked {
+    Reranked {
+        hits,
+        relevance: None,
+        error: Some(error),
+    }
+}
+
+/// Read a relevance score in `[0, 1]` from a model reply.
+fn parse_relevance(reply: &str, mode: RerankMode) -> Option<f32> {
+    let reply = reply.trim().to_lowercase();
+    match mode {
+        RerankMode::Model => {
+            if reply.starts_with("yes") {
+                Some(1.0)
+            } else if reply.starts_with("no") {
+                Some(0.0)
+            } else {
+                None
+            }
+        }
+        RerankMode::Prompt => {
+            let number: String = reply
+                .chars()
+                .skip_while(|c| !c.is_ascii_digit())
+                .take_while(|c| c.is_ascii_digit() || *c == '.')
+                .collect();
+            let grade: f32 = number.parse().ok()?;
+            Some((grade / 10.0).clamp(0.0, 1.0))
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RerankError {
+    #[error("reranker request failed: {0}")]
+    Model(#[from] ModelError),
+    #[error("reranker timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("could not read a relevance score from reranker reply: {0:?}")]
+    Unparseable(String),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::chunks::ChunkKind;
+    use crate::test_support::FakeChat;
+
+    struct ByLength;
+
+    impl Reranker for ByLength {
+        fn score(
+            &self,
+            _query: &str,
+            documents: &[&str],
+        ) -> impl Future<Output = Result<Vec<f32>, RerankError>> + Send {
+            let scores = documents.iter().map(|d| d.len() as f32).collect();
+            async move { Ok(scores) }
+        }
+    }
+
+    struct Failing;
+
+    impl Reranker for Failing {
+        async fn score(&self, _query: &str, _documents: &[&str]) -> Result<Vec<f32>, RerankError> {
+            Err(RerankError::Unparseable("?".to_string()))
+        }
+    }
+
+    fn hits() -> Vec<(Chunk, f32)> {
+        ["a", "ccc", "bb"]
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let chunk = Chunk {
+                    text: t.to_string(),
+                    kind: ChunkKind::Body,
+                    note_path: PathBuf::from("a.md"),
+                    index: i,
+                    meta: Default::default(),
+                    span: None,
+                    location: None,
+                };
+                (chunk, 0.9 - i as f32 * 0.1)
+            })
+            .collect()
+    }
+
+    fn texts(r: &Reranked) -> Vec<&str> {
+        r.hits.iter().map(|(c, _)| c.text.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn reorders_top_n_and_falls_back_on_error() {
+        let params = RerankParams {
+            top_n: 2,
+            ..Default::default()
+        };
+        let r = rerank(&ByLength, "q", hits(), &params).await;
+        assert_eq!(texts(&r), vec!["ccc", "a"]);
+        assert!(r.relevance.is_some());
+
+        let r = rerank(&Failing, "q", hits(), &params).await;
+        assert_eq!(texts(&r), vec!["a", "ccc", "bb"]);
+        assert!(r.relevance.is_none() && r.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn unreadable_replies_score_neutral() {
+        let grades = |prompt: &str| {
+            Ok(if prompt.contains("Excerpt:\nrent") {
+                "7".to_string()
+            } else if prompt.contains("Excerpt:\npets") {
+                "2/10".to_string()
+            } else {
+                "It depends.".to_string()
+            })
+        };
+        let reranker = LlmReranker::new(FakeChat(grades), "m");
+        let scores = reranker
+            .score("q", &["rent", "garden", "pets"])
+            .await
+            .unwrap();
+        assert_eq!(scores, vec![0.7, NEUTRAL_RELEVANCE, 0.2]);
+        assert!(matches!(
+            reranker.score("q", &["garden", "sky"]).await,
+            Err(RerankError::Unparseable(_))
+        ));
+    }
+
+    #[test]
+    fn parses_replies() {
+        assert_eq!(parse_relevance(" Yes.", RerankMode::Model), Some(1.0));
+        assert_eq!(parse_relevance("7/10", RerankMode::Prompt), Some(0.7));
+        assert_eq!(parse_relevance("unsure", RerankMode::Prompt), None);
+    }
+}
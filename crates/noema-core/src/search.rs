@@ -0,0 +1,50 @@
+//! Options for searching a [crate::persisted_index::PersistedIndex].
+
+use crate::chunks::Chunk;
+use crate::filter::SearchFilter;
+use crate::hybrid::HybridParams;
+
+/// How to run a search. The default is plain vector search with no filter.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Only return chunks matching this filter.
+    pub filter: Option<SearchFilter>,
+    /// Fuse vector and BM25 rankings; `None` searches embeddings only.
+    pub hybrid: Option<HybridParams>,
+    /// Diversify results with maximal marginal relevance at this lambda (1 = relevance
+    /// only, 0 = diversity only). See [crate::mmr].
+    pub mmr_lambda: Option<f32>,
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_filter(mut self, filter: Option<SearchFilter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_hybrid(mut self, params: HybridParams) -> Self {
+        self.hybrid = Some(params);
+        self
+    }
+
+    pub fn with_mmr(mut self, lambda: Option<f32>) -> Self {
+        self.mmr_lambda = lambda;
+        self
+    }
+}
+
+/// One search result with both of its scores.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub chunk: Chunk,
+    /// Cosine similarity to the query (the best over all queries of an expanded search).
+    pub score: f32,
+    /// The score results were ranked by: the cosine for plain vector search, or the fused
+    /// RRF score relative to the best candidate (at most 1) after hybrid or expansion fusion.
+    /// Lexical-only matches can rank high here while their cosine stays low.
+    pub relevance: f32,
+}
@@ -0,0 +1,490 @@
+//! Resumable index builds.
+//!
+//! A full build embeds notes a few hundred chunks at a time and, after each segment, writes
+//! that segment alone to a staging directory next to the live index (`index.staging/`),
+//! along with a small status file. Checkpoints only ever append a segment file, so their
+//! cost does not grow with the size of the build. If the build is interrupted, the next
+//! build merges the segments, keeps every note whose [NoteState] is unchanged and embeds
+//! only the rest. The live index is replaced once, when the build has finished, and the
+//! segments are removed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::binary_index;
+use crate::chunks::{chunk_note, Chunk};
+use crate::embed_pipeline::{embed_chunks, EmbedParams, EmbedProgress, ProgressFn};
+use crate::lexical::Bm25Index;
+use crate::notes::Note;
+use crate::persisted_index::{
+    note_state, unix_now_secs, BuildPersistedIndexError, IndexSettings, NoteState, PersistedIndex,
+    PersistedIndexError, INDEX_SCHEMA_VERSION,
+};
+use crate::provider::Embedder;
+use crate::quantize::Quantization;
+use crate::store::{StoreError, VectorStore};
+
+/// Chunks embedded between two checkpoints.
+pub const DEFAULT_CHECKPOINT_CHUNKS: usize = 1000;
+
+const STATUS_FILE: &str = "status.json";
+
+/// How far an unfinished build got, as of its last checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointStatus {
+    pub chunks_done: usize,
+    pub chunks_total: usize,
+    pub notes_done: usize,
+    pub notes_total: usize,
+    pub notes_root: String,
+    pub embed_model: String,
+    /// Unix time seconds of the checkpoint.
+    pub updated_at_unix: i64,
+}
+
+/// The staging directory of one index.
+#[derive(Debug, Clone)]
+pub struct IndexStaging {
+    dir: PathBuf,
+    checkpoint_chunks: usize,
+}
+
+impl IndexStaging {
+    /// Staging for the live index at `index_path`: the directory `<stem>.staging` next to
+    /// it.
+    pub fn for_index(index_path: &Path) -> Self {
+        let stem = index_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "index".to_string());
+        let dir = index_path.parent().unwrap_or(Path::new(""));
+        Self {
+            dir: dir.join(format!("{}.staging", stem)),
+            checkpoint_chunks: DEFAULT_CHECKPOINT_CHUNKS,
+        }
+    }
+
+    /// Write a checkpoint after roughly every `n` embedded chunks (whole notes at a time).
+    pub fn with_checkpoint_chunks(mut self, n: usize) -> Self {
+        self.checkpoint_chunks = n.max(1);
+        self
+    }
+
+    /// Directory holding the staged segments and status.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Status of the last checkpoint, or `None` if there is no unfinished build.
+    pub fn status(&self) -> Option<CheckpointStatus> {
+        if !self.segment_path(0).exists() {
+            return None;
+        }
+        let json = fs::read(self.dir.join(STATUS_FILE)).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Save the finished `index` to `live_path`, replacing the old index atomically, then
+    /// remove the staging directory.
+    pub fn commit(
+        &self,
+        index: &PersistedIndex,
+        live_path: &Path,
+    ) -> Result<(), PersistedIndexError> {
+        index.save_to_file(live_path)?;
+        self.clear()?;
+        Ok(())
+    }
+
+    /// Remove the staging directory, discarding any unfinished build.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn segment_path(&self, n: usize) -> PathBuf {
+        self.dir.join(format!("segment-{:05}.bin", n))
+    }
+
+    /// Number of segments written so far. Segments are numbered from zero without gaps.
+    fn segment_count(&self) -> usize {
+        (0..).take_while(|&n| self.segment_path(n).exists()).count()
+    }
+
+    /// The staged index merged from all segments, if every segment is readable and was
+    /// built with settings compatible with `settings`. A later segment replaces the notes
+    /// it holds in earlier ones.
+    fn load(&self, settings: &IndexSettings) -> Option<PersistedIndex> {
+        let count = self.segment_count();
+        if count == 0 {
+            return None;
+        }
+        let mut staged = empty_index(settings, settings.quantization);
+        for n in 0..count {
+            let segment = binary_index::read(&self.segment_path(n)).ok()?;
+            let was = &segment.settings;
+            let compatible = segment.schema_version == INDEX_SCHEMA_VERSION
+                && was.notes_root == settings.notes_root
+                && was.max_chars == settings.max_chars
+                && was.same_embedder(settings);
+            if !compatible {
+                return None;
+            }
+            append_segment(&mut staged, segment).ok()?;
+        }
+        Some(staged)
+    }
+
+    /// Write `segment` as the next segment file, then the status.
+    fn save(
+        &self,
+        n: usize,
+        segment: &PersistedIndex,
+        status: &CheckpointStatus,
+    ) -> Result<(), PersistedIndexError> {
+        fs::create_dir_all(&self.dir)?;
+        segment.save_to_file(self.segment_path(n))?;
+        let json = serde_json::to_vec(status).map_err(PersistedIndexError::Serialize)?;
+        fs::write(self.dir.join(STATUS_FILE), json)?;
+        Ok(())
+    }
+}
+
+fn empty_index(settings: &IndexSettings, quantization: Quantization) -> PersistedIndex {
+    let now = unix_now_secs();
+    let mut store = VectorStore::new();
+    store.set_quantization(quantization);
+    PersistedIndex {
+        schema_version: INDEX_SCHEMA_VERSION,
+        created_at_unix: now,
+        updated_at_unix: now,
+        settings: settings.clone(),
+        store,
+        note_states: BTreeMap::new(),
+        lexical: Bm25Index::new(),
+    }
+}
+
+/// Add the chunks and note states of `segment` to `index`, replacing any chunks `index`
+/// already holds for the segment's notes.
+fn append_segment(index: &mut PersistedIndex, segment: PersistedIndex) -> Result<(), StoreError> {
+    let replaced: BTreeSet<&str> = segment.note_states.keys().map(String::as_str).collect();
+    index.retain_notes(|p| !replaced.contains(p.to_string_lossy().as_ref()));
+    let vectors = segment.store.vectors();
+    let embeddings = (0..segment.store.len())
+        .map(|i| vectors.get(i).to_vec())
+        .collect();
+    index.add_chunks(segment.store.chunks().to_vec(), embeddings)?;
+    index.note_states.extend(segment.note_states);
+    Ok(())
+}
+
+/// Like [crate::build_persisted_index_with], but resumes from and writes checkpoints to
+/// `staging`. The returned index is not saved to the live path; use
+/// [IndexStaging::commit]. On error, the checkpoints written so far stay in place for the
+/// next attempt.
+pub async fn build_persisted_index_resumable(
+    notes: Vec<Note>,
+    embedder: &impl Embedder,
+    settings: IndexSettings,
+    params: &EmbedParams,
+    progress: ProgressFn<'_>,
+    staging: &IndexStaging,
+) -> Result<PersistedIndex, BuildPersistedIndexError> {
+    let started = Instant::now();
+    let mut index = match staging.load(&settings) {
+        Some(staged) => staged,
+        None => {
+            // Nothing usable to resume from; start the segments over.
+            staging.clear().map_err(PersistedIndexError::from)?;
+            empty_index(&settings, settings.quantization)
+        }
+    };
+    let mut next_segment = staging.segment_count();
+
+    // Keep staged notes only if they are still there and unchanged.
+    let current: BTreeMap<String, Option<NoteState>> = notes
+        .iter()
+        .map(|n| (n.path.to_string_lossy().into_owned(), note_state(&n.path)))
+        .collect();
+    index
+        .note_states
+        .retain(|path, was| current.get(path).is_some_and(|st| st.as_ref() == Some(was)));
+    let staged: BTreeSet<String> = index.note_states.keys().cloned().collect();
+    index.retain_notes(|p| staged.contains(p.to_string_lossy().as_ref()));
+
+    // Chunk the remaining notes; notes without chunks are done right away and go into the
+    // next segment written.
+    let mut unsaved_states = BTreeMap::new();
+    let mut pending: Vec<(String, Option<NoteState>, Vec<Chunk>)> = Vec::new();
+    for n in &notes {
+        let key = n.path.to_string_lossy().into_owned();
+        if index.note_states.contains_key(&key) {
+            continue;
+        }
+        let chunks = chunk_note(n, settings.max_chars);
+        let state = current.get(&key).cloned().flatten();
+        if chunks.is_empty() {
+            if let Some(st) = state {
+                index.note_states.insert(key.clone(), st.clone());
+                unsaved_states.insert(key, st);
+            }
+        } else {
+            pending.push((key, state, chunks));
+        }
+    }
+
+    let resumed_chunks = index.store.len();
+    let notes_resumed = notes.len() - pending.len();
+    let mut status = CheckpointStatus {
+        chunks_done: resumed_chunks,
+        chunks_total: resumed_chunks + pending.iter().map(|p| p.2.len()).sum::<usize>(),
+        notes_done: notes_resumed,
+        notes_total: notes.len(),
+        notes_root: settings.notes_root.clone(),
+        embed_model: settings.embed_model.clone(),
+        updated_at_unix: unix_now_secs(),
+    };
+
+    let mut pending = pending.into_iter().peekable();
+    while pending.peek().is_some() {
+        // Whole notes per segment, so a checkpoint never holds half a note.
+        let mut segment = Vec::new();
+        let mut segment_chunks = Vec::new();
+        while let Some(next) = pending.next_if(|_| segment_chunks.len() < staging.checkpoint_chunks)
+        {
+            segment_chunks.extend(next.2.iter().cloned());
+            segment.push((next.0, next.1));
+        }
+
+        let base = status.clone();
+        let report = |p: &EmbedProgress| {
+            let embedded = base.chunks_done - resumed_chunks + p.chunks_done;
+            let elapsed = started.elapsed().as_secs_f64();
+            let left = (base.chunks_total - base.chunks_done - p.chunks_done) as f64;
+            progress(&EmbedProgress {
+                chunks_done: base.chunks_done + p.chunks_done,
+                chunks_total: base.chunks_total,
+                notes_done: base.notes_done + p.notes_done,
+                notes_total: base.notes_total,
+                resumed_chunks,
+                elapsed_secs: elapsed,
+                eta_secs: (embedded > 0).then(|| elapsed / embedded as f64 * left),
+            })
+        };
+        let embeddings = embed_chunks(embedder, &segment_chunks, params, &report).await?;
+
+        status.chunks_done += segment_chunks.len();
+        status.notes_done += segment.len();
+        let mut checkpoint = empty_index(&settings, Quantization::None);
+        checkpoint.add_chunks(segment_chunks.clone(), embeddings.clone())?;
+        index.add_chunks(segment_chunks, embeddings)?;
+        for (key, state) in segment {
+            if let Some(st) = state {
+                unsaved_states.insert(key, st);
+            }
+        }
+        checkpoint.note_states = std::mem::take(&mut unsaved_states);
+        index.note_states.extend(checkpoint.note_states.clone());
+        index.updated_at_unix = unix_now_secs();
+        status.updated_at_unix = index.updated_at_unix;
+        staging.save(next_segment, &checkpoint, &status)?;
+        next_segment += 1;
+    }
+
+    if let Some(params) = index.settings.ann {
+        index.store.enable_ann(params);
+    }
+    index.settings.embed_dim = index.store.dimension();
+    index.updated_at_unix = unix_now_secs();
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::embed_pipeline::no_progress;
+    use crate::ollama::OllamaError;
+    use crate::provider::ModelError;
+
+    /// Embeds text as its length; fails every request after the first `limit` chunks.
+    struct Counting {
+        embedded: AtomicUsize,
+        limit: usize,
+    }
+
+    impl Counting {
+        fn new(limit: usize) -> Self {
+            Self {
+                embedded: AtomicUsize::new(0),
+                limit,
+            }
+        }
+    }
+
+    impl Embedder for Counting {
+        fn model_name(&self) -> &str {
+            "counting"
+        }
+
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+            Ok(vec![text.len() as f32, 1.0])
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+            if self.embedded.load(Ordering::SeqCst) + texts.len() > self.limit {
+                let e = url::ParseError::EmptyHost;
+                return Err(OllamaError::ParseUrl(e).into());
+            }
+            self.embedded.fetch_add(texts.len(), Ordering::SeqCst);
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+    }
+
+    fn notes_in(dir: &Path, count: usize) -> Vec<Note> {
+        fs::create_dir_all(dir).unwrap();
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("note{}.md", i));
+                let body = format!("Note number {} has a short body.", i);
+                fs::write(&path, &body).unwrap();
+                Note {
+                    path,
+                    raw: body.clone(),
+                    frontmatter: None,
+                    body,
+                    links: Vec::new(),
+                    locations: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    fn settings(root: &Path) -> IndexSettings {
+        IndexSettings {
+            notes_root: root.to_string_lossy().into_owned(),
+            max_chars: 512,
+            ollama_url: "http://localhost:11434".to_string(),
+            embed_model: "counting".to_string(),
+            embed_backend: Default::default(),
+            ann: None,
+            quantization: Quantization::None,
+            embed_dim: None,
+            embed_digest: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn interrupted_build_resumes_from_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("noema-{}-staging", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let notes = notes_in(&dir.join("notes"), 10);
+        let live = dir.join("index.bin");
+        let staging = IndexStaging::for_index(&live).with_checkpoint_chunks(3);
+        let params = EmbedParams {
+            batch_size: 2,
+            concurrency: 1,
+            ..Default::default()
+        };
+
+        // Fails during the third segment, after two checkpoints of three notes each.
+        let first = Counting::new(7);
+        let err = build_persisted_index_resumable(
+            notes.clone(),
+            &first,
+            settings(&dir),
+            &params,
+            &no_progress,
+            &staging,
+        )
+        .await;
+        assert!(err.is_err());
+        let status = staging.status().unwrap();
+        assert_eq!((status.chunks_done, status.chunks_total), (6, 10));
+        assert!(!live.exists());
+
+        let second = Counting::new(usize::MAX);
+        let idx = build_persisted_index_resumable(
+            notes,
+            &second,
+            settings(&dir),
+            &params,
+            &no_progress,
+            &staging,
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.embedded.load(Ordering::SeqCst), 4);
+        assert_eq!(idx.store.len(), 10);
+        assert_eq!(idx.note_states.len(), 10);
+
+        staging.commit(&idx, &live).unwrap();
+        assert!(live.exists());
+        assert!(staging.status().is_none() && !staging.dir().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn later_segments_replace_changed_notes() {
+        let dir = std::env::temp_dir().join(format!("noema-{}-segments", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut notes = notes_in(&dir.join("notes"), 10);
+        let staging = IndexStaging::for_index(&dir.join("index.bin")).with_checkpoint_chunks(3);
+        let params = EmbedParams {
+            batch_size: 3,
+            concurrency: 1,
+            ..Default::default()
+        };
+        let build = |notes: Vec<Note>, embedder: Counting| {
+            let (staging, params, settings) = (&staging, &params, settings(&dir));
+            async move {
+                let idx = build_persisted_index_resumable(
+                    notes,
+                    &embedder,
+                    settings,
+                    params,
+                    &no_progress,
+                    staging,
+                )
+                .await;
+                (idx, embedder.embedded.load(Ordering::SeqCst))
+            }
+        };
+
+        // Each checkpoint adds one segment file.
+        assert!(build(notes.clone(), Counting::new(6)).await.0.is_err());
+        assert_eq!(staging.segment_count(), 2);
+
+        // A note of the first segment changes and lands in the third.
+        let body = "Note number 0 was edited and is longer now.".to_string();
+        fs::write(&notes[0].path, &body).unwrap();
+        notes[0].raw = body.clone();
+        notes[0].body = body.clone();
+        let (idx, embedded) = build(notes.clone(), Counting::new(3)).await;
+        assert!(idx.is_err());
+        assert_eq!((embedded, staging.segment_count()), (3, 3));
+
+        let (idx, embedded) = build(notes, Counting::new(usize::MAX)).await;
+        let idx = idx.unwrap();
+        assert_eq!(embedded, 2);
+        assert_eq!(idx.store.len(), 10);
+        let texts: Vec<&str> = idx.store.chunks().iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts
+                .iter()
+                .filter(|t| t.starts_with("Note number 0 "))
+                .count(),
+            1
+        );
+        assert!(texts.contains(&body.as_str()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
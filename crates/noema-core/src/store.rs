@@ -1,12 +1,22 @@
 //! In-memory vector store for chunk embeddings. Supports add and similarity search.
 //! Can be serialized to disk for persistence.
+//!
+//! Search is exact (brute force) by default. An optional HNSW graph (see [crate::hnsw])
+//! can be kept alongside the items for approximate search on large vaults, and optional
+//! quantized codes (see [crate::quantize]) make the exact scan cheaper in memory.
 
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
 
 use crate::chunks::Chunk;
-use serde::{Deserialize, Serialize};
+use crate::hnsw::{HnswIndex, HnswParams, VectorSource};
+use crate::quantize::{Quantization, QuantizedVectors};
+use memmap2::Mmap;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
 /// A chunk with its embedding, stored for similarity search.
@@ -18,35 +28,159 @@ pub struct IndexedChunk {
 }
 
 /// In-memory vector store. Holds chunks and their embeddings; supports similarity search.
-#[derive(Debug, Default, Serialize, Deserialize)]
+///
+/// Embeddings live in one contiguous buffer, which may be a memory-mapped section of a
+/// binary index file (see [crate::binary_index]). The JSON form is unchanged: a list of
+/// `{chunk, embedding}` items plus the optional graph.
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "StoreRepr")]
 pub struct VectorStore {
-    items: Vec<IndexedChunk>,
+    chunks: Vec<Chunk>,
+    vectors: FlatVectors,
+    /// Optional approximate nearest-neighbour graph over the items, kept in sync on add/remove.
+    ann: Option<HnswIndex>,
+    /// Optional quantized codes for the exact scan, kept in sync on add/remove.
+    quantized: Option<QuantizedVectors>,
 }
 
 impl VectorStore {
     pub fn new() -> Self {
-        Self { items: Vec::new() }
+        Self::default()
     }
 
     /// Create a store from pre-existing indexed chunks.
     pub fn from_items(items: Vec<IndexedChunk>) -> Self {
-        Self { items }
+        let mut store = Self::new();
+        for item in items {
+            store.vectors.push(&item.embedding);
+            store.chunks.push(item.chunk);
+        }
+        store
+    }
+
+    /// Assemble a store from parts read from disk. `vectors` (and `quantized`, if set) must
+    /// hold one row per chunk.
+    pub(crate) fn from_parts(
+        chunks: Vec<Chunk>,
+        vectors: FlatVectors,
+        ann: Option<HnswIndex>,
+        quantized: Option<QuantizedVectors>,
+    ) -> Self {
+        Self {
+            chunks,
+            vectors,
+            ann,
+            quantized,
+        }
+    }
+
+    pub(crate) fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub(crate) fn vectors(&self) -> &FlatVectors {
+        &self.vectors
+    }
+
+    pub(crate) fn ann(&self) -> Option<&HnswIndex> {
+        self.ann.as_ref()
+    }
+
+    pub(crate) fn quantized(&self) -> Option<&QuantizedVectors> {
+        self.quantized.as_ref()
+    }
+
+    /// Compress the embeddings for the exact scan, which then ranks by the quantized codes
+    /// and rescores the best candidates at full precision. `Quantization::None` drops the
+    /// codes. Approximate (HNSW) search is unaffected.
+    pub fn set_quantization(&mut self, mode: Quantization) {
+        if self.quantization() == mode {
+            return;
+        }
+        self.quantized = QuantizedVectors::new(mode);
+        if let Some(q) = self.quantized.as_mut() {
+            for i in 0..self.chunks.len() {
+                q.push(self.vectors.get(i));
+            }
+        }
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.quantized
+            .as_ref()
+            .map_or(Quantization::None, QuantizedVectors::mode)
     }
 
-    /// Add a chunk with its embedding. Embedding is normalized before storage.
-    pub fn add(&mut self, chunk: Chunk, embedding: Vec<f32>) {
+    /// Build (or rebuild) the HNSW graph over all items. Later adds and removals keep it
+    /// up to date, and [VectorStore::search] uses it instead of a full scan.
+    pub fn enable_ann(&mut self, params: HnswParams) {
+        self.ann = Some(HnswIndex::build(params, &self.vectors, self.chunks.len()));
+    }
+
+    /// Drop the HNSW graph; search falls back to the exact scan.
+    pub fn disable_ann(&mut self) {
+        self.ann = None;
+    }
+
+    /// Parameters of the HNSW graph, if one is built.
+    pub fn ann_params(&self) -> Option<HnswParams> {
+        self.ann.as_ref().map(|a| a.params())
+    }
+
+    /// Tune the recall/latency trade-off of approximate search. No-op without a graph.
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        if let Some(ann) = self.ann.as_mut() {
+            ann.set_ef_search(ef_search);
+        }
+    }
+
+    /// Add a chunk with its embedding. Embedding is normalized before storage. Fails if
+    /// the embedding's length differs from the vectors already stored.
+    pub fn add(&mut self, chunk: Chunk, embedding: Vec<f32>) -> Result<(), StoreError> {
+        self.check_dimension(&embedding)?;
         let norm = normalize(&embedding);
-        self.items.push(IndexedChunk {
-            chunk,
-            embedding: norm,
-        });
+        self.vectors.push(&norm);
+        self.chunks.push(chunk);
+        let id = self.chunks.len() - 1;
+        if let Some(q) = self.quantized.as_mut() {
+            q.push(self.vectors.get(id));
+        }
+        if let Some(ann) = self.ann.as_mut() {
+            ann.insert(id, &self.vectors);
+        }
+        Ok(())
     }
 
-    /// Add multiple chunks with embeddings in one batch.
-    pub fn add_batch(&mut self, chunks: Vec<Chunk>, embeddings: Vec<Vec<f32>>) {
+    /// Add multiple chunks with embeddings in one batch. Nothing is added if any
+    /// embedding has the wrong length.
+    pub fn add_batch(
+        &mut self,
+        chunks: Vec<Chunk>,
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<(), StoreError> {
         assert_eq!(chunks.len(), embeddings.len());
+        let expected = self.dimension().or(embeddings.first().map(Vec::len));
+        if let Some(expected) = expected {
+            if let Some(bad) = embeddings.iter().find(|e| e.len() != expected) {
+                return Err(StoreError::DimensionMismatch {
+                    expected,
+                    found: bad.len(),
+                });
+            }
+        }
         for (chunk, embedding) in chunks.into_iter().zip(embeddings) {
-            self.add(chunk, embedding);
+            self.add(chunk, embedding)?;
+        }
+        Ok(())
+    }
+
+    fn check_dimension(&self, embedding: &[f32]) -> Result<(), StoreError> {
+        match self.dimension() {
+            Some(expected) if expected != embedding.len() => Err(StoreError::DimensionMismatch {
+                expected,
+                found: embedding.len(),
+            }),
+            _ => Ok(()),
         }
     }
 
@@ -66,42 +200,320 @@ impl VectorStore {
     }
 
     /// Search for chunks most similar to the query embedding. Returns up to k results
-    /// with similarity scores (cosine similarity, 0–1).
+    /// with similarity scores (cosine similarity, 0–1). Uses the HNSW graph when one is
+    /// built, otherwise an exact scan.
     pub fn search(&self, query_embedding: &[f32], k: usize) -> Vec<(Chunk, f32)> {
-        if self.items.is_empty() || query_embedding.is_empty() {
+        self.search_ids(query_embedding, k)
+            .into_iter()
+            .map(|(i, sim)| (self.chunks[i].clone(), sim))
+            .collect()
+    }
+
+    /// Exact search over every item, ignoring any HNSW graph.
+    pub fn search_exact(&self, query_embedding: &[f32], k: usize) -> Vec<(Chunk, f32)> {
+        self.search_exact_ids(query_embedding, k, None)
+            .into_iter()
+            .map(|(i, sim)| (self.chunks[i].clone(), sim))
+            .collect()
+    }
+
+    /// Search restricted to chunks for which `accept` holds. Uses the HNSW graph when at
+    /// least a tenth of the store is accepted, otherwise scans just the accepted chunks.
+    pub fn search_where(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        accept: &dyn Fn(&Chunk) -> bool,
+    ) -> Vec<(Chunk, f32)> {
+        self.search_ids_where(query_embedding, k, accept)
+            .into_iter()
+            .map(|(i, sim)| (self.chunks[i].clone(), sim))
+            .collect()
+    }
+
+    /// Like [VectorStore::search], but returns `(position, similarity)` pairs.
+    pub(crate) fn search_ids(&self, query_embedding: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let Some(ann) = self.ann.as_ref() else {
+            return self.search_exact_ids(query_embedding, k, None);
+        };
+        if self.chunks.is_empty() || query_embedding.is_empty() {
             return Vec::new();
         }
         let q_norm = normalize(query_embedding);
-        let mut scored: Vec<(Chunk, f32)> = self
-            .items
-            .iter()
-            .map(|ic| {
-                let sim = dot(&q_norm, &ic.embedding);
-                (ic.chunk.clone(), sim)
-            })
+        ann.search(&q_norm, k, &self.vectors)
+    }
+
+    /// Like [VectorStore::search_where], but returns `(position, similarity)` pairs.
+    pub(crate) fn search_ids_where(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        accept: &dyn Fn(&Chunk) -> bool,
+    ) -> Vec<(usize, f32)> {
+        let keep = self.keep_mask(accept);
+        let accepted = keep.iter().filter(|k| **k).count();
+        match self.ann.as_ref() {
+            Some(ann) if accepted * 10 >= self.chunks.len() && !query_embedding.is_empty() => {
+                let q_norm = normalize(query_embedding);
+                ann.search_where(&q_norm, k, &self.vectors, &|i| keep[i])
+            }
+            _ => self.search_exact_ids(query_embedding, k, Some(&keep)),
+        }
+    }
+
+    fn search_exact_ids(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        keep: Option<&[bool]>,
+    ) -> Vec<(usize, f32)> {
+        if self.chunks.is_empty() || query_embedding.is_empty() || k == 0 {
+            return Vec::new();
+        }
+        let q_norm = normalize(query_embedding);
+        if let Some(q) = self.quantized.as_ref() {
+            let candidates = k.saturating_mul(q.mode().rescore_factor());
+            let rescored = top_k(q.scan(&q_norm, keep), candidates)
+                .into_iter()
+                .map(|(i, _)| (i, dot(&q_norm, self.vectors.get(i))))
+                .collect();
+            return top_k(rescored, k);
+        }
+        let scored = (0..self.chunks.len())
+            .filter(|&i| keep.is_none_or(|keep| keep[i]))
+            .map(|i| (i, dot(&q_norm, self.vectors.get(i))))
             .collect();
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        scored.into_iter().take(k).collect()
+        top_k(scored, k)
+    }
+
+    /// Cosine similarity of the item at `i` to an already normalized query.
+    pub(crate) fn similarity(&self, i: usize, query_norm: &[f32]) -> f32 {
+        dot(query_norm, self.vectors.get(i))
+    }
+
+    /// Cosine similarity between the items at `a` and `b`.
+    pub(crate) fn item_similarity(&self, a: usize, b: usize) -> f32 {
+        dot(self.vectors.get(a), self.vectors.get(b))
+    }
+
+    /// Length of the stored embeddings, or `None` while the store is empty.
+    pub fn dimension(&self) -> Option<usize> {
+        (!self.is_empty()).then(|| self.vectors.dim())
     }
 
     /// Number of indexed chunks.
     pub fn len(&self) -> usize {
-        self.items.len()
+        self.chunks.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.items.is_empty()
+        self.chunks.is_empty()
     }
 
     /// Remove all chunks belonging to a note path. Returns number removed.
     pub fn remove_note(&mut self, note_path: &Path) -> usize {
-        let before = self.items.len();
-        self.items.retain(|ic| ic.chunk.note_path != note_path);
-        before - self.items.len()
+        let keep = self.keep_mask(|c| c.note_path != note_path);
+        self.retain(&keep)
+    }
+
+    /// One flag per item: true where `f` holds for the item's chunk.
+    pub(crate) fn keep_mask(&self, f: impl Fn(&Chunk) -> bool) -> Vec<bool> {
+        self.chunks.iter().map(f).collect()
+    }
+
+    /// Keep only the items whose flag is true, preserving order. Returns number removed.
+    pub(crate) fn retain(&mut self, keep: &[bool]) -> usize {
+        let removed = keep.iter().filter(|k| !**k).count();
+        if removed == 0 {
+            return 0;
+        }
+        if let Some(ann) = self.ann.as_mut() {
+            ann.retain(keep, &self.vectors);
+        }
+        if let Some(q) = self.quantized.as_mut() {
+            q.retain(keep);
+        }
+        self.vectors.retain(keep);
+        let mut flags = keep.iter();
+        self.chunks
+            .retain(|_| flags.next().copied().unwrap_or(true));
+        removed
+    }
+}
+
+impl Serialize for VectorStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct ItemRef<'a> {
+            chunk: &'a Chunk,
+            embedding: &'a [f32],
+        }
+
+        let items: Vec<ItemRef> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| ItemRef {
+                chunk,
+                embedding: self.vectors.get(i),
+            })
+            .collect();
+        let quantization = self.quantization();
+        let fields = 1 + usize::from(self.ann.is_some()) + usize::from(!quantization.is_none());
+        let mut st = serializer.serialize_struct("VectorStore", fields)?;
+        st.serialize_field("items", &items)?;
+        if let Some(ann) = self.ann.as_ref() {
+            st.serialize_field("ann", ann)?;
+        }
+        if !quantization.is_none() {
+            st.serialize_field("quantization", &quantization)?;
+        }
+        st.end()
+    }
+}
+
+/// JSON shape of a [VectorStore]. Quantized codes are not stored; they are rebuilt on load.
+#[derive(Deserialize)]
+struct StoreRepr {
+    items: Vec<IndexedChunk>,
+    #[serde(default)]
+    ann: Option<HnswIndex>,
+    #[serde(default)]
+    quantization: Quantization,
+}
+
+impl From<StoreRepr> for VectorStore {
+    fn from(repr: StoreRepr) -> Self {
+        let mut store = VectorStore::from_items(repr.items);
+        // A graph that does not fit the items is dropped; search falls back to a scan.
+        store.ann = repr
+            .ann
+            .filter(|ann| ann.len() == store.len() && ann.is_consistent());
+        store.set_quantization(repr.quantization);
+        store
     }
 }
 
-fn normalize(v: &[f32]) -> Vec<f32> {
+/// The `k` best `(position, score)` pairs, best first.
+fn top_k(mut scored: Vec<(usize, f32)>, k: usize) -> Vec<(usize, f32)> {
+    let by_score = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1);
+    if k == 0 {
+        return Vec::new();
+    }
+    if scored.len() > k {
+        scored.select_nth_unstable_by(k - 1, by_score);
+        scored.truncate(k);
+    }
+    scored.sort_by(by_score);
+    scored
+}
+
+/// Row-major embedding matrix, either owned or borrowed from a memory-mapped file.
+#[derive(Debug, Default)]
+pub(crate) struct FlatVectors {
+    dim: usize,
+    data: VectorData,
+}
+
+#[derive(Debug)]
+enum VectorData {
+    Owned(Vec<f32>),
+    /// `range` is a byte range of `map` holding little-endian, 4-byte aligned f32 values.
+    Mapped {
+        map: Arc<Mmap>,
+        range: Range<usize>,
+    },
+}
+
+impl Default for VectorData {
+    fn default() -> Self {
+        Self::Owned(Vec::new())
+    }
+}
+
+impl FlatVectors {
+    pub(crate) fn from_vec(dim: usize, data: Vec<f32>) -> Self {
+        Self {
+            dim,
+            data: VectorData::Owned(data),
+        }
+    }
+
+    /// Borrow vectors straight from a mapped file. Returns `None` if the range is not
+    /// suitably aligned for f32 on this platform; callers then copy instead.
+    pub(crate) fn from_mapped(dim: usize, map: Arc<Mmap>, range: Range<usize>) -> Option<Self> {
+        if cfg!(target_endian = "big") {
+            return None;
+        }
+        bytemuck::try_cast_slice::<u8, f32>(map.get(range.clone())?).ok()?;
+        Some(Self {
+            dim,
+            data: VectorData::Mapped { map, range },
+        })
+    }
+
+    pub(crate) fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub(crate) fn as_slice(&self) -> &[f32] {
+        match &self.data {
+            VectorData::Owned(v) => v,
+            VectorData::Mapped { map, range } => bytemuck::cast_slice(&map[range.clone()]),
+        }
+    }
+
+    pub(crate) fn get(&self, i: usize) -> &[f32] {
+        &self.as_slice()[i * self.dim..(i + 1) * self.dim]
+    }
+
+    /// Copy-on-write access: a mapped buffer is copied into memory before mutation.
+    fn owned_mut(&mut self) -> &mut Vec<f32> {
+        if let VectorData::Mapped { .. } = self.data {
+            self.data = VectorData::Owned(self.as_slice().to_vec());
+        }
+        match &mut self.data {
+            VectorData::Owned(v) => v,
+            VectorData::Mapped { .. } => unreachable!("converted above"),
+        }
+    }
+
+    /// Append one row. The first row into an empty buffer fixes the dimension; callers
+    /// check that later rows match it.
+    fn push(&mut self, v: &[f32]) {
+        if self.as_slice().is_empty() {
+            self.dim = v.len();
+        }
+        debug_assert_eq!(v.len(), self.dim);
+        self.owned_mut().extend_from_slice(v);
+    }
+
+    fn retain(&mut self, keep: &[bool]) {
+        let dim = self.dim;
+        let data = self.owned_mut();
+        let mut write = 0usize;
+        for (row, &k) in keep.iter().enumerate() {
+            if k {
+                if write != row {
+                    data.copy_within(row * dim..(row + 1) * dim, write * dim);
+                }
+                write += 1;
+            }
+        }
+        data.truncate(write * dim);
+        if write == 0 {
+            self.dim = 0;
+        }
+    }
+}
+
+impl VectorSource for FlatVectors {
+    fn vector(&self, id: usize) -> &[f32] {
+        self.get(id)
+    }
+}
+
+pub(crate) fn normalize(v: &[f32]) -> Vec<f32> {
     let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
     if norm <= 0.0 {
         return v.to_vec();
@@ -109,7 +521,7 @@ fn normalize(v: &[f32]) -> Vec<f32> {
     v.iter().map(|x| x / norm).collect()
 }
 
-fn dot(a: &[f32], b: &[f32]) -> f32 {
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len().min(b.len());
     (0..n).map(|i| a[i] * b[i]).sum()
 }
@@ -123,4 +535,146 @@ pub enum StoreError {
     Serialize(serde_json::Error),
     #[error("failed to deserialize store: {0}")]
     Deserialize(serde_json::Error),
+    #[error("embedding has {found} dimensions, the index has {expected}")]
+    DimensionMismatch { expected: usize, found: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn chunk(path: &str) -> Chunk {
+        Chunk {
+            text: path.to_string(),
+            kind: Default::default(),
+            note_path: PathBuf::from(path),
+            index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_dimensions_until_emptied() {
+        let mut store = VectorStore::new();
+        store.add(chunk("a.md"), vec![1.0, 0.0, 0.0]).unwrap();
+        let err = store
+            .add_batch(
+                vec![chunk("b.md"), chunk("c.md")],
+                vec![vec![0.0, 1.0, 0.0], vec![0.0, 1.0]],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            StoreError::DimensionMismatch {
+                expected: 3,
+                found: 2
+            }
+        ));
+        assert_eq!(store.len(), 1);
+
+        // Once empty, a store can be refilled by a model with another dimension.
+        store.remove_note(Path::new("a.md"));
+        store.add(chunk("d.md"), vec![0.0, 1.0]).unwrap();
+        assert_eq!(store.dimension(), Some(2));
+        assert_eq!(store.search(&[0.0, 1.0], 1)[0].0.text, "d.md");
+    }
+
+    /// A deterministic, well spread pseudo-random vector.
+    fn vector(seed: usize, dim: usize) -> Vec<f32> {
+        (0..dim)
+            .map(|j| ((seed * 7919 + j * 104_729) as f32 * 0.618).sin())
+            .collect()
+    }
+
+    /// 400 chunks of 16 dimensions, ten per note.
+    fn filled_store() -> VectorStore {
+        let mut store = VectorStore::new();
+        let chunks = (0..400)
+            .map(|i| Chunk {
+                index: i % 10,
+                ..chunk(&format!("{}.md", i / 10))
+            })
+            .collect();
+        let embeddings = (0..400).map(|i| vector(i, 16)).collect();
+        store.add_batch(chunks, embeddings).unwrap();
+        store
+    }
+
+    #[test]
+    fn hnsw_recall_matches_exact_search_after_removing_notes() {
+        let mut store = filled_store();
+        store.enable_ann(HnswParams::default());
+        let removed: Vec<PathBuf> = (0..40)
+            .step_by(4)
+            .map(|n| format!("{}.md", n).into())
+            .collect();
+        for path in &removed {
+            assert_eq!(store.remove_note(path), 10);
+        }
+        assert_eq!(store.len(), 300);
+        assert!(store.ann().unwrap().is_consistent());
+
+        let (mut found, mut wanted) = (0, 0);
+        for q in 1000..1020 {
+            let query = vector(q, 16);
+            let exact: Vec<(PathBuf, usize)> = store
+                .search_exact(&query, 10)
+                .into_iter()
+                .map(|(c, _)| (c.note_path, c.index))
+                .collect();
+            let approx = store.search(&query, 10);
+            assert!(approx.iter().all(|(c, _)| !removed.contains(&c.note_path)));
+            found += approx
+                .iter()
+                .filter(|(c, _)| exact.contains(&(c.note_path.clone(), c.index)))
+                .count();
+            wanted += exact.len();
+        }
+        assert!(found * 10 >= wanted * 9, "recall {}/{}", found, wanted);
+    }
+
+    #[test]
+    fn filtered_and_quantized_searches_agree_with_exact_scan() {
+        let mut store = filled_store();
+        let query = vector(7, 16);
+        let best = store.search_exact(&query, 1)[0].0.clone();
+        assert_eq!(best.note_path, PathBuf::from("0.md"));
+
+        store.set_quantization(Quantization::Int8);
+        assert_eq!(store.search_exact(&query, 1)[0].0.note_path, best.note_path);
+
+        // Few accepted chunks use a scan, many use the graph; both only return accepted ones.
+        store.enable_ann(HnswParams::default());
+        let few = |c: &Chunk| c.note_path == Path::new("3.md");
+        let many = |c: &Chunk| c.note_path != Path::new("0.md");
+        let hits = store.search_where(&query, 5, &few);
+        assert_eq!(hits.len(), 5);
+        assert!(hits.iter().all(|(c, _)| few(c)));
+        let hits = store.search_ids_where(&query, 5, &many);
+        assert_eq!(hits.len(), 5);
+        assert!(hits.iter().all(|&(i, _)| many(&store.chunks()[i])));
+    }
+
+    #[test]
+    fn add_batch_rejects_mixed_dimensions_in_an_empty_store() {
+        let mut store = VectorStore::new();
+        let err = store
+            .add_batch(
+                vec![chunk("a.md"), chunk("b.md")],
+                vec![vec![1.0, 0.0], vec![1.0, 0.0, 0.0]],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            StoreError::DimensionMismatch {
+                expected: 2,
+                found: 3
+            }
+        ));
+        assert!(store.is_empty() && store.dimension().is_none());
+    }
 }
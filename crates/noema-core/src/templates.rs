@@ -0,0 +1,646 @@
+//! Prompt templates.
+//!
+//! Every prompt Noema sends to a chat model is rendered from a [minijinja] template. Built-in
+//! templates reproduce the default prompts; templates in `<app_data_dir>/templates/` and in
+//! `<vault>/.noema/templates/` (files named `<name>.jinja`) override them or add variants, the
+//! vault's taking precedence.
+//!
+//! A template's kind, and with it the variables it can use, comes from its name: `ask`,
+//! `chat_system`, `chat_turn`, `condense`, `hyde`, `paraphrase`, `rerank` and `verify`,
+//! optionally followed by `-<variant>` (e.g. `ask-brief`). Variants are chosen per call; see [PromptTemplates::render_ask].
+//! Templates are checked when loaded by rendering them with sample values, so a syntax
+//! error or a misspelled variable is reported up front rather than on the next question.
+//!
+//! Variables, besides `date` (today, `YYYY-MM-DD`) which every kind gets:
+//! - `ask`: `question`, `context` (the numbered excerpts) and `sources`, one
+//!   [TemplateSource] per excerpt with `n`, `title`, `path`, `kind`, `text`, `tags`,
+//!   `note_type` and `date`.
+//! - `chat_system`: only `date`.
+//! - `chat_turn`: the user message of a chat turn, with the same variables as `ask`.
+//! - `condense`: `question` and `history`, messages with `role`, `speaker` and `content`.
+//! - `hyde`: `question`.
+//! - `paraphrase`: `question` and `n`, the number of rephrasings wanted.
+//! - `rerank`: `question` and `document`, the excerpt to grade.
+//! - `verify`: `claim`, a sentence of an answer, and `sources`, the texts it cites.
+//!
+//! Dedicated reranker models ([crate::rerank::RerankMode::Model]) expect a fixed input
+//! format, so theirs is not a template.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use minijinja::{context, Environment, UndefinedBehavior, Value};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::app_data::app_data_dir;
+use crate::chat::{ChatMessage, ChatRole};
+use crate::chunks::{Chunk, ChunkKind};
+
+const TEMPLATES_DIRNAME: &str = "templates";
+const TEMPLATE_EXTENSION: &str = "jinja";
+/// Templates of a vault live in this directory under its root.
+const VAULT_TEMPLATES_DIR: &str = ".noema/templates";
+
+const ASK_TEMPLATE: &str = "You are Noema, a local-first knowledge assistant.\nUse ONLY the \
+following numbered excerpts as context.\nEach excerpt is tagged as [title] or [body]. Treat \
+title excerpts as metadata and prefer body excerpts for factual grounding.\nIf context is \
+insufficient, say you don't know.\nCite supporting excerpts inline as [1], [2], etc.\nDo not \
+mention note file names or paths unless the user explicitly asks for them.\n\nContext:\n\
+{{ context }}\nQuestion:\n{{ question }}\n\nAnswer in a concise paragraph or two with citation \
+markers:\n";
+
+const CHAT_SYSTEM_TEMPLATE: &str = "You are Noema, a local-first knowledge assistant having a \
+conversation about the user's notes.\nAnswer the latest question using ONLY the numbered \
+excerpts given with it and the conversation so far.\nEach excerpt is tagged as [title] or \
+[body]. Treat title excerpts as metadata and prefer body excerpts for factual grounding.\nIf \
+context is insufficient, say you don't know.\nCite supporting excerpts inline as [1], [2], \
+etc.\nDo not mention note file names or paths unless the user explicitly asks for them.";
+
+const CHAT_TURN_TEMPLATE: &str = "Context:\n{{ context }}\nQuestion:\n{{ question }}";
+
+const CONDENSE_TEMPLATE: &str = "Rewrite the follow-up question so it can be understood \
+without the conversation: resolve pronouns and references such as \"it\" or \"last year\" \
+using the conversation. Keep the user's wording otherwise. If the question already stands on \
+its own, repeat it unchanged. Reply with the question only.\n\nConversation:\n\
+{% for m in history %}{{ m.speaker }}: {{ m.content }}\n{% endfor %}\n\
+Follow-up question: {{ question }}\n\nStandalone question:";
+
+const HYDE_TEMPLATE: &str = "Write a short passage, as it might appear in someone's personal \
+notes, that answers the question below. Invent plausible details if needed; do not say you \
+don't know. Reply with the passage only.\n\nQuestion: {{ question }}\n\nPassage:";
+
+const PARAPHRASE_TEMPLATE: &str = "Write {{ n }} different rephrasings of the search query \
+below, using other words a note about it might contain. One rephrasing per line, no \
+numbering, nothing else.\n\nQuery: {{ question }}\n\nRephrasings:";
+
+const RERANK_TEMPLATE: &str = "Rate how relevant the note excerpt is to the question, from 0 \
+(unrelated) to 10 (directly answers it). Reply with the number only.\n\nQuestion: \
+{{ question }}\n\nExcerpt:\n{{ document }}\n\nRelevance:";
+
+const VERIFY_TEMPLATE: &str = "Does the source text support the claim? Answer with one word: \
+yes, partly or no.\n\nSource text:\n{% for s in sources %}{{ s }}\n\n{% endfor %}\
+Claim: {{ claim }}\n\nAnswer:";
+
+/// What a template is for, which fixes the variables it is rendered with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    /// Single-turn answer over numbered excerpts.
+    Ask,
+    /// System message of a chat session.
+    ChatSystem,
+    /// User message of a chat turn: the question with its numbered excerpts.
+    ChatTurn,
+    /// Rewrite of a follow-up into a standalone question.
+    Condense,
+    /// Hypothetical answer passage for HyDE.
+    Hyde,
+    /// Rephrasings of a search query.
+    Paraphrase,
+    /// Relevance grade of one search result, for reranking.
+    Rerank,
+    /// Whether cited sources support a sentence of an answer.
+    Verify,
+}
+
+impl TemplateKind {
+    pub const ALL: [TemplateKind; 8] = [
+        TemplateKind::Ask,
+        TemplateKind::ChatSystem,
+        TemplateKind::ChatTurn,
+        TemplateKind::Condense,
+        TemplateKind::Hyde,
+        TemplateKind::Paraphrase,
+        TemplateKind::Rerank,
+        TemplateKind::Verify,
+    ];
+
+    /// Name of the default template of this kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            TemplateKind::Ask => "ask",
+            TemplateKind::ChatSystem => "chat_system",
+            TemplateKind::ChatTurn => "chat_turn",
+            TemplateKind::Condense => "condense",
+            TemplateKind::Hyde => "hyde",
+            TemplateKind::Paraphrase => "paraphrase",
+            TemplateKind::Rerank => "rerank",
+            TemplateKind::Verify => "verify",
+        }
+    }
+
+    /// Kind of the template called `name`: the kind's name, or it followed by `-<variant>`.
+    pub fn of(name: &str) -> Option<TemplateKind> {
+        let base = name.split_once('-').map_or(name, |(base, _)| base);
+        Self::ALL.into_iter().find(|k| k.name() == base)
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            TemplateKind::Ask => ASK_TEMPLATE,
+            TemplateKind::ChatSystem => CHAT_SYSTEM_TEMPLATE,
+            TemplateKind::ChatTurn => CHAT_TURN_TEMPLATE,
+            TemplateKind::Condense => CONDENSE_TEMPLATE,
+            TemplateKind::Hyde => HYDE_TEMPLATE,
+            TemplateKind::Paraphrase => PARAPHRASE_TEMPLATE,
+            TemplateKind::Rerank => RERANK_TEMPLATE,
+            TemplateKind::Verify => VERIFY_TEMPLATE,
+        }
+    }
+
+    /// Values a template of this kind is validated with.
+    fn sample(self) -> Value {
+        let date = "2024-01-31";
+        match self {
+            TemplateKind::Ask | TemplateKind::ChatTurn => {
+                let source = TemplateSource {
+                    n: 1,
+                    title: "Rent".to_string(),
+                    path: "finance/rent.md".to_string(),
+                    kind: "body",
+                    text: "Rent 2024: 14k".to_string(),
+                    tags: vec!["finance".to_string()],
+                    note_type: Some("log".to_string()),
+                    date: Some(date.to_string()),
+                    location: None,
+                };
+                context! {
+                    question => "What did I spend on rent?",
+                    context => "[1][body]\nRent 2024: 14k\n\n",
+                    sources => vec![source],
+                    date,
+                }
+            }
+            TemplateKind::ChatSystem => context! { date },
+            TemplateKind::Condense => context! {
+                question => "and the year before?",
+                history => vec![TemplateMessage {
+                    role: ChatRole::User,
+                    speaker: "User",
+                    content: "What did I spend on rent in 2024?".to_string(),
+                }],
+                date,
+            },
+            TemplateKind::Hyde => context! { question => "rent", date },
+            TemplateKind::Paraphrase => context! { question => "rent", n => 3, date },
+            TemplateKind::Rerank => context! {
+                question => "What did I spend on rent?",
+                document => "Rent 2024: 14k",
+                date,
+            },
+            TemplateKind::Verify => context! {
+                claim => "Rent was 14k in 2024.",
+                sources => vec!["Rent 2024: 14k"],
+                date,
+            },
+        }
+    }
+}
+
+/// One excerpt as seen by an `ask` template.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateSource {
+    /// Citation number, as in `[n]`.
+    pub n: usize,
+    pub title: String,
+    pub path: String,
+    /// `title` or `body`.
+    pub kind: &'static str,
+    pub text: String,
+    pub tags: Vec<String>,
+    pub note_type: Option<String>,
+    pub date: Option<String>,
+    /// Page or section of a document, e.g. `page 4 of insurance.pdf`.
+    pub location: Option<String>,
+}
+
+impl TemplateSource {
+    /// Excerpt `n` from `chunk`, titled after its file name.
+    pub fn from_chunk(n: usize, chunk: &Chunk) -> Self {
+        Self {
+            n,
+            title: chunk
+                .note_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: chunk.note_path.display().to_string(),
+            kind: match chunk.kind {
+                ChunkKind::Title => "title",
+                ChunkKind::Body => "body",
+            },
+            text: chunk.text.clone(),
+            tags: chunk.meta.tags.clone(),
+            note_type: chunk.meta.note_type.clone(),
+            date: chunk.meta.date.clone(),
+            location: chunk.location.as_ref().map(|l| {
+                let file = chunk.note_path.file_name().unwrap_or_default();
+                l.describe(&file.to_string_lossy())
+            }),
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct TemplateMessage {
+    role: ChatRole,
+    speaker: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("failed to read templates from {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "template {name:?} does not match any kind (ask, chat_system, chat_turn, condense, \
+         hyde, paraphrase, rerank, verify)"
+    )]
+    UnknownKind { name: String },
+    #[error("invalid template {name:?}: {source}")]
+    Invalid {
+        name: String,
+        source: minijinja::Error,
+    },
+    #[error("no template named {0:?}")]
+    UnknownTemplate(String),
+    #[error("template {name:?} is not a {expected} template")]
+    WrongKind {
+        name: String,
+        expected: &'static str,
+    },
+    #[error("failed to render template {name:?}: {source}")]
+    Render {
+        name: String,
+        source: minijinja::Error,
+    },
+}
+
+/// The templates available for prompts: the built-ins plus any loaded from disk.
+#[derive(Debug, Clone)]
+pub struct PromptTemplates {
+    env: Environment<'static>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl PromptTemplates {
+    /// Only the built-in templates.
+    pub fn builtin() -> Self {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_keep_trailing_newline(true);
+        for kind in TemplateKind::ALL {
+            env.add_template(kind.name(), kind.builtin())
+                .expect("built-in templates parse");
+        }
+        Self { env }
+    }
+
+    /// The built-ins overridden by `<name>.jinja` files in `dirs`, later directories taking
+    /// precedence. Directories that do not exist are skipped.
+    pub fn load<P: AsRef<Path>>(dirs: &[P]) -> Result<Self, TemplateError> {
+        let mut templates = Self::builtin();
+        for dir in dirs {
+            templates.add_dir(dir.as_ref())?;
+        }
+        Ok(templates)
+    }
+
+    /// Templates from the app data dir and, if given, the vault at `vault_root`.
+    pub fn load_default(vault_root: Option<&Path>) -> Result<Self, TemplateError> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        if let Some(dir) = app_data_dir() {
+            dirs.push(dir.join(TEMPLATES_DIRNAME));
+        }
+        if let Some(root) = vault_root {
+            dirs.push(root.join(VAULT_TEMPLATES_DIR));
+        }
+        Self::load(&dirs)
+    }
+
+    /// Add or replace the template `name`, checking that it renders with the variables of
+    /// its kind.
+    pub fn add(&mut self, name: &str, source: &str) -> Result<(), TemplateError> {
+        let kind = TemplateKind::of(name).ok_or_else(|| TemplateError::UnknownKind {
+            name: name.to_string(),
+        })?;
+        let invalid = |source| TemplateError::Invalid {
+            name: name.to_string(),
+            source,
+        };
+        let mut candidate = self.env.clone();
+        candidate
+            .add_template_owned(name.to_string(), source.to_string())
+            .map_err(invalid)?;
+        candidate
+            .get_template(name)
+            .and_then(|t| t.render(kind.sample()))
+            .map_err(invalid)?;
+        self.env = candidate;
+        Ok(())
+    }
+
+    /// Names of all templates, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.env.templates().map(|(n, _)| n.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    /// Names of the templates of `kind`, sorted; the default one is always present.
+    pub fn names_of(&self, kind: TemplateKind) -> Vec<String> {
+        self.names()
+            .into_iter()
+            .filter(|n| TemplateKind::of(n) == Some(kind))
+            .collect()
+    }
+
+    /// Prompt of a single-turn question over numbered excerpts, rendered with `template`
+    /// (an `ask` template; default `ask`).
+    pub fn render_ask(
+        &self,
+        template: Option<&str>,
+        context: &str,
+        question: &str,
+        sources: &[TemplateSource],
+    ) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::Ask,
+            template,
+            context! { context, question, sources, date => today() },
+        )
+    }
+
+    /// System message of a chat session, rendered with `template` (a `chat_system`
+    /// template; default `chat_system`).
+    pub fn render_chat_system(&self, template: Option<&str>) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::ChatSystem,
+            template,
+            context! { date => today() },
+        )
+    }
+
+    /// User message of a chat turn: `question` with the numbered excerpts in `context`.
+    pub fn render_chat_turn(
+        &self,
+        context: &str,
+        question: &str,
+        sources: &[TemplateSource],
+    ) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::ChatTurn,
+            None,
+            context! { context, question => question.trim(), sources, date => today() },
+        )
+    }
+
+    /// Prompt to rewrite `question` so it can be understood without `history`.
+    pub fn render_condense(
+        &self,
+        history: &[ChatMessage],
+        question: &str,
+    ) -> Result<String, TemplateError> {
+        let history: Vec<TemplateMessage> = history
+            .iter()
+            .filter_map(|m| {
+                let speaker = match m.role {
+                    ChatRole::User => "User",
+                    ChatRole::Assistant => "Assistant",
+                    ChatRole::System => return None,
+                };
+                Some(TemplateMessage {
+                    role: m.role,
+                    speaker,
+                    content: m.content.trim().to_string(),
+                })
+            })
+            .collect();
+        self.render(
+            TemplateKind::Condense,
+            None,
+            context! { history, question => question.trim(), date => today() },
+        )
+    }
+
+    /// Prompt for a hypothetical passage answering `question`.
+    pub fn render_hyde(&self, question: &str) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::Hyde,
+            None,
+            context! { question => question.trim(), date => today() },
+        )
+    }
+
+    /// Prompt for `n` rephrasings of `question`.
+    pub fn render_paraphrase(&self, question: &str, n: usize) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::Paraphrase,
+            None,
+            context! { question => question.trim(), n, date => today() },
+        )
+    }
+
+    /// Prompt grading how relevant `document` is to `question`.
+    pub fn render_rerank(&self, question: &str, document: &str) -> Result<String, TemplateError> {
+        self.render(
+            TemplateKind::Rerank,
+            None,
+            context! { question, document, date => today() },
+        )
+    }
+
+    /// Prompt asking whether `sources` support `claim`.
+    pub fn render_verify(&self, claim: &str, sources: &[&str]) -> Result<String, TemplateError> {
+        let sources: Vec<&str> = sources.iter().map(|s| s.trim()).collect();
+        self.render(
+            TemplateKind::Verify,
+            None,
+            context! { claim => claim.trim(), sources, date => today() },
+        )
+    }
+
+    fn render(
+        &self,
+        kind: TemplateKind,
+        template: Option<&str>,
+        vars: Value,
+    ) -> Result<String, TemplateError> {
+        let name = template.unwrap_or(kind.name());
+        if TemplateKind::of(name) != Some(kind) {
+            return Err(TemplateError::WrongKind {
+                name: name.to_string(),
+                expected: kind.name(),
+            });
+        }
+        let tmpl = self
+            .env
+            .get_template(name)
+            .map_err(|_| TemplateError::UnknownTemplate(name.to_string()))?;
+        tmpl.render(vars).map_err(|source| TemplateError::Render {
+            name: name.to_string(),
+            source,
+        })
+    }
+
+    fn add_dir(&mut self, dir: &Path) -> Result<(), TemplateError> {
+        let io_err = |source| TemplateError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(io_err(e)),
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == TEMPLATE_EXTENSION))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let source = std::fs::read_to_string(&path).map_err(|source| TemplateError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            self.add(name, &source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Today's date (UTC) as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        / 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_render_default_prompts() {
+        let templates = PromptTemplates::builtin();
+        let prompt = templates
+            .render_ask(
+                None,
+                "[1][body]\nRent 2024: 14k\n\n",
+                "What about rent?",
+                &[],
+            )
+            .unwrap();
+        assert!(prompt.starts_with("You are Noema, a local-first knowledge assistant.\n"));
+        assert!(prompt
+            .contains("Context:\n[1][body]\nRent 2024: 14k\n\n\nQuestion:\nWhat about rent?\n\n"));
+        assert!(prompt.ends_with("citation markers:\n"));
+        assert_eq!(
+            templates.render_paraphrase(" rent ", 2).unwrap(),
+            "Write 2 different rephrasings of the search query below, using other words a note \
+             about it might contain. One rephrasing per line, no numbering, nothing else.\n\n\
+             Query: rent\n\nRephrasings:"
+        );
+        assert_eq!(
+            templates
+                .render_verify("Rent was 14k [1].", &[" Rent 2024: 14k\n"])
+                .unwrap(),
+            "Does the source text support the claim? Answer with one word: yes, partly or \
+             no.\n\nSource text:\nRent 2024: 14k\n\nClaim: Rent was 14k [1].\n\nAnswer:"
+        );
+        assert_eq!(
+            templates
+                .render_chat_turn("[1][body]\nRent 2024: 14k\n", " and 2023? ", &[])
+                .unwrap(),
+            "Context:\n[1][body]\nRent 2024: 14k\n\nQuestion:\nand 2023?"
+        );
+        assert!(templates
+            .render_rerank("rent?", "Rent 2024: 14k")
+            .unwrap()
+            .ends_with("Question: rent?\n\nExcerpt:\nRent 2024: 14k\n\nRelevance:"));
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_753), (2024, 1, 31));
+    }
+
+    #[test]
+    fn loads_overrides_and_rejects_invalid_templates() {
+        let dir = std::env::temp_dir().join(format!("noema-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("ask-brief.jinja"),
+            "{% for s in sources %}[{{ s.n }}] {{ s.title }} ({{ s.date or \"undated\" }}): {{ s.text }}\n{% endfor %}\
+             {{ date }} Q: {{ question }}",
+        )
+        .unwrap();
+        let templates = PromptTemplates::load(&[&dir]).unwrap();
+        assert_eq!(
+            templates.names_of(TemplateKind::Ask),
+            vec!["ask", "ask-brief"]
+        );
+
+        let chunk = Chunk {
+            text: "Rent 2024: 14k".to_string(),
+            kind: ChunkKind::Body,
+            note_path: PathBuf::from("finance/rent.md"),
+            index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
+        };
+        let sources = [TemplateSource::from_chunk(1, &chunk)];
+        let prompt = templates
+            .render_ask(Some("ask-brief"), "", "rent?", &sources)
+            .unwrap();
+        assert!(prompt.starts_with("[1] rent (undated): Rent 2024: 14k\n"));
+        assert!(matches!(
+            templates.render_ask(Some("hyde"), "", "rent?", &[]),
+            Err(TemplateError::WrongKind { .. })
+        ));
+
+        std::fs::write(dir.join("hyde.jinja"), "Passage about {{ questoin }}").unwrap();
+        assert!(matches!(
+            PromptTemplates::load(&[&dir]),
+            Err(TemplateError::Invalid { .. })
+        ));
+        std::fs::write(dir.join("hyde.jinja"), "{% if question %}").unwrap();
+        assert!(matches!(
+            PromptTemplates::load(&[&dir]),
+            Err(TemplateError::Invalid { .. })
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
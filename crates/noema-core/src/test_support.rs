@@ -0,0 +1,97 @@
+//! Fake model clients shared by unit tests.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_util::{stream, StreamExt};
+
+use crate::chat::ChatMessage;
+use crate::provider::{ChatModel, Embedder, GenerateOptions, ModelError, TokenStream};
+
+/// Chat model that answers a prompt with `reply(prompt)`. Chat requests are answered as
+/// the last message; streams yield the reply word by word.
+pub(crate) struct FakeChat<F>(pub F);
+
+/// A chat model for tests that never generate: every request fails.
+pub(crate) fn no_chat() -> FakeChat<impl Fn(&str) -> Result<String, ModelError> + Send + Sync> {
+    FakeChat(|_: &str| Err(ModelError::Unsupported("no chat model in this test")))
+}
+
+impl<F> ChatModel for FakeChat<F>
+where
+    F: Fn(&str) -> Result<String, ModelError> + Send + Sync,
+{
+    async fn generate(
+        &self,
+        _model: &str,
+        prompt: &str,
+        _options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        (self.0)(prompt)
+    }
+
+    async fn generate_stream(
+        &self,
+        _model: &str,
+        prompt: &str,
+        _options: &GenerateOptions,
+    ) -> Result<TokenStream, ModelError> {
+        let reply = (self.0)(prompt)?;
+        let tokens: Vec<_> = reply.split_inclusive(' ').map(|t| t.to_string()).collect();
+        // Yield between tokens like a network stream, so the consumer can be cancelled.
+        Ok(Box::pin(stream::iter(tokens).then(|t| async {
+            tokio::task::yield_now().await;
+            Ok(t)
+        })))
+    }
+
+    async fn chat(
+        &self,
+        _model: &str,
+        messages: &[ChatMessage],
+        _options: &GenerateOptions,
+    ) -> Result<String, ModelError> {
+        (self.0)(messages.last().map_or("", |m| m.content.as_str()))
+    }
+}
+
+/// Embedder that embeds each text as `embed(text)` and counts the texts it was sent.
+pub(crate) struct FakeEmbedder<F> {
+    embed: F,
+    embedded: AtomicUsize,
+}
+
+impl<F> FakeEmbedder<F>
+where
+    F: Fn(&str) -> Vec<f32> + Send + Sync,
+{
+    pub(crate) fn new(embed: F) -> Self {
+        Self {
+            embed,
+            embedded: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of texts embedded so far.
+    pub(crate) fn embedded(&self) -> usize {
+        self.embedded.load(Ordering::Relaxed)
+    }
+}
+
+impl<F> Embedder for FakeEmbedder<F>
+where
+    F: Fn(&str) -> Vec<f32> + Send + Sync,
+{
+    fn model_name(&self) -> &str {
+        "fake"
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, ModelError> {
+        self.embedded.fetch_add(1, Ordering::Relaxed);
+        Ok((self.embed)(text))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ModelError> {
+        self.embedded.fetch_add(texts.len(), Ordering::Relaxed);
+        Ok(texts.iter().map(|t| (self.embed)(t)).collect())
+    }
+}
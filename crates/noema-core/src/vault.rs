@@ -0,0 +1,270 @@
+//! Named vaults: separate notes folders, each with its own model settings and index file.
+//!
+//! Vaults are listed in config as `[[vaults]]` tables. A config without vaults keeps
+//! working as before: its `notes_root` acts as a single implicit vault named
+//! [DEFAULT_VAULT_NAME] using the default index path. Adding the first vault turns that
+//! implicit vault into an explicit one so its index is not lost.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_data::app_data_dir;
+use crate::config::{load_config, save_config, Config, ConfigError, ModelConfig};
+use crate::persisted_index::default_index_path;
+
+/// Name of the implicit vault of a config that predates vaults.
+pub const DEFAULT_VAULT_NAME: &str = "default";
+
+/// One vault as stored in config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultConfig {
+    pub name: String,
+    /// Notes folder of this vault.
+    pub root: String,
+    /// Model overrides for this vault; unset fields fall back to the global `[models]`.
+    #[serde(default)]
+    pub models: ModelConfig,
+    /// Index file. Default: `<app_data_dir>/vaults/<name>/index.bin`.
+    pub index_path: Option<String>,
+}
+
+/// A vault with its paths resolved.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vault {
+    pub name: String,
+    pub root: PathBuf,
+    /// `None` if the vault uses the default index path and the app data directory cannot
+    /// be determined.
+    pub index_path: Option<PathBuf>,
+    /// Effective model settings: vault overrides merged over the global ones.
+    pub models: ModelConfig,
+    pub active: bool,
+}
+
+impl Config {
+    /// All vaults with resolved paths, including the implicit one of a pre-vault config.
+    pub fn vaults(&self) -> Vec<Vault> {
+        if self.vaults.is_empty() {
+            let Some(root) = self.notes_root.as_deref().filter(|r| !r.is_empty()) else {
+                return Vec::new();
+            };
+            return vec![Vault {
+                name: DEFAULT_VAULT_NAME.to_string(),
+                root: PathBuf::from(root),
+                index_path: default_index_path(),
+                models: self.models.clone(),
+                active: true,
+            }];
+        }
+        let active = self.active_vault_name();
+        self.vaults
+            .iter()
+            .map(|v| Vault {
+                name: v.name.clone(),
+                root: PathBuf::from(&v.root),
+                index_path: match v.index_path.as_deref() {
+                    Some(p) => Some(PathBuf::from(p)),
+                    None => vault_index_path(&v.name),
+                },
+                models: v.models.merged_over(&self.models),
+                active: Some(v.name.as_str()) == active,
+            })
+            .collect()
+    }
+
+    /// The vault that commands operate on: the configured active vault, else the first.
+    pub fn active_vault(&self) -> Option<Vault> {
+        self.vaults().into_iter().find(|v| v.active)
+    }
+
+    /// Model settings of the active vault, or the global ones without vaults.
+    pub fn active_models(&self) -> ModelConfig {
+        self.active_vault()
+            .map(|v| v.models)
+            .unwrap_or_else(|| self.models.clone())
+    }
+
+    fn active_vault_name(&self) -> Option<&str> {
+        self.active_vault
+            .as_deref()
+            .filter(|name| self.vaults.iter().any(|v| v.name == *name))
+            .or_else(|| self.vaults.first().map(|v| v.name.as_str()))
+    }
+
+    /// Add a vault. `root` must be an existing directory. The first vault added to a
+    /// pre-vault config also keeps the old root as the [DEFAULT_VAULT_NAME] vault.
+    pub fn add_vault(&mut self, name: &str, root: &Path) -> Result<(), ConfigError> {
+        let name = validate_vault_name(name)?;
+        let root = root.canonicalize().map_err(ConfigError::Canonicalize)?;
+        if !root.is_dir() {
+            return Err(ConfigError::NotADirectory(root));
+        }
+        if self.vaults.is_empty() {
+            if let Some(legacy) = self.notes_root.clone().filter(|r| !r.is_empty()) {
+                self.vaults.push(VaultConfig {
+                    name: DEFAULT_VAULT_NAME.to_string(),
+                    root: legacy,
+                    models: ModelConfig::default(),
+                    index_path: default_index_path().map(|p| p.to_string_lossy().into_owned()),
+                });
+                self.active_vault = Some(DEFAULT_VAULT_NAME.to_string());
+            }
+        }
+        if self.vaults.iter().any(|v| v.name == name) {
+            return Err(ConfigError::DuplicateVault(name));
+        }
+        self.vaults.push(VaultConfig {
+            name: name.clone(),
+            root: root.to_string_lossy().into_owned(),
+            models: ModelConfig::default(),
+            index_path: None,
+        });
+        if self.active_vault.is_none() {
+            self.active_vault = Some(name);
+        }
+        Ok(())
+    }
+
+    /// Remove a vault from config. Its notes are untouched. If it was active, the first
+    /// remaining vault becomes active.
+    pub fn remove_vault(&mut self, name: &str) -> Result<VaultConfig, ConfigError> {
+        let pos = self
+            .vaults
+            .iter()
+            .position(|v| v.name == name)
+            .ok_or_else(|| ConfigError::UnknownVault(name.to_string()))?;
+        let removed = self.vaults.remove(pos);
+        if self.active_vault.as_deref() == Some(name) {
+            self.active_vault = self.vaults.first().map(|v| v.name.clone());
+        }
+        Ok(removed)
+    }
+
+    /// Make `name` the active vault.
+    pub fn switch_vault(&mut self, name: &str) -> Result<(), ConfigError> {
+        if !self.vaults.iter().any(|v| v.name == name) {
+            return Err(ConfigError::UnknownVault(name.to_string()));
+        }
+        self.active_vault = Some(name.to_string());
+        Ok(())
+    }
+}
+
+/// Default index path of a vault: `<app_data_dir>/vaults/<name>/index.bin`. The directory
+/// is created when the index is first saved.
+pub fn vault_index_path(name: &str) -> Option<PathBuf> {
+    Some(app_data_dir()?.join("vaults").join(name).join("index.bin"))
+}
+
+/// List configured vaults.
+pub fn list_vaults() -> Result<Vec<Vault>, ConfigError> {
+    Ok(load_config()?.vaults())
+}
+
+/// The active vault, if any notes folder is configured.
+pub fn active_vault() -> Result<Option<Vault>, ConfigError> {
+    Ok(load_config()?.active_vault())
+}
+
+/// Index path of the active vault, falling back to the default index path.
+pub fn active_index_path() -> Result<Option<PathBuf>, ConfigError> {
+    Ok(active_vault()?
+        .and_then(|v| v.index_path)
+        .or_else(default_index_path))
+}
+
+/// Add a vault and persist the config.
+pub fn add_vault(name: &str, root: &Path) -> Result<(), ConfigError> {
+    let mut config = load_config()?;
+    config.add_vault(name, root)?;
+    save_config(&config)
+}
+
+/// Remove a vault and persist the config. Also deletes its index if it lives in the app
+/// data directory; the notes folder is never touched.
+pub fn remove_vault(name: &str) -> Result<(), ConfigError> {
+    let mut config = load_config()?;
+    let removed = config.remove_vault(name)?;
+    save_config(&config)?;
+    if removed.index_path.is_none() {
+        if let Some(dir) = vault_index_path(name).as_deref().and_then(Path::parent) {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir).ok();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Switch the active vault and persist the config.
+pub fn switch_vault(name: &str) -> Result<(), ConfigError> {
+    let mut config = load_config()?;
+    config.switch_vault(name)?;
+    save_config(&config)
+}
+
+/// Vault names become directory names, so keep them to one plain path component.
+fn validate_vault_name(name: &str) -> Result<String, ConfigError> {
+    let name = name.trim();
+    let ok = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', ':'])
+        && !name.chars().any(char::is_control);
+    if ok {
+        Ok(name.to_string())
+    } else {
+        Err(ConfigError::InvalidVaultName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_vault_keeps_legacy_root() {
+        let dir = std::env::temp_dir();
+        let mut config = Config {
+            notes_root: Some("/old/notes".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.vaults()[0].name, DEFAULT_VAULT_NAME);
+
+        config.add_vault("research", &dir).unwrap();
+        let names: Vec<String> = config.vaults().into_iter().map(|v| v.name).collect();
+        assert_eq!(names, vec!["default", "research"]);
+        assert_eq!(config.active_vault().unwrap().name, "default");
+        let saved: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(saved.vaults, config.vaults);
+        assert!(matches!(
+            config.add_vault("research", &dir),
+            Err(ConfigError::DuplicateVault(_))
+        ));
+
+        config.switch_vault("research").unwrap();
+        config.remove_vault("research").unwrap();
+        assert_eq!(config.active_vault().unwrap().name, "default");
+        assert!(config.add_vault("../x", &dir).is_err());
+    }
+
+    #[test]
+    fn vault_models_override_global() {
+        let mut config = Config::default();
+        config.models.chat_model = Some("llama3.1".to_string());
+        config.models.default_k = Some(4);
+        config.vaults.push(VaultConfig {
+            name: "work".to_string(),
+            root: "/work".to_string(),
+            models: ModelConfig {
+                chat_model: Some("qwen2.5".to_string()),
+                ..Default::default()
+            },
+            index_path: Some("/tmp/work.bin".to_string()),
+        });
+        let models = config.active_models();
+        assert_eq!(models.chat_model.as_deref(), Some("qwen2.5"));
+        assert_eq!(models.default_k, Some(4));
+    }
+}
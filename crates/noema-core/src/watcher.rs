@@ -7,13 +7,15 @@ use std::time::Duration;
 use notify_debouncer_mini::notify;
 use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
 
-use crate::notes::{scan_notes, Note};
+use crate::notes::{scan_notes_with, FormatRegistry, NoteScan};
 
-/// Watches `root` and calls `on_change` whenever files change (debounced).
+/// Watches `root` and calls `on_change` with the notes `formats` reads, and the files it
+/// skipped, whenever files change (debounced).
 /// Blocks until the watcher is stopped (e.g. Ctrl+C). Returns Ok when stopped, Err on setup failure.
 pub fn watch_notes(
     root: &Path,
-    on_change: impl Fn(Result<Vec<Note>, crate::notes::ScanError>) + Send + 'static,
+    formats: FormatRegistry,
+    on_change: impl Fn(Result<NoteScan, crate::notes::ScanError>) + Send + 'static,
 ) -> Result<(), WatchError> {
     if !root.is_dir() {
         return Err(WatchError::NotADirectory(root.to_path_buf()));
@@ -24,7 +26,7 @@ pub fn watch_notes(
     let debounce = Duration::from_millis(400);
     let mut debouncer = new_debouncer(debounce, move |res: DebounceEventResult| match res {
         Ok(_) => {
-            let notes = scan_notes(&root_for_callback);
+            let notes = scan_notes_with(&root_for_callback, &formats);
             on_change(notes);
         }
         Err(e) => eprintln!("Watcher error: {}", e),
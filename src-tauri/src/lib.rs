@@ -1,22 +1,43 @@
 //! Tauri app entry point. Exposes noema-core commands to the frontend.
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::{Arc, Mutex};
 
 use noema_core::{
-    build_memory_overview, build_persisted_index, default_index_path, extract_note_signals,
-    get_notes_root as core_get_notes_root, load_config, scan_notes, set_notes_root as core_set_notes_root,
-    ChunkKind, IndexSettings, MemoryOverview, OllamaClient, PersistedIndex, DEFAULT_BASE_URL, DEFAULT_CHAT_MODEL,
-    DEFAULT_EMBED_MODEL, DEFAULT_MAX_CHARS, INDEX_SCHEMA_VERSION,
+    active_index_path, add_vault as core_add_vault, build_memory_overview, build_persisted_index_resumable, default_index_path,
+    extract_note_signals, list_vaults as core_list_vaults, remove_vault as core_remove_vault, switch_vault as core_switch_vault, legacy_index_path,
+    get_notes_root as core_get_notes_root, load_config, scan_notes_with, NoteScan, ScanError, set_notes_root as core_set_notes_root,
+    rerank, stream_completion, LinkEdge, LinkGraph, UnresolvedLink, PromptTemplates, TemplateSource, ground_answer, GroundedAnswer, GroundingMethod, embed_expansions, generate_expansions, ExpansionTexts, QueryExpansion, context_length_for, Chunk, chat_messages, PromptBudget, condense_question, ChatMessage, ChatSession, ChatSessionSummary, CheckpointStatus, ChunkKind, Config, EmbedProgress, IndexStaging, EmbedSource, EmbeddingCache, GenerateOptions, ModelConfig, SessionStore, Vault, HybridParams, IndexSettings, MemoryOverview, CachedEmbedder, ChatModel, Embedder, LlmReranker, ModelBackend, ModelClient, ModelDetails, EmbeddingProbe, ModelKind, OllamaClient, PersistedIndex, SearchFilter, SearchHit, SearchOptions, SourceSpan, DEFAULT_BASE_URL, DEFAULT_CHAT_MODEL,
+    DEFAULT_MAX_CHARS, INDEX_SCHEMA_VERSION,
 };
+use futures_util::future::{AbortHandle, Abortable, Aborted};
 use serde::Serialize;
+use tauri::{AppHandle, Emitter, State};
 
+/// Cutoffs on a source's search relevance (see [SearchHit::relevance]): an absolute floor,
+/// and a fraction of the best source's relevance.
 const MIN_ASK_SOURCE_SCORE: f32 = 0.25;
 const MIN_ASK_SOURCE_RATIO: f32 = 0.8;
+/// Minimum reranker score for an ask source, used instead of the relevance thresholds above
+/// when reranking succeeded.
+const MIN_RERANK_RELEVANCE: f32 = 0.3;
+
+/// The saved config. An invalid config file is reported rather than replaced by defaults.
+fn load_settings() -> Result<Config, String> {
+    load_config().map_err(|e| e.to_string())
+}
+
+/// Index path of the active vault.
+fn index_path() -> Result<PathBuf, String> {
+    active_index_path()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Could not determine index path".to_string())
+}
 
 fn notes_root() -> Result<PathBuf, String> {
-    match core_get_notes_root() {
+    match core_get_notes_root().map_err(|e| e.to_string())? {
         Some(p) => {
             if p.is_dir() {
                 Ok(p)
@@ -28,6 +49,59 @@ fn notes_root() -> Result<PathBuf, String> {
     }
 }
 
+/// Notes under `root` in the formats enabled in the config, and the files skipped.
+fn scan_notes(root: &Path) -> Result<NoteScan, String> {
+    let formats = load_settings()?.indexing.formats();
+    scan_notes_with(root, &formats).map_err(|e| format!("Failed to scan notes: {}", e))
+}
+
+/// A file left out of a scan, for display.
+#[derive(Debug, Serialize)]
+struct SkippedFile {
+    path: String,
+    reason: String,
+}
+
+fn skipped_files(root: &Path, skipped: &[(PathBuf, ScanError)]) -> Vec<SkippedFile> {
+    skipped
+        .iter()
+        .map(|(path, e)| SkippedFile {
+            path: make_relative(root, path),
+            reason: match e {
+                ScanError::Read(_, e) => e.to_string(),
+                ScanError::Extract(_, e) => e.to_string(),
+                e => e.to_string(),
+            },
+        })
+        .collect()
+}
+
+/// Load a persisted index. The default index is migrated from a legacy `index.json` to the
+/// binary format if needed; vault indexes never had a legacy form.
+fn load_index(index_path: &Path) -> Result<PersistedIndex, noema_core::PersistedIndexError> {
+    match legacy_index_path() {
+        Some(legacy) if default_index_path().as_deref() == Some(index_path) => {
+            PersistedIndex::load_or_migrate(index_path, legacy)
+        }
+        _ => PersistedIndex::load_from_file(index_path),
+    }
+}
+
+/// Open the embedding cache in the app data directory, unless disabled in config.
+fn open_embed_cache(cfg: &Config) -> Option<Arc<Mutex<EmbeddingCache>>> {
+    let max_bytes = cfg.cache.embed_cache_bytes()?;
+    EmbeddingCache::open_default(max_bytes).map(|c| Arc::new(Mutex::new(c)))
+}
+
+/// Best effort: a cache that fails to save only costs re-embedding later.
+fn save_embed_cache(cache: Option<&Arc<Mutex<EmbeddingCache>>>) {
+    if let Some(cache) = cache {
+        if let Ok(mut cache) = cache.lock() {
+            let _ = cache.save();
+        }
+    }
+}
+
 fn make_relative(root: &Path, path: &Path) -> String {
     match path.strip_prefix(root) {
         Ok(rel) => rel.to_string_lossy().into_owned(),
@@ -102,13 +176,19 @@ pub struct NoteDetail {
 
 #[derive(Serialize)]
 pub struct QueryResult {
+    /// Name of the vault the result came from.
+    pub vault: String,
     pub note_path: String,
     pub score: f32,
     pub preview: String,
     pub text: String,
+    /// Byte offsets and line numbers of the chunk in the note file, if known.
+    pub span: Option<SourceSpan>,
+    /// Page or section of a document, e.g. "page 4 of insurance.pdf".
+    pub location: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct AskSource {
     pub note_path: String,
     pub title: Option<String>,
@@ -116,6 +196,10 @@ pub struct AskSource {
     pub kind: String,
     pub chunk_index: usize,
     pub score: f32,
+    /// Byte offsets and line numbers of the excerpt in the note file, if known.
+    pub span: Option<SourceSpan>,
+    /// Page or section of a document, e.g. "page 4 of insurance.pdf".
+    pub location: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -123,6 +207,10 @@ pub struct AskResponse {
     pub question: String,
     pub answer: String,
     pub sources: Vec<AskSource>,
+    /// Retrieved sources left out of the prompt to fit the model's context window.
+    pub dropped_sources: Vec<AskSource>,
+    /// Per-sentence citations and support, unless answer checking is off.
+    pub grounding: Option<GroundedAnswer>,
 }
 
 fn parse_frontmatter_title(raw: &str) -> (Option<String>, String) {
@@ -228,17 +316,25 @@ fn snippet_preview(text: &str, max_chars: usize) -> String {
     format!("{}…", preview.trim_end())
 }
 
+/// Drop sources far less relevant than the best one. Relevance is the search's ranking
+/// score rather than the cosine kept in `results`, so exact-term hits from hybrid search
+/// are not dropped for a low cosine.
 fn filter_ask_results_by_score(
     results: Vec<(noema_core::Chunk, f32)>,
+    relevance: impl Fn(&Chunk) -> f32,
 ) -> Vec<(noema_core::Chunk, f32)> {
-    let Some((_, top_score)) = results.first() else {
+    let Some(top_relevance) = results
+        .iter()
+        .map(|(chunk, _)| relevance(chunk))
+        .reduce(f32::max)
+    else {
         return results;
     };
-    let threshold = MIN_ASK_SOURCE_SCORE.max(*top_score * MIN_ASK_SOURCE_RATIO);
+    let threshold = MIN_ASK_SOURCE_SCORE.max(top_relevance * MIN_ASK_SOURCE_RATIO);
     let mut filtered: Vec<_> = results
         .iter()
         .cloned()
-        .filter(|(_, score)| *score >= threshold)
+        .filter(|(chunk, _)| relevance(chunk) >= threshold)
         .collect();
     if filtered.is_empty() {
         if let Some(first) = results.into_iter().next() {
@@ -248,50 +344,103 @@ fn filter_ask_results_by_score(
     filtered
 }
 
-#[tauri::command]
-fn list_chat_models() -> Result<Vec<String>, String> {
-    let output = Command::new("ollama")
-        .arg("list")
-        .output()
-        .map_err(|e| format!("Failed to run `ollama list`: {}", e))?;
-
-    if !output.status.success() {
-        return Err(format!(
-            "`ollama list` exited with status: {}",
-            output.status
-        ));
+/// Rerank `hits` with the configured reranker, if any. Returns the hits and whether the
+/// reranker's order and relevance cutoff were applied; on failure the search order is kept.
+async fn rerank_if_configured(
+    cfg: &Config,
+    templates: &PromptTemplates,
+    query: &str,
+    hits: Vec<(Chunk, f32)>,
+) -> (Vec<(Chunk, f32)>, bool) {
+    let Some(model) = cfg.rerank.model.as_deref().filter(|m| !m.trim().is_empty()) else {
+        return (hits, false);
+    };
+    let mut models = cfg.active_models();
+    if let Some(url) = cfg.rerank.url.clone() {
+        models.chat_url = Some(url);
     }
+    let Ok(client) = ModelClient::for_chat(&models) else {
+        return (hits, false);
+    };
+    let reranker = LlmReranker::new(client, model)
+        .with_mode(cfg.rerank.mode.unwrap_or_default())
+        .with_templates(templates.clone());
+    let reranked = rerank(&reranker, query, hits, &cfg.rerank.params()).await;
+    let Some(relevance) = reranked.relevance else {
+        return (reranked.hits, false);
+    };
+    let min = cfg.rerank.min_relevance.unwrap_or(MIN_RERANK_RELEVANCE);
+    let mut kept: Vec<_> = reranked
+        .hits
+        .iter()
+        .zip(&relevance)
+        .filter(|(_, r)| **r >= min)
+        .map(|(hit, _)| hit.clone())
+        .collect();
+    if kept.is_empty() {
+        kept.extend(reranked.hits.into_iter().take(1));
+    }
+    (kept, true)
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut models = Vec::new();
+/// Completion models on the configured chat server. Only Ollama servers can be listed; for
+/// other backends this is the configured chat model, if any.
+#[tauri::command]
+async fn list_chat_models() -> Result<Vec<String>, String> {
+    let models = load_settings()?.active_models();
+    let backend = models.chat_backend.unwrap_or_default();
+    if backend != ModelBackend::Ollama {
+        return Ok(models.chat_model.into_iter().collect());
+    }
+    let details = ollama_models(models.chat_url.as_deref()).await?;
+    Ok(details
+        .into_iter()
+        .filter(|m| m.kind == ModelKind::Completion)
+        .map(|m| m.name)
+        .collect())
+}
 
-    for (idx, line) in stdout.lines().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-        // Skip header row if present (e.g. "NAME  SIZE  ...").
-        if idx == 0 && trimmed.to_ascii_lowercase().contains("name") {
-            continue;
-        }
-        let name = trimmed.split_whitespace().next().unwrap_or("");
-        if name.is_empty() {
-            continue;
+/// Models on the configured chat and embedding servers (Ollama only), with family,
+/// parameter size, context length and kind.
+#[tauri::command]
+async fn list_models() -> Result<Vec<ModelDetails>, String> {
+    let models = load_settings()?.active_models();
+    let mut urls: Vec<String> = Vec::new();
+    for (backend, url) in [
+        (models.chat_backend, &models.chat_url),
+        (models.embed_backend, &models.embed_url),
+    ] {
+        let backend = backend.unwrap_or_default();
+        let url = url.clone().unwrap_or_else(|| backend.default_url().to_string());
+        if backend == ModelBackend::Ollama && !urls.contains(&url) {
+            urls.push(url);
         }
-        let lower = name.to_ascii_lowercase();
-        // Heuristic: filter out obvious embedding models.
-        if lower.contains("embed") || lower.contains("embedding") {
-            continue;
+    }
+    let mut out: Vec<ModelDetails> = Vec::new();
+    for url in urls {
+        for m in ollama_models(Some(&url)).await? {
+            if !out.iter().any(|o| o.name == m.name) {
+                out.push(m);
+            }
         }
-        models.push(name.to_string());
     }
+    Ok(out)
+}
 
-    Ok(models)
+async fn ollama_models(url: Option<&str>) -> Result<Vec<ModelDetails>, String> {
+    let url = url.unwrap_or(DEFAULT_BASE_URL);
+    let client = OllamaClient::from_url(url).map_err(|e| e.to_string())?;
+    client
+        .list_models()
+        .await
+        .map_err(|e| format!("Failed to list models at {}: {}", url, e))
 }
 
 #[tauri::command]
-fn get_notes_root() -> Option<String> {
-    core_get_notes_root().map(|p| p.to_string_lossy().into_owned())
+fn get_notes_root() -> Result<Option<String>, String> {
+    Ok(core_get_notes_root()
+        .map_err(|e| e.to_string())?
+        .map(|p| p.to_string_lossy().into_owned()))
 }
 
 #[tauri::command]
@@ -304,52 +453,152 @@ fn set_notes_root(path: String) -> Result<String, String> {
     Ok(p.to_string_lossy().into_owned())
 }
 
+#[tauri::command]
+fn list_vaults() -> Result<Vec<Vault>, String> {
+    core_list_vaults().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_vault(name: String, path: String) -> Result<Vec<Vault>, String> {
+    let p = PathBuf::from(path.trim());
+    if !p.is_dir() {
+        return Err(format!("Not a directory: {}", p.display()));
+    }
+    core_add_vault(&name, &p).map_err(|e| format!("Failed to add vault: {}", e))?;
+    list_vaults()
+}
+
+/// Forget a vault. Its notes folder is left untouched.
+#[tauri::command]
+fn remove_vault(name: String) -> Result<Vec<Vault>, String> {
+    core_remove_vault(&name).map_err(|e| format!("Failed to remove vault: {}", e))?;
+    list_vaults()
+}
+
+#[tauri::command]
+fn switch_vault(name: String) -> Result<Vec<Vault>, String> {
+    core_switch_vault(&name).map_err(|e| format!("Failed to switch vault: {}", e))?;
+    list_vaults()
+}
+
 #[tauri::command]
 fn status() -> String {
     noema_core::status().to_string()
 }
 
+/// Event carrying [EmbedProgress] while [rebuild_index] runs.
+const INDEX_PROGRESS_EVENT: &str = "index-progress";
+
+/// Outcome of [rebuild_index].
+#[derive(Debug, Serialize)]
+struct RebuildReport {
+    chunks: usize,
+    /// Files that could not be read and are not in the index.
+    skipped: Vec<SkippedFile>,
+}
+
 #[tauri::command]
-async fn rebuild_index() -> Result<String, String> {
+async fn rebuild_index(app: AppHandle) -> Result<RebuildReport, String> {
     let root = notes_root()?;
-    let notes = scan_notes(&root).map_err(|e| format!("Failed to scan notes: {}", e))?;
-
-    let cfg = load_config();
-    let url = cfg
-        .models
+    let NoteScan { notes, skipped } = scan_notes(&root)?;
+
+    let cfg = load_settings()?;
+    let models = cfg.active_models();
+    let client = ModelClient::for_embedding(&models).map_err(|e| e.to_string())?;
+    let probe = client.probe_embedding().await.map_err(|e| {
+        format!("Embedding model {} is not available: {}", client.model_name(), e)
+    })?;
+    let backend = client.backend();
+    let url = models
         .embed_url
         .clone()
-        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
-    let model = cfg
-        .models
-        .embed_model
-        .clone()
-        .unwrap_or_else(|| DEFAULT_EMBED_MODEL.to_string());
-    let client = OllamaClient::from_url(&url)
-        .map_err(|e| e.to_string())?
-        .with_embed_model(&model);
+        .unwrap_or_else(|| backend.default_url().to_string());
+    let embed_cache = open_embed_cache(&cfg);
+    let embedder = CachedEmbedder::new(client, embed_cache.clone()).with_source(EmbedSource {
+        backend,
+        url: url.clone(),
+        digest: probe.digest.clone(),
+    });
     let settings = IndexSettings {
         notes_root: root.to_string_lossy().into_owned(),
         max_chars: DEFAULT_MAX_CHARS,
         ollama_url: url,
-        embed_model: model,
+        embed_model: embedder.model_name().to_string(),
+        embed_backend: backend,
+        ann: cfg.search.hnsw_params(),
+        quantization: cfg.search.quantization.unwrap_or_default(),
+        embed_dim: Some(probe.dimension),
+        embed_digest: probe.digest,
     };
 
-    let idx = build_persisted_index(notes, &client, settings)
-        .await
-        .map_err(|e| format!("Failed to build index: {}", e))?;
+    let index_path = index_path()?;
+    let staging = IndexStaging::for_index(&index_path)
+        .with_checkpoint_chunks(cfg.indexing.checkpoint_chunks());
+    let report = |progress: &EmbedProgress| {
+        let _ = app.emit(INDEX_PROGRESS_EVENT, progress);
+    };
+    // Resumes from the last checkpoint of an interrupted rebuild, if any.
+    let idx = build_persisted_index_resumable(
+        notes,
+        &embedder,
+        settings,
+        &cfg.indexing.embed_params(),
+        &report,
+        &staging,
+    )
+    .await;
+    // Keep embeddings computed so far even if the build failed part way.
+    save_embed_cache(embed_cache.as_ref());
+    let idx = idx.map_err(|e| format!("Failed to build index: {}", e))?;
     let chunk_count = idx.store.len();
-    let index_path = default_index_path().ok_or("Could not determine index path")?;
-    idx.save_to_file(&index_path)
+    staging
+        .commit(&idx, &index_path)
         .map_err(|e| format!("Failed to save index: {}", e))?;
 
-    Ok(format!("index rebuilt ({} chunks)", chunk_count))
+    Ok(RebuildReport {
+        chunks: chunk_count,
+        skipped: skipped_files(&root, &skipped),
+    })
+}
+
+/// Probe the configured embedding model and check it against the active vault's index:
+/// fails if the model is unavailable, or differs in name, dimension or digest from the one
+/// the index was built with.
+#[tauri::command]
+async fn check_embed_model() -> Result<EmbeddingProbe, String> {
+    let models = load_settings()?.active_models();
+    let client = ModelClient::for_embedding(&models).map_err(|e| e.to_string())?;
+    let probe = client.probe_embedding().await.map_err(|e| {
+        format!("Embedding model {} is not available: {}", client.model_name(), e)
+    })?;
+    let index_path = index_path()?;
+    if let Ok(idx) = load_index(&index_path) {
+        idx.settings
+            .check_embedder(&probe.model, Some(probe.dimension), probe.digest.as_deref())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(probe)
+}
+
+/// Progress of an interrupted rebuild of the active vault's index, if one can be resumed.
+#[tauri::command]
+fn index_checkpoint() -> Result<Option<CheckpointStatus>, String> {
+    let index_path = index_path()?;
+    let root = notes_root()?;
+    let models = load_settings()?.active_models();
+    let embed_model = ModelClient::for_embedding(&models)
+        .map_err(|e| e.to_string())?
+        .model_name()
+        .to_string();
+    Ok(IndexStaging::for_index(&index_path)
+        .status()
+        .filter(|s| s.notes_root == root.to_string_lossy() && s.embed_model == embed_model))
 }
 
 #[tauri::command]
 fn list_notes() -> Result<Vec<NoteListItem>, String> {
     let root = notes_root()?;
-    let notes = scan_notes(&root).map_err(|e| e.to_string())?;
+    let notes = scan_notes(&root)?.notes;
     let mut items: Vec<NoteListItem> = notes
         .into_iter()
         .map(|n| {
@@ -380,6 +629,23 @@ fn read_note(path: String) -> Result<NoteDetail, String> {
     if !abs.starts_with(&root) {
         return Err("Path is outside notes root".to_string());
     }
+    let formats = load_settings()?.indexing.formats();
+    if formats.extractor_for(&abs).is_some() {
+        // Documents are shown as their extracted text.
+        let note = formats
+            .read(&abs)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Not a note: {}", abs.display()))?;
+        let title = note
+            .frontmatter
+            .and_then(|fm| fm.title)
+            .unwrap_or_else(|| file_label(&abs));
+        return Ok(NoteDetail {
+            path: make_relative(&root, &abs),
+            title,
+            body: note.body,
+        });
+    }
     let raw =
         fs::read_to_string(&abs).map_err(|e| format!("Failed to read {}: {}", abs.display(), e))?;
     let (title, body) = split_note_content(&raw);
@@ -390,6 +656,62 @@ fn read_note(path: String) -> Result<NoteDetail, String> {
     })
 }
 
+/// File name of `path`, e.g. `insurance.pdf`.
+fn file_label(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// "page 4 of insurance.pdf" for chunks of documents.
+fn chunk_location(chunk: &Chunk) -> Option<String> {
+    chunk
+        .location
+        .as_ref()
+        .map(|l| l.describe(&file_label(&chunk.note_path)))
+}
+
+/// Link graph of the notes under the current root.
+fn link_graph() -> Result<(PathBuf, LinkGraph), String> {
+    let root = notes_root()?;
+    let notes = scan_notes(&root)?.notes;
+    let graph = LinkGraph::build(&root, &notes);
+    Ok((root, graph))
+}
+
+/// `path` (absolute, or relative to `root`) as a link graph key: relative, `/`-separated.
+fn graph_key(root: &Path, path: &str) -> String {
+    make_relative(root, Path::new(path)).replace('\\', "/")
+}
+
+/// Links from the note at `path` to other notes, in order of appearance.
+#[tauri::command]
+fn note_outlinks(path: String) -> Result<Vec<LinkEdge>, String> {
+    let (root, graph) = link_graph()?;
+    Ok(graph.outlinks(&graph_key(&root, &path)).into_iter().cloned().collect())
+}
+
+/// Links to the note at `path` from other notes.
+#[tauri::command]
+fn note_backlinks(path: String) -> Result<Vec<LinkEdge>, String> {
+    let (root, graph) = link_graph()?;
+    Ok(graph.backlinks(&graph_key(&root, &path)).into_iter().cloned().collect())
+}
+
+/// Notes with no links to or from other notes.
+#[tauri::command]
+fn orphan_notes() -> Result<Vec<String>, String> {
+    let (_, graph) = link_graph()?;
+    Ok(graph.orphans().into_iter().map(str::to_string).collect())
+}
+
+/// Links that name no note in the vault.
+#[tauri::command]
+fn unresolved_links() -> Result<Vec<UnresolvedLink>, String> {
+    let (_, graph) = link_graph()?;
+    Ok(graph.unresolved().to_vec())
+}
+
 #[tauri::command]
 fn save_note(path: String, title: String, body: String) -> Result<(), String> {
     let root = notes_root()?;
@@ -402,6 +724,12 @@ fn save_note(path: String, title: String, body: String) -> Result<(), String> {
     if !abs.starts_with(&root) {
         return Err("Path is outside notes root".to_string());
     }
+    if load_settings()?.indexing.formats().extractor_for(&abs).is_some() {
+        return Err(format!(
+            "{} is a document; only its extracted text is shown, it cannot be edited",
+            abs.display()
+        ));
+    }
     let content = join_note_content(&title, &body);
     fs::create_dir_all(
         abs.parent()
@@ -480,11 +808,11 @@ fn delete_note(path: String) -> Result<(), String> {
     }
 
     // Keep persisted semantic memory in sync with file deletes.
-    if let Some(index_path) = default_index_path() {
-        if let Ok(mut idx) = PersistedIndex::load_from_file(&index_path) {
+    if let Ok(Some(index_path)) = active_index_path() {
+        if let Ok(mut idx) = load_index(&index_path) {
             let rel = make_relative(&root, &abs);
-            idx.store.remove_note(&abs);
-            idx.store.remove_note(Path::new(&rel));
+            idx.remove_note(&abs);
+            idx.remove_note(Path::new(&rel));
             idx.note_states.remove(&abs.to_string_lossy().into_owned());
             idx.note_states.remove(&rel);
             idx.updated_at_unix =
@@ -520,11 +848,11 @@ fn move_note(source: String, dest: String) -> Result<String, String> {
     .map_err(|e| format!("Failed to create destination folder: {}", e))?;
     fs::rename(&src, &dst).map_err(|e| format!("Failed to move note: {}", e))?;
 
-    if let Some(index_path) = default_index_path() {
-        if let Ok(mut idx) = PersistedIndex::load_from_file(&index_path) {
+    if let Ok(Some(index_path)) = active_index_path() {
+        if let Ok(mut idx) = load_index(&index_path) {
             let old_rel = make_relative(&root, &src);
-            idx.store.remove_note(&src);
-            idx.store.remove_note(Path::new(&old_rel));
+            idx.remove_note(&src);
+            idx.remove_note(Path::new(&old_rel));
             idx.note_states.remove(&src.to_string_lossy().into_owned());
             idx.note_states.remove(&old_rel);
             idx.updated_at_unix = std::time::SystemTime::now()
@@ -541,7 +869,7 @@ fn move_note(source: String, dest: String) -> Result<String, String> {
 #[tauri::command]
 fn memory_overview(limit: Option<usize>) -> Result<MemoryOverview, String> {
     let root = notes_root()?;
-    let notes = scan_notes(&root).map_err(|e| e.to_string())?;
+    let notes = scan_notes(&root)?.notes;
     let mut overview = build_memory_overview(&notes, limit.unwrap_or(8));
     for card in &mut overview.cards {
         let p = Path::new(&card.note_path);
@@ -551,9 +879,89 @@ fn memory_overview(limit: Option<usize>) -> Result<MemoryOverview, String> {
 }
 
 #[tauri::command]
-async fn query(query: String, k: Option<usize>) -> Result<Vec<QueryResult>, String> {
-    let index_path = default_index_path().ok_or("Could not determine index path")?;
-    let idx = PersistedIndex::load_from_file(&index_path)
+async fn query(
+    query: String,
+    k: Option<usize>,
+    filter: Option<SearchFilter>,
+    mmr_lambda: Option<f32>,
+    vaults: Option<Vec<String>>,
+    expansion: Option<QueryExpansion>,
+) -> Result<Vec<QueryResult>, String> {
+    let cfg = load_settings()?;
+    let targets: Vec<Vault> = match vaults.filter(|names| !names.is_empty()) {
+        Some(names) => {
+            let all = cfg.vaults();
+            names
+                .iter()
+                .map(|name| {
+                    all.iter()
+                        .find(|v| v.name == *name)
+                        .cloned()
+                        .ok_or_else(|| format!("Unknown vault: {}", name))
+                })
+                .collect::<Result<_, _>>()?
+        }
+        None => vec![cfg
+            .active_vault()
+            .ok_or("No notes root configured in the desktop app.")?],
+    };
+    let k = k.or(cfg.active_models().default_k).unwrap_or(5);
+    let options = SearchOptions::new()
+        .with_filter(filter)
+        .with_hybrid(HybridParams::default())
+        .with_mmr(mmr_lambda.or(cfg.search.mmr_lambda));
+    let templates = prompt_templates()?;
+    let expansions = expansion_texts(&cfg, &templates, &query, expansion, None).await?;
+
+    // Search each vault on its own index and embedding model, then merge by rank. With
+    // several vaults, one that cannot be searched (e.g. not indexed yet) is skipped.
+    let mut per_vault: Vec<Vec<QueryResult>> = Vec::new();
+    let mut first_error = None;
+    for vault in &targets {
+        match query_vault(&cfg, &templates, vault, &query, &expansions, k, &options).await {
+            Ok(hits) => per_vault.push(hits),
+            Err(e) if targets.len() == 1 => return Err(e),
+            Err(e) => {
+                first_error.get_or_insert(format!("{}: {}", vault.name, e));
+            }
+        }
+    }
+    if per_vault.iter().all(Vec::is_empty) {
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+    }
+    Ok(merge_by_rank(per_vault, k))
+}
+
+/// Interleave ranked lists by position, keeping each list's order: first the best result of
+/// every list, then the second, and so on. Scores are not comparable across vaults (and are
+/// only cosine even within one, where fusion, MMR or reranking decided the order).
+fn merge_by_rank<T>(lists: Vec<Vec<T>>, k: usize) -> Vec<T> {
+    let mut lists: Vec<_> = lists.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Vec::new();
+    while merged.len() < k {
+        let before = merged.len();
+        merged.extend(lists.iter_mut().filter_map(Iterator::next));
+        if merged.len() == before {
+            break;
+        }
+    }
+    merged.truncate(k);
+    merged
+}
+
+async fn query_vault(
+    cfg: &Config,
+    templates: &PromptTemplates,
+    vault: &Vault,
+    query: &str,
+    expansions: &ExpansionTexts,
+    k: usize,
+    options: &SearchOptions,
+) -> Result<Vec<QueryResult>, String> {
+    let index_path = vault.index_path.as_deref().ok_or("Could not determine index path")?;
+    let mut idx = load_index(index_path)
         .map_err(|e| format!("Failed to load index: {}. Rebuild the index in the app.", e))?;
 
     if idx.schema_version != INDEX_SCHEMA_VERSION {
@@ -564,39 +972,44 @@ async fn query(query: String, k: Option<usize>) -> Result<Vec<QueryResult>, Stri
         return Err("Index is empty. Rebuild the index in the app.".to_string());
     }
 
-    let cfg = load_config();
-    let url = cfg.models.embed_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
-    let model = cfg
-        .models
-        .embed_model
-        .as_deref()
-        .unwrap_or(DEFAULT_EMBED_MODEL);
-    let k = k.or(cfg.models.default_k).unwrap_or(5);
+    if let Some(ef) = cfg.search.hnsw_ef_search {
+        idx.store.set_ef_search(ef);
+    }
 
-    let client = OllamaClient::from_url(url)
-        .map_err(|e| e.to_string())?
-        .with_embed_model(model);
+    let client = ModelClient::for_embedding(&vault.models).map_err(|e| e.to_string())?;
+    idx.settings
+        .check_embedder(client.model_name(), None, None)
+        .map_err(|e| e.to_string())?;
 
-    let q_emb = client.embed(&query).await.map_err(|e| e.to_string())?;
-    let raw = idx.store.search(&q_emb, k);
+    let queries = embed_expansions(&client, query, expansions)
+        .await
+        .map_err(|e| e.to_string())?;
+    idx.check_embedder(client.model_name(), queries[0].embedding.len())
+        .map_err(|e| e.to_string())?;
+    let fetch = if cfg.rerank.model.is_some() {
+        k.max(cfg.rerank.params().top_n)
+    } else {
+        k
+    };
+    let raw = idx.search_expanded(&queries, fetch, options);
     let index_notes_root = Path::new(&idx.settings.notes_root);
-    let current_root = notes_root().ok();
-    let preferred_root = current_root.as_deref().unwrap_or(index_notes_root);
+    let current_root = Some(vault.root.as_path()).filter(|r| r.is_dir());
+    let preferred_root = current_root.unwrap_or(index_notes_root);
 
     let filtered: Vec<_> = raw
         .iter()
         .cloned()
-        .filter(|(chunk, _)| {
-            chunk_note_exists(&chunk.note_path, index_notes_root, current_root.as_deref())
-        })
+        .filter(|(chunk, _)| chunk_note_exists(&chunk.note_path, index_notes_root, current_root))
         .collect();
     let chosen = if filtered.is_empty() { raw } else { filtered };
+    let (mut chosen, _) = rerank_if_configured(cfg, templates, query, chosen).await;
+    chosen.truncate(k);
 
     let results: Vec<QueryResult> = chosen
         .into_iter()
         .map(|(chunk, score)| {
             let display_path =
-                resolve_existing_note_path(&chunk.note_path, index_notes_root, current_root.as_deref())
+                resolve_existing_note_path(&chunk.note_path, index_notes_root, current_root)
                     .map(|p| make_relative(preferred_root, &p))
                     .unwrap_or_else(|| chunk.note_path.display().to_string());
             let preview: String = chunk.text.chars().take(120).collect();
@@ -606,9 +1019,12 @@ async fn query(query: String, k: Option<usize>) -> Result<Vec<QueryResult>, Stri
                 preview
             };
             QueryResult {
+                vault: vault.name.clone(),
                 note_path: display_path,
                 score,
                 preview: preview.trim().to_string(),
+                span: chunk.span,
+                location: chunk_location(&chunk),
                 text: chunk.text,
             }
         })
@@ -617,14 +1033,92 @@ async fn query(query: String, k: Option<usize>) -> Result<Vec<QueryResult>, Stri
     Ok(results)
 }
 
-#[tauri::command]
-async fn ask(
-    question: String,
+/// Which answer prompt [prepare_ask] builds, and from which templates.
+struct PromptChoice<'a> {
+    templates: &'a PromptTemplates,
+    /// Template chosen per call: an `ask` template, or a `chat_system` one with `history`.
+    template: Option<&'a str>,
+    /// Conversation so far; `None` for a single-turn question.
+    history: Option<&'a [ChatMessage]>,
+}
+
+/// Everything needed to answer a question except the generation itself.
+struct PreparedAsk {
+    chat_client: ModelClient,
+    chat_model: String,
+    /// Numbered excerpts of the sources, as embedded in `prompt`.
+    context: String,
+    /// The sources as template variables, for the `chat_turn` message.
+    template_sources: Vec<TemplateSource>,
+    /// The full prompt of a single-turn question; for chat, the system message.
+    prompt: String,
+    /// Sources in the prompt; excerpt `[n]` is `sources[n - 1]`.
+    sources: Vec<AskSource>,
+    dropped_sources: Vec<AskSource>,
+    /// Generation options with the context window the prompt was sized for.
+    options: GenerateOptions,
+    /// Full text of the excerpts in the prompt, in citation order.
+    excerpts: Vec<String>,
+    /// Client for the index's embedding model, for answer checking.
+    embed_client: ModelClient,
+    grounding: GroundingMethod,
+    /// Templates the prompt came from, for the `verify` prompt of answer checking.
+    templates: PromptTemplates,
+}
+
+impl PreparedAsk {
+    /// Citations and support of `answer`; `None` if checking is off or fails.
+    async fn check_answer(&self, answer: &str) -> Option<GroundedAnswer> {
+        if self.grounding == GroundingMethod::Off {
+            return None;
+        }
+        ground_answer(
+            answer,
+            &self.excerpts,
+            self.grounding,
+            &self.embed_client,
+            &self.chat_client,
+            &self.chat_model,
+            &self.templates,
+        )
+        .await
+        .ok()
+    }
+}
+
+/// Resolve chat URL and model with config overrides and optional per-call model.
+fn chat_client_and_model(
+    models: &ModelConfig,
+    model: Option<String>,
+) -> Result<(ModelClient, String), String> {
+    let chat_model = match model {
+        Some(m) if !m.trim().is_empty() => m,
+        _ => models
+            .chat_model
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CHAT_MODEL.to_string()),
+    };
+    let chat_client = ModelClient::for_chat(models).map_err(|e| e.to_string())?;
+    Ok((chat_client, chat_model))
+}
+
+/// Prompt templates of the app data dir and the active vault.
+fn prompt_templates() -> Result<PromptTemplates, String> {
+    PromptTemplates::load_default(notes_root().ok().as_deref()).map_err(|e| e.to_string())
+}
+
+/// Retrieve sources for `question` and build the answer prompt.
+async fn prepare_ask(
+    question: &str,
+    choice: PromptChoice<'_>,
     k: Option<usize>,
     model: Option<String>,
-) -> Result<AskResponse, String> {
-    let index_path = default_index_path().ok_or("Could not determine index path")?;
-    let idx = PersistedIndex::load_from_file(&index_path)
+    filter: Option<SearchFilter>,
+    mmr_lambda: Option<f32>,
+    expansion: Option<QueryExpansion>,
+) -> Result<PreparedAsk, String> {
+    let index_path = index_path()?;
+    let mut idx = load_index(&index_path)
         .map_err(|e| format!("Failed to load index: {}. Rebuild the index in the app.", e))?;
 
     if idx.schema_version != INDEX_SCHEMA_VERSION {
@@ -636,55 +1130,56 @@ async fn ask(
     }
 
     // Determine effective top-k, honoring optional config default.
-    let cfg = load_config();
+    let cfg = load_settings()?;
+    let models = cfg.active_models();
     let mut effective_k = k.unwrap_or(6);
     if effective_k == 6 {
-        if let Some(default_k) = cfg.models.default_k {
+        if let Some(default_k) = models.default_k {
             effective_k = default_k;
         }
     }
-
-    // Build a minimal metadata map from current notes on disk for titles.
-    use std::collections::{HashMap, HashSet};
-    #[derive(Clone)]
-    struct NoteMeta {
-        title: Option<String>,
-    }
-
-    let mut note_meta: HashMap<String, NoteMeta> = HashMap::new();
-    if let Ok(notes) = scan_notes(Path::new(&idx.settings.notes_root)) {
-        for n in notes {
-            let abs_key = n.path.display().to_string();
-            let rel_key = n
-                .path
-                .strip_prefix(Path::new(&idx.settings.notes_root))
-                .ok()
-                .map(|p| p.to_string_lossy().into_owned());
-            let title = derive_note_title(&n.raw, &abs_key);
-            let meta = NoteMeta {
-                title: Some(title),
-            };
-            note_meta.insert(abs_key, meta.clone());
-            if let Some(rel) = rel_key {
-                note_meta.insert(rel, meta);
-            }
-        }
+    if let Some(ef) = cfg.search.hnsw_ef_search {
+        idx.store.set_ef_search(ef);
     }
 
-    // Use the index's embedding settings for query embedding.
-    let embed_client = OllamaClient::from_url(&idx.settings.ollama_url)
-        .map_err(|e| e.to_string())?
-        .with_embed_model(idx.settings.embed_model.clone());
+    // Titles come from the index rather than rereading (and re-extracting) every note.
+    let note_titles = idx.note_titles();
 
-    let q_emb = embed_client
-        .embed(&question)
+    // Use the index's embedding settings for query embedding, but refuse once the config
+    // has moved to another model: the index no longer matches what rebuilds would produce.
+    let configured = ModelClient::for_embedding(&models).map_err(|e| e.to_string())?;
+    idx.settings
+        .check_embedder(configured.model_name(), None, None)
+        .map_err(|e| e.to_string())?;
+    let embed_client = ModelClient::for_index(&idx.settings, models.api_key.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    let (chat_client, chat_model) = chat_client_and_model(&models, model)?;
+    let expansions =
+        expansion_texts(&cfg, choice.templates, question, expansion, Some((&chat_client, &chat_model)))
+            .await?;
+    let queries = embed_expansions(&embed_client, question, &expansions)
         .await
         .map_err(|e| e.to_string())?;
+    idx.check_embedder(embed_client.model_name(), queries[0].embedding.len())
+        .map_err(|e| e.to_string())?;
 
     let index_notes_root = Path::new(&idx.settings.notes_root);
     let current_root = notes_root().ok();
     let preferred_root = current_root.as_deref().unwrap_or(index_notes_root);
-    let raw_search = idx.store.search(&q_emb, effective_k.saturating_mul(6));
+    let options = SearchOptions::new()
+        .with_filter(filter)
+        .with_hybrid(HybridParams::default())
+        .with_mmr(mmr_lambda.or(cfg.search.mmr_lambda));
+    let hits = idx.search_expanded_hits(&queries, effective_k.saturating_mul(6), &options);
+    let relevance: HashMap<(PathBuf, usize), f32> = hits
+        .iter()
+        .map(|hit| ((hit.chunk.note_path.clone(), hit.chunk.index), hit.relevance))
+        .collect();
+    let raw_search: Vec<_> = hits
+        .into_iter()
+        .map(|SearchHit { chunk, score, .. }| (chunk, score))
+        .collect();
     let filtered: Vec<_> = raw_search
         .iter()
         .cloned()
@@ -697,6 +1192,7 @@ async fn ask(
     } else {
         filtered
     };
+    let (ranked, reranked) = rerank_if_configured(&cfg, choice.templates, question, ranked).await;
     let (body_results, title_results): (Vec<_>, Vec<_>) = ranked
         .into_iter()
         .partition(|(chunk, _)| chunk.kind == ChunkKind::Body);
@@ -713,111 +1209,502 @@ async fn ask(
             }
         }
     }
-    let raw_results = filter_ask_results_by_score(raw_results);
+    let raw_results = if reranked {
+        raw_results
+    } else {
+        filter_ask_results_by_score(raw_results, |chunk| {
+            relevance
+                .get(&(chunk.note_path.clone(), chunk.index))
+                .copied()
+                .unwrap_or(0.0)
+        })
+    };
     if raw_results.is_empty() {
         return Err("No results.".to_string());
     }
 
-    // Build context from top chunks.
-    let mut context = String::new();
-    use std::fmt::Write as FmtWrite;
-    for (i, (chunk, _score)) in raw_results.iter().enumerate() {
-        let _ = FmtWrite::write_fmt(
-            &mut context,
-            format_args!(
-                "[{}][{}]\n{}\n\n",
-                i + 1,
-                match chunk.kind {
-                    ChunkKind::Title => "title",
-                    ChunkKind::Body => "body",
-                },
-                chunk.text
-            ),
-        );
+    // Fit as many top chunks as the chat model's context window allows.
+    let context_length = chat_context_length(&chat_client, &chat_model, &models).await;
+    let templates = choice.templates;
+    let system = match choice.history {
+        Some(_) => Some(
+            templates
+                .render_chat_system(choice.template)
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+    let fixed = match (choice.history, &system) {
+        (Some(history), Some(system)) => chat_messages(templates, system, history, "", question, &[])
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => templates
+            .render_ask(choice.template, "", question, &[])
+            .map_err(|e| e.to_string())?,
+    };
+    let fit = PromptBudget::new(context_length).fit_sources(&fixed, &raw_results);
+    let context = fit.context;
+    let options = GenerateOptions {
+        context_length: Some(context_length as u64),
+        ..Default::default()
+    };
+
+    // Prepare sources payload for the frontend (clickable references).
+    let to_source = |i: usize| {
+        let (chunk, score) = raw_results[i].clone();
+        let resolved =
+            resolve_existing_note_path(&chunk.note_path, index_notes_root, current_root.as_deref());
+        let note_key = resolved
+            .as_ref()
+            .map(|p| make_relative(preferred_root, p))
+            .unwrap_or_else(|| chunk.note_path.display().to_string());
+        let title = note_titles.get(&chunk.note_path).cloned();
+        AskSource {
+            note_path: note_key,
+            title,
+            excerpt: snippet_preview(&chunk.text, 160),
+            kind: match chunk.kind {
+                ChunkKind::Title => "title".to_string(),
+                ChunkKind::Body => "body".to_string(),
+            },
+            chunk_index: chunk.index,
+            score,
+            span: chunk.span,
+            location: chunk_location(&chunk),
+        }
+    };
+    let sources: Vec<AskSource> = fit.kept.iter().map(|&i| to_source(i)).collect();
+    let dropped_sources = fit.dropped.iter().map(|&i| to_source(i)).collect();
+    let excerpts = fit.kept.iter().map(|&i| raw_results[i].0.text.clone()).collect();
+
+    let template_sources: Vec<TemplateSource> = fit
+        .kept
+        .iter()
+        .zip(&sources)
+        .enumerate()
+        .map(|(n, (&i, source))| {
+            let mut t = TemplateSource::from_chunk(n + 1, &raw_results[i].0);
+            t.path = source.note_path.clone();
+            match &source.title {
+                Some(title) => t.with_title(title.clone()),
+                None => t,
+            }
+        })
+        .collect();
+    let prompt = match system {
+        Some(system) => system,
+        None => templates
+            .render_ask(choice.template, &context, question, &template_sources)
+            .map_err(|e| e.to_string())?,
+    };
+
+    Ok(PreparedAsk {
+        chat_client,
+        chat_model,
+        context,
+        template_sources,
+        prompt,
+        sources,
+        dropped_sources,
+        options,
+        excerpts,
+        embed_client,
+        grounding: cfg.answers.grounding.unwrap_or_default(),
+        templates: templates.clone(),
+    })
+}
+
+/// HyDE passage and paraphrases of `question` as chosen per call, else by the `[search]`
+/// config. Generated with `chat` if given, else with the configured chat model.
+async fn expansion_texts(
+    cfg: &Config,
+    templates: &PromptTemplates,
+    question: &str,
+    expansion: Option<QueryExpansion>,
+    chat: Option<(&ModelClient, &str)>,
+) -> Result<ExpansionTexts, String> {
+    let expansion = expansion.unwrap_or_else(|| cfg.search.query_expansion());
+    if !expansion.is_enabled() {
+        return Ok(ExpansionTexts::default());
     }
+    let texts = match chat {
+        Some((client, model)) => {
+            generate_expansions(client, model, templates, question, &expansion).await
+        }
+        None => {
+            let (client, model) = chat_client_and_model(&cfg.active_models(), None)?;
+            generate_expansions(&client, &model, templates, question, &expansion).await
+        }
+    };
+    Ok(texts)
+}
 
-    // Resolve chat URL and model with config overrides and optional per-call model.
-    let chat_url = cfg
-        .models
-        .chat_url
-        .as_deref()
-        .unwrap_or(DEFAULT_BASE_URL)
-        .to_string();
-    let chat_model = match model {
-        Some(m) if !m.trim().is_empty() => m,
-        _ => cfg
-            .models
-            .chat_model
-            .clone()
-            .unwrap_or_else(|| DEFAULT_CHAT_MODEL.to_string()),
+/// Context window to size prompts for: configured, else reported by Ollama for `model`.
+async fn chat_context_length(client: &ModelClient, model: &str, models: &ModelConfig) -> usize {
+    let reported = match client {
+        ModelClient::Ollama(c) if models.context_length.is_none() => {
+            c.show_model(model).await.ok().and_then(|d| d.context_length)
+        }
+        _ => None,
+    };
+    context_length_for(models.context_length, reported)
+}
+
+#[tauri::command]
+async fn ask(
+    question: String,
+    k: Option<usize>,
+    model: Option<String>,
+    filter: Option<SearchFilter>,
+    mmr_lambda: Option<f32>,
+    expansion: Option<QueryExpansion>,
+    template: Option<String>,
+) -> Result<AskResponse, String> {
+    let templates = prompt_templates()?;
+    let choice = PromptChoice {
+        templates: &templates,
+        template: template.as_deref(),
+        history: None,
     };
+    let prepared = prepare_ask(&question, choice, k, model, filter, mmr_lambda, expansion).await?;
+    let answer = prepared
+        .chat_client
+        .generate(&prepared.chat_model, &prepared.prompt, &prepared.options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let answer = answer.trim().to_string();
+    let grounding = prepared.check_answer(&answer).await;
 
-    let chat_client = OllamaClient::from_url(&chat_url).map_err(|e| e.to_string())?;
+    Ok(AskResponse {
+        question,
+        answer,
+        sources: prepared.sources,
+        dropped_sources: prepared.dropped_sources,
+        grounding,
+    })
+}
 
-    let prompt = format!(
-        "You are Noema, a local-first knowledge assistant.\nUse ONLY the following numbered excerpts as context.\nEach excerpt is tagged as [title] or [body]. Treat title excerpts as metadata and prefer body excerpts for factual grounding.\nIf context is insufficient, say you don't know.\nCite supporting excerpts inline as [1], [2], etc.\nDo not mention note file names or paths unless the user explicitly asks for them.\n\nContext:\n{}\nQuestion:\n{}\n\nAnswer in a concise paragraph or two with citation markers:\n",
-        context, question
-    );
+/// Event emitted by [ask_stream]; all events of one call share its `request_id`.
+const ASK_STREAM_EVENT: &str = "ask-stream";
 
-    let answer = chat_client
-        .generate(&chat_model, &prompt)
+#[derive(Serialize, Clone)]
+struct AskStreamEvent {
+    request_id: String,
+    #[serde(flatten)]
+    kind: AskStreamEventKind,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AskStreamEventKind {
+    /// Retrieved sources, sent before any answer text.
+    Sources {
+        sources: Vec<AskSource>,
+        dropped_sources: Vec<AskSource>,
+    },
+    /// Next fragment of the answer.
+    Token { text: String },
+    /// Citation check of the finished answer, sent before `Done` unless checking is off.
+    Grounding { grounding: GroundedAnswer },
+    /// Generation finished, failed or was cancelled.
+    Done {
+        cancelled: bool,
+        error: Option<String>,
+    },
+}
+
+/// Abort handles of in-flight [ask_stream] calls, by request id.
+#[derive(Default)]
+struct AskStreams(Mutex<HashMap<String, AbortHandle>>);
+
+/// Like [ask], but emits [ASK_STREAM_EVENT] events as the answer is generated: first the
+/// sources, then answer tokens, then `done`. Resolves with the full (or, if cancelled,
+/// partial) answer. [cancel_ask] with the same `request_id` stops generation.
+#[tauri::command]
+async fn ask_stream(
+    app: AppHandle,
+    streams: State<'_, AskStreams>,
+    request_id: String,
+    question: String,
+    k: Option<usize>,
+    model: Option<String>,
+    filter: Option<SearchFilter>,
+    mmr_lambda: Option<f32>,
+    expansion: Option<QueryExpansion>,
+    template: Option<String>,
+) -> Result<AskResponse, String> {
+    let emit = |kind: AskStreamEventKind| {
+        let _ = app.emit(
+            ASK_STREAM_EVENT,
+            AskStreamEvent {
+                request_id: request_id.clone(),
+                kind,
+            },
+        );
+    };
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    if let Ok(mut streams) = streams.0.lock() {
+        streams.insert(request_id.clone(), abort_handle);
+    }
+
+    // Filled in as the request progresses, so a cancelled request still reports them.
+    let mut answer = String::new();
+    let mut chosen: Option<(Vec<AskSource>, Vec<AskSource>)> = None;
+    let run = async {
+        let templates = prompt_templates()?;
+        let choice = PromptChoice {
+            templates: &templates,
+            template: template.as_deref(),
+            history: None,
+        };
+        let prepared =
+            prepare_ask(&question, choice, k, model, filter, mmr_lambda, expansion).await?;
+        emit(AskStreamEventKind::Sources {
+            sources: prepared.sources.clone(),
+            dropped_sources: prepared.dropped_sources.clone(),
+        });
+        chosen = Some((prepared.sources.clone(), prepared.dropped_sources.clone()));
+        stream_completion(
+            &prepared.chat_client,
+            &prepared.chat_model,
+            &prepared.prompt,
+            &prepared.options,
+            |token| {
+                answer.push_str(token);
+                emit(AskStreamEventKind::Token {
+                    text: token.to_string(),
+                });
+            },
+        )
         .await
         .map_err(|e| e.to_string())?;
+        let grounding = prepared.check_answer(answer.trim()).await;
+        if let Some(grounding) = &grounding {
+            emit(AskStreamEventKind::Grounding {
+                grounding: grounding.clone(),
+            });
+        }
+        Ok::<_, String>((prepared.sources, prepared.dropped_sources, grounding))
+    };
+    let outcome = Abortable::new(run, abort_registration).await;
+    if let Ok(mut streams) = streams.0.lock() {
+        streams.remove(&request_id);
+    }
 
-    // Prepare sources payload for the frontend (clickable references).
-    let sources = raw_results
-        .into_iter()
-        .map(|(chunk, score)| {
-            let resolved =
-                resolve_existing_note_path(&chunk.note_path, index_notes_root, current_root.as_deref());
-            let note_key = resolved
-                .as_ref()
-                .map(|p| make_relative(preferred_root, p))
-                .unwrap_or_else(|| chunk.note_path.display().to_string());
-            let title = note_meta
-                .get(&note_key)
-                .or_else(|| note_meta.get(&chunk.note_path.display().to_string()))
-                .and_then(|m| m.title.clone());
-            AskSource {
-                note_path: note_key,
-                title,
-                excerpt: snippet_preview(&chunk.text, 160),
-                kind: match chunk.kind {
-                    ChunkKind::Title => "title".to_string(),
-                    ChunkKind::Body => "body".to_string(),
-                },
-                chunk_index: chunk.index,
-                score,
-            }
-        })
-        .collect();
+    match outcome {
+        Ok(Ok((sources, dropped_sources, grounding))) => {
+            emit(AskStreamEventKind::Done {
+                cancelled: false,
+                error: None,
+            });
+            Ok(AskResponse {
+                question,
+                answer: answer.trim().to_string(),
+                sources,
+                dropped_sources,
+                grounding,
+            })
+        }
+        Ok(Err(e)) => {
+            emit(AskStreamEventKind::Done {
+                cancelled: false,
+                error: Some(e.clone()),
+            });
+            Err(e)
+        }
+        Err(Aborted) => {
+            emit(AskStreamEventKind::Done {
+                cancelled: true,
+                error: None,
+            });
+            // The sources event may already have gone out; answer with the same sources.
+            let (sources, dropped_sources) = chosen.unwrap_or_default();
+            Ok(AskResponse {
+                question,
+                answer: answer.trim().to_string(),
+                sources,
+                dropped_sources,
+                grounding: None,
+            })
+        }
+    }
+}
 
-    Ok(AskResponse {
+#[derive(Serialize)]
+pub struct ChatResponse {
+    pub session_id: String,
+    pub question: String,
+    /// The question rewritten to stand on its own, as used for retrieval.
+    pub standalone_question: String,
+    pub answer: String,
+    pub sources: Vec<AskSource>,
+    /// Retrieved sources left out of the prompt to fit the model's context window.
+    pub dropped_sources: Vec<AskSource>,
+    /// Per-sentence citations and support, unless answer checking is off.
+    pub grounding: Option<GroundedAnswer>,
+}
+
+fn session_store() -> Result<SessionStore, String> {
+    SessionStore::open_default().ok_or_else(|| "Could not determine sessions directory".to_string())
+}
+
+/// Answer `question` as the next turn of a chat session, starting a new session if
+/// `session_id` is not given. Follow-ups are rewritten into standalone questions before
+/// retrieval; the answer sees the session history. The turn is saved to the session.
+#[tauri::command]
+async fn chat(
+    session_id: Option<String>,
+    question: String,
+    k: Option<usize>,
+    model: Option<String>,
+    filter: Option<SearchFilter>,
+    mmr_lambda: Option<f32>,
+    expansion: Option<QueryExpansion>,
+    template: Option<String>,
+) -> Result<ChatResponse, String> {
+    let store = session_store()?;
+    let mut session = match session_id {
+        Some(id) => store.load(&id).map_err(|e| e.to_string())?,
+        None => ChatSession::new(),
+    };
+
+    let models = load_settings()?.active_models();
+    let templates = prompt_templates()?;
+    let (chat_client, chat_model) = chat_client_and_model(&models, model.clone())?;
+    let standalone =
+        condense_question(&chat_client, &chat_model, &templates, session.history(), &question)
+            .await;
+
+    let choice = PromptChoice {
+        templates: &templates,
+        template: template.as_deref(),
+        history: Some(session.history()),
+    };
+    let prepared = prepare_ask(
+        &standalone,
+        choice,
+        k,
+        model,
+        filter,
+        mmr_lambda,
+        expansion,
+    )
+    .await?;
+    let messages = chat_messages(
+        &prepared.templates,
+        &prepared.prompt,
+        session.history(),
+        &prepared.context,
+        &question,
+        &prepared.template_sources,
+    )
+    .map_err(|e| e.to_string())?;
+    let answer = prepared
+        .chat_client
+        .chat(&prepared.chat_model, &messages, &prepared.options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let answer = answer.trim().to_string();
+    let grounding = prepared.check_answer(&answer).await;
+
+    let mut user_message = ChatMessage::user(question.clone());
+    if standalone.trim() != question.trim() {
+        user_message.standalone = Some(standalone.clone());
+    }
+    let mut assistant_message = ChatMessage::assistant(answer.clone());
+    assistant_message.sources = prepared.sources.iter().map(|s| s.note_path.clone()).collect();
+    session.push(user_message);
+    session.push(assistant_message);
+    store.save(&session).map_err(|e| e.to_string())?;
+
+    Ok(ChatResponse {
+        session_id: session.id,
         question,
-        answer: answer.trim().to_string(),
-        sources,
+        standalone_question: standalone,
+        answer,
+        sources: prepared.sources,
+        dropped_sources: prepared.dropped_sources,
+        grounding,
     })
 }
 
+/// Names of the available prompt templates, built-in and from the app data dir or vault.
+#[tauri::command]
+fn list_prompt_templates() -> Result<Vec<String>, String> {
+    Ok(prompt_templates()?.names())
+}
+
+/// Chat sessions, most recent first.
+#[tauri::command]
+fn list_chat_sessions() -> Result<Vec<ChatSessionSummary>, String> {
+    session_store()?.list().map_err(|e| e.to_string())
+}
+
+/// Full history of a chat session, to continue it with [chat].
+#[tauri::command]
+fn resume_chat_session(session_id: String) -> Result<ChatSession, String> {
+    session_store()?.load(&session_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_chat_session(session_id: String) -> Result<(), String> {
+    session_store()?.delete(&session_id).map_err(|e| e.to_string())
+}
+
+/// Stop a running [ask_stream] call. Returns false if no call with that id is running.
+#[tauri::command]
+fn cancel_ask(streams: State<'_, AskStreams>, request_id: String) -> bool {
+    let handle = streams.0.lock().ok().and_then(|mut s| s.remove(&request_id));
+    match handle {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
+        .manage(AskStreams::default())
         .invoke_handler(tauri::generate_handler![
             get_notes_root,
             set_notes_root,
+            list_vaults,
+            add_vault,
+            remove_vault,
+            switch_vault,
             status,
             rebuild_index,
+            index_checkpoint,
+            check_embed_model,
             list_notes,
             read_note,
             save_note,
             create_note,
             delete_note,
             move_note,
+            note_outlinks,
+            note_backlinks,
+            orphan_notes,
+            unresolved_links,
             memory_overview,
             query,
             ask,
-            list_chat_models
+            ask_stream,
+            cancel_ask,
+            chat,
+            list_prompt_templates,
+            list_chat_sessions,
+            resume_chat_session,
+            delete_chat_session,
+            list_chat_models,
+            list_models
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -866,6 +1753,34 @@ mod tests {
         assert_eq!(body, "Body copy.");
     }
 
+    #[test]
+    fn query_keeps_each_vault_ranking() {
+        let result = |vault: &str, note_path: &str, score: f32| QueryResult {
+            vault: vault.to_string(),
+            note_path: note_path.to_string(),
+            score,
+            preview: String::new(),
+            text: String::new(),
+            span: None,
+            location: None,
+        };
+        // An exact-term (BM25-only) hit ranked first by fusion despite a low cosine.
+        let work = || {
+            vec![
+                result("work", "invoice-4711.md", 0.08),
+                result("work", "billing.md", 0.7),
+            ]
+        };
+        let home = vec![result("home", "garden.md", 0.9), result("home", "bills.md", 0.6)];
+
+        let single = merge_by_rank(vec![work()], 1);
+        assert_eq!(single[0].note_path, "invoice-4711.md");
+
+        let merged = merge_by_rank(vec![work(), home], 3);
+        let paths: Vec<_> = merged.iter().map(|r| r.note_path.as_str()).collect();
+        assert_eq!(paths, ["invoice-4711.md", "garden.md", "billing.md"]);
+    }
+
     #[test]
     fn filter_ask_results_drops_low_scoring_sources() {
         let base_chunk = Chunk {
@@ -873,13 +1788,26 @@ mod tests {
             kind: ChunkKind::Body,
             note_path: PathBuf::from("note.md"),
             index: 0,
+            meta: Default::default(),
+            span: None,
+            location: None,
         };
-        let filtered = filter_ask_results_by_score(vec![
-            (base_chunk.clone(), 0.9),
-            (base_chunk.clone(), 0.76),
-            (base_chunk, 0.41),
-        ]);
+        let by_score = |chunk: &Chunk| [0.9, 0.76, 0.41][chunk.index];
+        let results: Vec<_> = (0..3)
+            .map(|index| {
+                let chunk = Chunk { index, ..base_chunk.clone() };
+                let score = by_score(&chunk);
+                (chunk, score)
+            })
+            .collect();
+        let filtered = filter_ask_results_by_score(results.clone(), by_score);
         assert_eq!(filtered.len(), 2);
         assert!(filtered.iter().all(|(_, score)| *score >= 0.72));
+
+        // A hybrid exact-term hit ranks high on fused relevance despite a low cosine.
+        let fused = |chunk: &Chunk| [1.0, 0.5, 0.95][chunk.index];
+        let filtered = filter_ask_results_by_score(results, fused);
+        let kept: Vec<_> = filtered.iter().map(|(chunk, _)| chunk.index).collect();
+        assert_eq!(kept, [0, 2]);
     }
 }
@@ -3,6 +3,7 @@
 // floating utility bar (search + ask) at bottom center.
 
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./style.css";
 
 // ─── State ──────────────────────────────────────────
@@ -30,6 +31,8 @@ let searchError = "";
 let isSearching = false;
 let askResponse = null;
 let isAsking = false;
+let askRequestId = null;
+let askRequestCounter = 0;
 let utilityMode = "idle";
 let utilityFocused = false;
 let utilityHideTimer = null;
@@ -37,6 +40,7 @@ let utilityModelExitTimer = null;
 let utilityPanelSignature = "";
 
 let chatModels = [];
+let indexCheckpoint = null;
 let selectedModel = null;
 
 let createPromise = null;
@@ -98,6 +102,7 @@ async function init() {
 
   await refreshNotes();
   await loadModels();
+  await loadIndexCheckpoint();
   renderApp();
 }
 
@@ -109,6 +114,14 @@ async function refreshNotes() {
   }
 }
 
+async function loadIndexCheckpoint() {
+  try {
+    indexCheckpoint = await invoke("index_checkpoint");
+  } catch {
+    indexCheckpoint = null;
+  }
+}
+
 async function loadModels() {
   try {
     chatModels = await invoke("list_chat_models");
@@ -245,11 +258,12 @@ function renderApp() {
       h(
         "button",
         {
+          id: "rebuild-index-btn",
           className:
             "text-sm text-stone-400 hover:text-stone-600 focus:outline-none focus-visible:ring-1 focus-visible:ring-stone-400 rounded",
           onClick: rebuildIndex,
         },
-        "rebuild index",
+        rebuildIndexLabel(),
       ),
     ),
   );
@@ -821,12 +835,24 @@ function sourceTitle(source) {
   return source.title?.trim() || cleanNoteLabel(source.note_path);
 }
 
+function sourceLinesLabel(source) {
+  const span = source.span;
+  if (!span) return "";
+  return span.start_line === span.end_line
+    ? ` · line ${span.start_line}`
+    : ` · lines ${span.start_line}–${span.end_line}`;
+}
+
 function sourceMetaLabel(source) {
   const pathLabel = cleanNoteLabel(source.note_path);
+  const kindLabel = source.kind === "title" ? "title excerpt" : "body excerpt";
+  if (source.location) {
+    return `${source.location} · ${kindLabel}`;
+  }
   if (!pathLabel || pathLabel === sourceTitle(source)) {
-    return source.kind === "title" ? "title excerpt" : "body excerpt";
+    return `${kindLabel}${sourceLinesLabel(source)}`;
   }
-  return `${pathLabel} · ${source.kind === "title" ? "title excerpt" : "body excerpt"}`;
+  return `${pathLabel} · ${kindLabel}${sourceLinesLabel(source)}`;
 }
 
 function renderAskSource(source, index) {
@@ -865,7 +891,7 @@ function renderAskSource(source, index) {
 }
 
 function renderAskPanel() {
-  if (isAsking) return resultCard(h("p", { className: "text-sm text-stone-400" }, "thinking\u2026"));
+  if (isAsking && !askResponse?.answer) return resultCard(h("p", { className: "text-sm text-stone-400" }, "thinking\u2026"));
 
   if (askResponse?.error) {
     const showRebuild =
@@ -886,6 +912,10 @@ function renderAskPanel() {
           "text-sm text-stone-700 leading-relaxed text-pretty whitespace-pre-wrap",
       }, askResponse.answer),
     ];
+    const groundingNote = describeGrounding(askResponse.grounding);
+    if (groundingNote) {
+      items.push(h("p", { className: "mt-2 text-xs text-stone-400" }, groundingNote));
+    }
     if (askResponse.sources?.length > 0) {
       items.push(
         h("div", { className: "mt-4 border-t border-stone-100 pt-4" },
@@ -896,6 +926,14 @@ function renderAskPanel() {
         ),
       );
     }
+    const dropped = askResponse.dropped_sources?.length ?? 0;
+    if (dropped > 0) {
+      items.push(
+        h("p", { className: "mt-3 text-xs text-stone-400" },
+          `${dropped} more ${dropped === 1 ? "source" : "sources"} left out to fit the model\u2019s context window`,
+        ),
+      );
+    }
     return h("div", {
       className:
         "border border-stone-200 rounded-xl bg-white shadow-sm px-5 py-4 max-h-[28rem] overflow-y-auto",
@@ -907,6 +945,20 @@ function renderAskPanel() {
   );
 }
 
+// One line on how well the answer's sentences are backed by the sources they cite.
+function describeGrounding(g) {
+  if (!g || g.sentences.length === 0) return "";
+  const parts = [`grounded ${Math.round(g.groundedness * 100)}%`];
+  if (g.uncited_sentences > 0) {
+    parts.push(`${g.uncited_sentences} uncited ${g.uncited_sentences === 1 ? "sentence" : "sentences"}`);
+  }
+  if (g.invalid_citations.length > 0) {
+    const list = g.invalid_citations.map((n) => `[${n}]`).join(" ");
+    parts.push(`${list} not in sources`);
+  }
+  return parts.join(" \u00b7 ");
+}
+
 function renderSearchPanel() {
   if (isSearching) return resultCard(h("p", { className: "text-sm text-stone-400" }, "searching\u2026"));
 
@@ -1048,7 +1100,7 @@ function updateUtilityPanel() {
   if (utilityMode === "ask") {
     showUtilityResults(
       renderAskPanel(),
-      `ask:${isAsking ? "loading" : askResponse?.error ? `error:${askResponse.error}` : askResponse?.answer ? `answer:${askResponse.answer}` : "idle"}`,
+      `ask:${isAsking && !askResponse?.answer ? "loading" : askResponse?.error ? `error:${askResponse.error}` : askResponse?.answer ? `answer:${askResponse.answer}` : "idle"}`,
       false,
     );
     return;
@@ -1098,6 +1150,7 @@ function clearUtilityResults() {
   searchResults = [];
   searchError = "";
   askResponse = null;
+  cancelAsk();
   utilityMode = "idle";
   isSearching = false;
   isAsking = false;
@@ -1301,26 +1354,102 @@ async function doSearch(query) {
   updateUtilityPanel();
 }
 
+// Streams the answer: sources arrive first, then answer tokens as "ask-stream" events.
 async function doAsk(question) {
+  cancelAsk();
+  const requestId = `ask-${++askRequestCounter}`;
+  askRequestId = requestId;
   utilityMode = "ask";
   isAsking = true;
   askResponse = null;
   updateUtilityPanel();
+
+  const unlisten = await listen("ask-stream", (event) => {
+    const payload = event.payload;
+    if (payload.request_id !== requestId || askRequestId !== requestId) return;
+    if (payload.type === "sources") {
+      askResponse = {
+        answer: "",
+        sources: payload.sources,
+        dropped_sources: payload.dropped_sources,
+        error: "",
+      };
+    } else if (payload.type === "token" && askResponse) {
+      askResponse.answer += payload.text;
+    } else if (payload.type === "grounding" && askResponse) {
+      askResponse.grounding = payload.grounding;
+    }
+    updateUtilityPanel();
+  });
   try {
-    const res = await invoke("ask", { question, model: selectedModel });
-    askResponse = { answer: res.answer, sources: res.sources, error: "" };
+    const res = await invoke("ask_stream", { requestId, question, model: selectedModel });
+    if (askRequestId === requestId) {
+      const sources = res.sources.length > 0 ? res.sources : askResponse?.sources ?? [];
+      const dropped_sources = res.sources.length > 0 ? res.dropped_sources : askResponse?.dropped_sources ?? [];
+      const grounding = res.grounding ?? askResponse?.grounding ?? null;
+      askResponse = { answer: res.answer, sources, dropped_sources, grounding, error: "" };
+    }
   } catch (e) {
-    askResponse = { answer: "", sources: [], error: String(e) };
+    if (askRequestId === requestId) askResponse = { answer: "", sources: [], error: String(e) };
+  } finally {
+    unlisten();
   }
+  if (askRequestId === requestId) {
+    askRequestId = null;
+    isAsking = false;
+    updateUtilityPanel();
+  }
+}
+
+function cancelAsk() {
+  if (!askRequestId) return;
+  invoke("cancel_ask", { requestId: askRequestId }).catch(() => {});
+  askRequestId = null;
   isAsking = false;
-  updateUtilityPanel();
+}
+
+function formatEta(secs) {
+  if (secs == null || !isFinite(secs)) return "";
+  if (secs < 60) return ` \u00b7 ~${Math.max(1, Math.round(secs))}s left`;
+  return ` \u00b7 ~${Math.round(secs / 60)}m left`;
+}
+
+function rebuildIndexLabel() {
+  const c = indexCheckpoint;
+  if (!c || c.chunks_total === 0) return "rebuild index";
+  const n = (x) => x.toLocaleString("en-US");
+  return `resume indexing (${n(c.chunks_done)} / ${n(c.chunks_total)} chunks)`;
 }
 
 async function rebuildIndex() {
+  const button = document.getElementById("rebuild-index-btn");
+  if (button?.disabled) return;
+  if (button) {
+    button.disabled = true;
+    button.textContent = "indexing\u2026";
+  }
+  const unlisten = await listen("index-progress", (event) => {
+    const p = event.payload;
+    if (button && p.chunks_total > 0) {
+      const verb = p.resumed_chunks > 0 ? "resuming" : "indexing";
+      button.textContent = `${verb} ${p.chunks_done}/${p.chunks_total}${formatEta(p.eta_secs)}`;
+    }
+  });
   try {
-    await invoke("rebuild_index");
+    const report = await invoke("rebuild_index");
+    if (report.skipped.length > 0) {
+      const files = report.skipped.map((f) => `${f.path} (${f.reason})`).join(", ");
+      showError(`Indexed without ${report.skipped.length} unreadable file(s): ${files}`);
+    }
   } catch (e) {
     showError("Rebuild failed: " + e);
+  } finally {
+    unlisten();
+    await loadIndexCheckpoint();
+    if (button) {
+      button.disabled = false;
+      button.textContent = rebuildIndexLabel();
+    }
   }
 }
 